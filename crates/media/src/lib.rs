@@ -34,9 +34,100 @@ pub enum MediaError {
     #[error("Cannot run any further operations on a pipeline that has been shut down")]
     ShutdownPipeline,
 
+    #[error("A Tokio runtime is required to build a pipeline")]
+    NoRuntime,
+
     #[error("Failed to launch task: {0}")]
     TaskLaunch(String),
 
+    #[error("Pipeline build timed out waiting for tasks: {}", .0.join(", "))]
+    BuildTimeout(Vec<String>),
+
+    #[error("Pipeline produced no items for {0:?}")]
+    IdleTimeout(std::time::Duration),
+
+    #[error("Edge '{0}' stopped draining during shutdown")]
+    DrainStalled(String),
+
+    #[error("Task '{task}' was not launched as its dependency '{dependency}' never became ready: {detail}")]
+    DependencyNotReady {
+        task: String,
+        dependency: String,
+        detail: String,
+    },
+
+    #[error("Task '{task}' exited before signalling ready: {detail}")]
+    TaskExitedBeforeReady { task: String, detail: String },
+
+    #[error("Task '{task}' requires the '{capability}' capability, which is not available")]
+    MissingCapability { task: String, capability: String },
+
+    #[error("Source '{0}' stopped without finishing, its output may be incomplete")]
+    SourceCrashed(String),
+
+    #[error("None of the candidates for task '{task}' started: {}", .errors.join("; "))]
+    NoCandidateStarted { task: String, errors: Vec<String> },
+
+    #[error("A task named '{0}' has already been added to the pipeline")]
+    DuplicateTask(String),
+
+    #[error("A control listener named '{0}' has already been added to the pipeline")]
+    DuplicateControlListener(String),
+
+    #[error(
+        "Task '{task}' depends on '{dependency}', which was not added to the pipeline before it"
+    )]
+    UnknownDependency { task: String, dependency: String },
+
+    #[error("Task '{task}' depends on '{dependency}', which has a low start priority")]
+    LowPriorityDependency { task: String, dependency: String },
+
+    #[error("Task '{0}' has a queue size of zero")]
+    ZeroQueueSize(String),
+
+    #[error("A path endpoint named '{0}' has already been sealed")]
+    DuplicateEndpoint(String),
+
+    #[error("No path endpoint named '{0}' has been sealed")]
+    UnknownEndpoint(String),
+
+    #[error("Path endpoint '{endpoint}' carries {sealed}, not {requested}")]
+    EndpointTypeMismatch {
+        endpoint: String,
+        sealed: &'static str,
+        requested: &'static str,
+    },
+
+    #[error("Estimated worst-case latency of {estimate:?} exceeds the budget of {budget:?}")]
+    LatencyBudgetExceeded {
+        estimate: std::time::Duration,
+        budget: std::time::Duration,
+    },
+
+    #[error("No edge named '{0}' carries items of the requested type")]
+    UnknownEdge(String),
+
+    #[error("Edge '{0}' can't be drained while its producer is still running")]
+    EdgeStillLive(String),
+
+    #[error("Tasks did not acknowledge the control message in time: {}", .0.join(", "))]
+    ControlAckTimeout(Vec<String>),
+
+    #[error("No task named '{0}' is listening for control messages")]
+    UnknownTask(String),
+
+    #[error("Task '{0}' isn't keeping up with its control messages")]
+    ControlQueueFull(String),
+
+    #[error("A source rate of {0} items per second is out of bounds")]
+    InvalidSourceRate(f64),
+
+    #[error("Item from mux input {input} arrived {late_by:?} too late to be written in order")]
+    MuxItemTooLate {
+        input: usize,
+        late_by: std::time::Duration,
+    },
+
     #[error("FFmpeg error: {0}")]
     FFmpeg(#[from] ffmpeg::Error),
 
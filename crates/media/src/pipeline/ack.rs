@@ -0,0 +1,346 @@
+//! Item-level acknowledgement between a sink and the source feeding it.
+//!
+//! Sources tag items with a [`Sequence`], sinks acknowledge each sequence once it has been
+//! durably written, and the source keeps every item that hasn't been acknowledged yet in an
+//! [`UnackedBuffer`]. After a restart a [`Resumable`] source seeks to the sequence after the
+//! last acknowledged one and replays anything still buffered, which gives at-least-once
+//! delivery. Sinks that drop items with a sequence they've already written get exactly-once.
+//!
+//! The buffer is the source's responsibility: it must hold every un-acked item for as long as
+//! it may need to replay it. When the buffer is full the source has to wait for acks before
+//! producing more (see [`AckReceiver::wait`]); dropping items at that point breaks the
+//! delivery guarantee.
+//!
+//! [`PipelineBuilder::spawn_resumable_source`](super::builder::PipelineBuilder::spawn_resumable_source)
+//! wires this up: it resumes the source from the sequence after the last one the sink
+//! acknowledged and hands it an [`AckedOutput`], which keeps the buffer and waits for acks.
+
+use std::collections::VecDeque;
+
+use flume::{Receiver, Sender, TryRecvError};
+
+use crate::pipeline::{
+    metrics::MeteredSender,
+    task::{PipelineReadySignal, PipelineSourceTask, TaskContext},
+    PipelineControlSignal,
+};
+use crate::MediaError;
+
+pub type Sequence = u64;
+
+#[derive(Debug, Clone)]
+pub struct Sequenced<T> {
+    pub sequence: Sequence,
+    pub item: T,
+}
+
+/// Creates the channel a sink uses to acknowledge items back to its source.
+pub fn ack_channel() -> (AckSender, AckReceiver) {
+    // Unbounded so that acknowledging never blocks the sink.
+    let (tx, rx) = flume::unbounded();
+
+    (
+        AckSender(tx),
+        AckReceiver {
+            rx,
+            last_acked: None,
+        },
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct AckSender(Sender<Sequence>);
+
+impl AckSender {
+    pub fn ack(&self, sequence: Sequence) {
+        // The source having gone away is not the sink's problem.
+        let _ = self.0.send(sequence);
+    }
+}
+
+#[derive(Debug)]
+pub struct AckReceiver {
+    rx: Receiver<Sequence>,
+    last_acked: Option<Sequence>,
+}
+
+impl AckReceiver {
+    pub fn last_acked(&self) -> Option<Sequence> {
+        self.last_acked
+    }
+
+    /// Collects any pending acks without blocking and returns the latest acknowledged sequence.
+    pub fn poll(&mut self) -> Option<Sequence> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(sequence) => self.record(sequence),
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => {
+                    return self.last_acked
+                }
+            }
+        }
+    }
+
+    /// Blocks until at least one new ack arrives, returning `None` if the sink has gone away.
+    pub fn wait(&mut self) -> Option<Sequence> {
+        let sequence = self.rx.recv().ok()?;
+        self.record(sequence);
+
+        self.poll()
+    }
+
+    fn record(&mut self, sequence: Sequence) {
+        self.last_acked = Some(self.last_acked.map_or(sequence, |last| last.max(sequence)));
+    }
+}
+
+/// Implemented by sources that can restart production from a given sequence.
+pub trait Resumable {
+    /// Positions the source so that the next item it produces has the given sequence.
+    fn resume_from(&mut self, sequence: Sequence) -> Result<(), MediaError>;
+}
+
+/// Holds the items a source has sent but not yet had acknowledged, so they can be replayed.
+#[derive(Debug)]
+pub struct UnackedBuffer<T> {
+    items: VecDeque<Sequenced<T>>,
+    capacity: usize,
+    next_sequence: Sequence,
+}
+
+impl<T: Clone> UnackedBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self::starting_at(0, capacity)
+    }
+
+    pub fn starting_at(next_sequence: Sequence, capacity: usize) -> Self {
+        Self {
+            items: VecDeque::with_capacity(capacity),
+            capacity,
+            next_sequence,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.items.len() >= self.capacity
+    }
+
+    /// The sequence the next pushed item gets.
+    pub fn next_sequence(&self) -> Sequence {
+        self.next_sequence
+    }
+
+    /// Assigns the next sequence to the item and retains a copy until it's acknowledged.
+    /// Returns the item back if the buffer is full.
+    pub fn push(&mut self, item: T) -> Result<Sequenced<T>, T> {
+        if self.is_full() {
+            return Err(item);
+        }
+
+        let sequenced = Sequenced {
+            sequence: self.next_sequence,
+            item,
+        };
+        self.next_sequence += 1;
+        self.items.push_back(sequenced.clone());
+
+        Ok(sequenced)
+    }
+
+    /// Releases every item up to and including the acknowledged sequence.
+    pub fn acknowledge(&mut self, sequence: Sequence) {
+        while self
+            .items
+            .front()
+            .is_some_and(|item| item.sequence <= sequence)
+        {
+            self.items.pop_front();
+        }
+    }
+
+    /// The items that still have to be delivered, oldest first.
+    pub fn replay(&self) -> impl Iterator<Item = &Sequenced<T>> {
+        self.items.iter()
+    }
+}
+
+/// The output of a source spawned with
+/// [`PipelineBuilder::spawn_resumable_source`](super::builder::PipelineBuilder::spawn_resumable_source).
+/// Sends each item with the next sequence and keeps it in an [`UnackedBuffer`] until the
+/// sink acknowledges it, waiting for acks while the buffer is full.
+pub struct AckedOutput<T> {
+    output: MeteredSender<Sequenced<T>>,
+    buffer: UnackedBuffer<T>,
+    acks: AckReceiver,
+}
+
+impl<T: Clone + Send + 'static> AckedOutput<T> {
+    pub(super) fn new(
+        output: MeteredSender<Sequenced<T>>,
+        buffer: UnackedBuffer<T>,
+        acks: AckReceiver,
+    ) -> Self {
+        Self {
+            output,
+            buffer,
+            acks,
+        }
+    }
+
+    /// The sequence the next item is sent with.
+    pub fn next_sequence(&self) -> Sequence {
+        self.buffer.next_sequence()
+    }
+
+    /// How many items have been sent but not acknowledged yet.
+    pub fn unacked(&mut self) -> usize {
+        self.release_acked();
+        self.buffer.len()
+    }
+
+    /// Sends the item, first waiting for acks while the buffer is full. Hands the item back
+    /// once the sink has gone away, as it can then neither receive nor acknowledge it.
+    pub fn send(&mut self, item: T) -> Result<Sequence, T> {
+        self.release_acked();
+
+        let mut item = item;
+        let sequenced = loop {
+            match self.buffer.push(item) {
+                Ok(sequenced) => break sequenced,
+                Err(rejected) => {
+                    let Some(acked) = self.acks.wait() else {
+                        return Err(rejected);
+                    };
+                    self.buffer.acknowledge(acked);
+                    item = rejected;
+                }
+            }
+        };
+
+        let sequence = sequenced.sequence;
+        self.output
+            .send(sequenced)
+            .map_err(|error| error.into_inner().item)?;
+
+        Ok(sequence)
+    }
+
+    /// Sends every item that hasn't been acknowledged yet again, oldest first, e.g. once the
+    /// sink has reconnected to the external system it writes to.
+    pub fn replay(&mut self) -> Result<(), MediaError> {
+        self.release_acked();
+
+        for sequenced in self.buffer.replay() {
+            if self.output.send(sequenced.clone()).is_err() {
+                return Err(MediaError::Any("The sink has gone away".into()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn release_acked(&mut self) {
+        if let Some(acked) = self.acks.poll() {
+            self.buffer.acknowledge(acked);
+        }
+    }
+}
+
+/// Resumes the source before running it, see
+/// [`PipelineBuilder::spawn_resumable_source`](super::builder::PipelineBuilder::spawn_resumable_source).
+pub(super) struct ResumingSource<S> {
+    pub source: S,
+    pub start: Sequence,
+}
+
+impl<S: PipelineSourceTask + Resumable> PipelineSourceTask for ResumingSource<S> {
+    type Clock = S::Clock;
+
+    fn run(
+        &mut self,
+        clock: Self::Clock,
+        ready_signal: PipelineReadySignal,
+        control_signal: PipelineControlSignal,
+    ) {
+        self.run_with_context(clock, ready_signal, control_signal, TaskContext::default());
+    }
+
+    fn run_with_context(
+        &mut self,
+        clock: Self::Clock,
+        ready_signal: PipelineReadySignal,
+        control_signal: PipelineControlSignal,
+        context: TaskContext,
+    ) {
+        if let Err(error) = self.source.resume_from(self.start) {
+            let _ = ready_signal.send(Err(error));
+            return;
+        }
+
+        self.source
+            .run_with_context(clock, ready_signal, control_signal, context);
+    }
+
+    fn queue_size(&self) -> usize {
+        self.source.queue_size()
+    }
+
+    fn acks_control_messages(&self) -> bool {
+        self.source.acks_control_messages()
+    }
+
+    fn required_capabilities(&self) -> &'static [&'static str] {
+        self.source.required_capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unacked_buffer_holds_items_until_acknowledged() {
+        let mut buffer = UnackedBuffer::starting_at(5, 2);
+
+        assert_eq!(buffer.push("a").unwrap().sequence, 5);
+        assert_eq!(buffer.push("b").unwrap().sequence, 6);
+        assert!(buffer.is_full());
+        assert_eq!(buffer.push("c").unwrap_err(), "c");
+
+        // Acks are cumulative.
+        buffer.acknowledge(5);
+        let replayed = buffer.replay().map(|item| item.item).collect::<Vec<_>>();
+        assert_eq!(replayed, vec!["b"]);
+
+        assert_eq!(buffer.push("c").unwrap().sequence, 7);
+        buffer.acknowledge(7);
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.next_sequence(), 8);
+    }
+
+    #[test]
+    fn ack_receiver_keeps_the_latest_sequence() {
+        let (sender, mut receiver) = ack_channel();
+        assert_eq!(receiver.poll(), None);
+
+        sender.ack(3);
+        sender.ack(1);
+        assert_eq!(receiver.poll(), Some(3));
+
+        sender.ack(4);
+        assert_eq!(receiver.wait(), Some(4));
+        assert_eq!(receiver.last_acked(), Some(4));
+
+        drop(sender);
+        assert_eq!(receiver.wait(), None);
+        assert_eq!(receiver.last_acked(), Some(4));
+    }
+}
@@ -0,0 +1,154 @@
+//! Checks that two timestamped streams, such as the audio and video edges of a recording,
+//! stay in sync over a session.
+//!
+//! Each stream is recorded as [`SyncSample`]s: the timestamp an item carried, and when it
+//! arrived on a reference timeline shared by both streams. A stream's lag is how far its
+//! arrivals trail its timestamps, and the drift between the streams is the difference in
+//! how that lag changes over the session. A constant offset between the streams, as fixed
+//! by [`PipelinePathBuilder::shift_timestamps`](super::builder::PipelinePathBuilder::shift_timestamps),
+//! doesn't count as drift.
+//!
+//! Arrival times are supplied by the caller, so they can come from a deterministic clock
+//! in tests as well as from `Instant`s in the field.
+
+use std::{fmt, time::Duration};
+
+use super::stages::TimestampMut;
+
+/// One item of a recorded stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncSample {
+    /// The timestamp the item carried.
+    pub timestamp: Duration,
+    /// When the item arrived, on the timeline shared by the streams being compared.
+    pub arrived_at: Duration,
+}
+
+impl SyncSample {
+    pub fn new(timestamp: Duration, arrived_at: Duration) -> Self {
+        Self {
+            timestamp,
+            arrived_at,
+        }
+    }
+
+    /// Records an item, such as a `(frame, seconds)` pair taken off an edge.
+    pub fn of(item: &impl TimestampMut, arrived_at: Duration) -> Self {
+        Self::new(item.timestamp(), arrived_at)
+    }
+
+    fn lag(&self) -> f64 {
+        self.arrived_at.as_secs_f64() - self.timestamp.as_secs_f64()
+    }
+}
+
+/// How far two streams drifted apart, see [`measure_drift`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriftReport {
+    pub max_drift: Duration,
+    pub mean_drift: Duration,
+    /// The number of samples of the first stream the drift was measured at.
+    pub samples: usize,
+}
+
+impl DriftReport {
+    /// Panics with the report if the maximum drift exceeds `tolerance`.
+    #[track_caller]
+    pub fn assert_within(&self, tolerance: Duration) {
+        assert!(
+            self.max_drift <= tolerance,
+            "A/V drift exceeds {tolerance:?}: {self}"
+        );
+    }
+}
+
+impl fmt::Display for DriftReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "max drift {:?}, mean drift {:?} over {} samples",
+            self.max_drift, self.mean_drift, self.samples
+        )
+    }
+}
+
+/// Measures the drift of `b` relative to `a`, both in arrival order. Each sample of `a` is
+/// compared with the latest sample of `b` that arrived by then. Returns `None` if either
+/// stream is empty or they never overlap.
+pub fn measure_drift(a: &[SyncSample], b: &[SyncSample]) -> Option<DriftReport> {
+    let (first_a, first_b) = (a.first()?, b.first()?);
+    let mut b_index = 0;
+    let mut drifts = vec![];
+
+    for sample in a {
+        while b
+            .get(b_index + 1)
+            .is_some_and(|next| next.arrived_at <= sample.arrived_at)
+        {
+            b_index += 1;
+        }
+
+        let other = &b[b_index];
+        if other.arrived_at > sample.arrived_at {
+            continue;
+        }
+
+        let drift = (sample.lag() - first_a.lag()) - (other.lag() - first_b.lag());
+        drifts.push(drift.abs());
+    }
+
+    if drifts.is_empty() {
+        return None;
+    }
+
+    let max = drifts.iter().copied().fold(0.0, f64::max);
+    let mean = drifts.iter().sum::<f64>() / drifts.len() as f64;
+
+    Some(DriftReport {
+        max_drift: Duration::from_secs_f64(max),
+        mean_drift: Duration::from_secs_f64(mean),
+        samples: drifts.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(period_ms: u64, drift_per_item_us: u64, count: u64) -> Vec<SyncSample> {
+        (0..count)
+            .map(|i| {
+                let timestamp = Duration::from_millis(i * period_ms);
+                SyncSample::new(
+                    timestamp,
+                    timestamp + Duration::from_micros(i * drift_per_item_us),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn constant_offset_is_not_drift() {
+        let video = stream(33, 0, 100);
+        let audio = stream(10, 0, 330)
+            .into_iter()
+            .map(|s| SyncSample::new(s.timestamp, s.arrived_at + Duration::from_millis(40)))
+            .collect::<Vec<_>>();
+
+        let report = measure_drift(&video, &audio).unwrap();
+
+        report.assert_within(Duration::from_micros(1));
+    }
+
+    #[test]
+    fn drift_grows_over_the_session() {
+        let video = stream(33, 0, 100);
+        // The audio arrives 100us later with every item, so over 30ms late by the end.
+        let audio = stream(10, 100, 330);
+
+        let report = measure_drift(&video, &audio).unwrap();
+
+        assert!(report.max_drift > Duration::from_millis(30));
+        assert!(report.mean_drift < report.max_drift);
+    }
+}
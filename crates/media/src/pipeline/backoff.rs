@@ -0,0 +1,121 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+/// Exponential backoff with full jitter, for tasks that retry something that failed, such
+/// as re-opening a device. The `n`th delay is picked uniformly between zero and
+/// `base * 2^n`, capped at `cap`, so tasks that failed together retry spread out rather
+/// than all at once.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    base: Duration,
+    cap: Duration,
+    attempt: u32,
+    rng: u64,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, cap: Duration) -> Self {
+        Self {
+            base,
+            cap,
+            attempt: 0,
+            rng: 0,
+        }
+        .with_seed(RandomState::new().build_hasher().finish())
+    }
+
+    /// Makes the jitter repeatable, e.g. for tests.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        // Xorshift gets stuck on zero.
+        self.rng = seed.max(1);
+        self
+    }
+
+    /// How many delays have been taken since creation or the last [`Self::reset`].
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// The longest the next delay can be.
+    pub fn ceiling(&self) -> Duration {
+        let factor = 1u32.checked_shl(self.attempt).unwrap_or(u32::MAX);
+        self.base.saturating_mul(factor).min(self.cap)
+    }
+
+    /// The delay to wait before the next retry.
+    pub fn next_delay(&mut self) -> Duration {
+        let ceiling = self.ceiling();
+        self.attempt = self.attempt.saturating_add(1);
+
+        let fraction = (self.next_random() >> 11) as f64 / (1u64 << 53) as f64;
+        ceiling.mul_f64(fraction)
+    }
+
+    /// Starts over from the shortest delay, e.g. once the retried operation succeeded.
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// Xorshift64*, plenty for spreading out retries.
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ceiling_doubles_up_to_the_cap() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(1));
+
+        let ceilings = (0..6)
+            .map(|_| {
+                let ceiling = backoff.ceiling();
+                backoff.next_delay();
+                ceiling.as_millis()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ceilings, vec![100, 200, 400, 800, 1000, 1000]);
+
+        backoff.reset();
+        assert_eq!(backoff.ceiling(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn jitter_stays_within_the_ceiling_and_spreads_out() {
+        let mut backoff =
+            Backoff::new(Duration::from_millis(10), Duration::from_millis(80)).with_seed(7);
+        let mut delays = vec![];
+
+        for _ in 0..1000 {
+            let ceiling = backoff.ceiling();
+            let delay = backoff.next_delay();
+            assert!(delay <= ceiling, "{delay:?} exceeds {ceiling:?}");
+            if ceiling == Duration::from_millis(80) {
+                delays.push(delay);
+            }
+        }
+
+        let shortest = delays.iter().min().unwrap();
+        let longest = delays.iter().max().unwrap();
+        assert!(*shortest < Duration::from_millis(20) && *longest > Duration::from_millis(60));
+    }
+
+    #[test]
+    fn a_huge_attempt_count_doesnt_overflow() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+
+        for _ in 0..100 {
+            backoff.next_delay();
+        }
+        assert_eq!(backoff.ceiling(), Duration::from_secs(30));
+    }
+}
@@ -1,30 +1,202 @@
 use flume::Receiver;
-use futures::pin_mut;
-use indexmap::IndexMap;
+use futures::{pin_mut, stream::FuturesUnordered, Stream, StreamExt};
+use indexmap::{IndexMap, IndexSet};
 use std::{
-    thread::{self, JoinHandle},
+    any::Any,
+    cell::RefCell,
+    collections::HashSet,
+    fmt::Display,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Once,
+    },
+    thread,
     time::Duration,
 };
-use tokio::sync::oneshot;
-use tracing::{error, info, trace};
+use tokio::{runtime::Handle, sync::oneshot};
+use tracing::{error, info, trace, warn};
 
 use crate::pipeline::{
+    ack::{
+        ack_channel, AckSender, AckedOutput, Resumable, ResumingSource, Sequence, Sequenced,
+        UnackedBuffer,
+    },
     clock::CloneFrom,
-    control::ControlBroadcast,
-    task::{PipelineReadySignal, PipelineSinkTask, PipelineSourceTask},
-    MediaError, Pipeline, PipelineClock,
+    completion::{
+        Completion, CompletionReason, DurationMeasure, ShutdownHook, ShutdownReport, TaskOutcomes,
+    },
+    control::{
+        spawn_periodic_message, Control, ControlBroadcast, ControlMessage, ControlMessages,
+        ControlRelay,
+    },
+    device::{DeviceRegistry, TaskDeviceScope},
+    lazy::LazyReceiver,
+    lifecycle::{LifecycleEvent, LifecycleEvents},
+    metrics::{MeteredSender, PipelineMetrics, SingleConsumer},
+    pool::{TaskHandle, ThreadPool},
+    stages::{Backpressure, PathMode},
+    task::{
+        PipelineReadySignal, PipelineSinkTask, PipelineSourceTask, SourceEnd, TaskContext,
+        DEFAULT_QUEUE_SIZE,
+    },
+    task_log::task_dispatcher,
+    watchdog::{
+        spawn_duration_limit, spawn_idle_watchdog, spawn_no_data_check, spawn_skew_monitor,
+    },
+    ChosenCandidate, Extensions, MediaError, Pipeline, PipelineClock, PipelineControlSignal,
 };
+use crate::sources::StreamSource;
+
+const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// When a task is started, see [`PipelineBuilder::spawn_source_with_priority`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StartPriority {
+    /// Started straight away, and `build` waits for the task to become ready.
+    #[default]
+    Normal,
+    /// Started once every normal task is ready, and `build` returns without waiting for
+    /// the task, which carries on starting in the background. The task is optional: if
+    /// it fails, whether to start or later on, it shows up as failed in the lifecycle
+    /// events and the shutdown report without stopping the pipeline.
+    Low,
+}
 
 struct Task {
     ready_signal: Receiver<Result<(), MediaError>>,
-    join_handle: JoinHandle<()>,
+    thread: TaskThread,
     done_rx: tokio::sync::oneshot::Receiver<Result<(), String>>,
+    priority: StartPriority,
+}
+
+/// A task's thread is spawned straight away, unless the task depends on other tasks or has
+/// a low start priority, in which case `build` spawns it once they are all ready.
+enum TaskThread {
+    Running(TaskHandle),
+    Deferred {
+        dependencies: Vec<String>,
+        spawn: Box<dyn FnOnce() -> TaskHandle + Send>,
+    },
+}
+
+/// Creates one of the candidate tasks of
+/// [`PipelineBuilder::spawn_source_with_fallback`], e.g. by opening a device.
+pub type SourceFactory<S> = Box<dyn FnOnce() -> Result<S, MediaError> + Send>;
+
+/// A mistake in how the pipeline was put together, found while adding a task. Unlike
+/// [`MediaError`], this can be reported more than once, see [`PipelineBuilder::validate`].
+#[derive(Debug, Clone)]
+enum BuildProblem {
+    DuplicateTask(String),
+    DuplicateControlListener(String),
+    UnknownDependency {
+        task: String,
+        dependency: String,
+    },
+    LowPriorityDependency {
+        task: String,
+        dependency: String,
+    },
+    MissingCapability {
+        task: String,
+        capability: String,
+    },
+    ZeroQueueSize(String),
+    DuplicateEndpoint(String),
+    UnknownEndpoint(String),
+    EndpointTypeMismatch {
+        endpoint: String,
+        sealed: &'static str,
+        requested: &'static str,
+    },
+}
+
+impl BuildProblem {
+    fn error(&self) -> MediaError {
+        match self.clone() {
+            Self::DuplicateTask(task) => MediaError::DuplicateTask(task),
+            Self::DuplicateControlListener(name) => MediaError::DuplicateControlListener(name),
+            Self::UnknownDependency { task, dependency } => {
+                MediaError::UnknownDependency { task, dependency }
+            }
+            Self::LowPriorityDependency { task, dependency } => {
+                MediaError::LowPriorityDependency { task, dependency }
+            }
+            Self::MissingCapability { task, capability } => {
+                MediaError::MissingCapability { task, capability }
+            }
+            Self::ZeroQueueSize(task) => MediaError::ZeroQueueSize(task),
+            Self::DuplicateEndpoint(endpoint) => MediaError::DuplicateEndpoint(endpoint),
+            Self::UnknownEndpoint(endpoint) => MediaError::UnknownEndpoint(endpoint),
+            Self::EndpointTypeMismatch {
+                endpoint,
+                sealed,
+                requested,
+            } => MediaError::EndpointTypeMismatch {
+                endpoint,
+                sealed,
+                requested,
+            },
+        }
+    }
+}
+
+/// The end of a path sealed with [`PipelinePathBuilder::seal`], to be carried on from
+/// with [`PipelineBuilder::reopen`].
+struct Endpoint {
+    /// The path's `Receiver<O>`.
+    receiver: Box<dyn Any + Send>,
+    item_type: &'static str,
+    mode: PathMode,
+    latency: Duration,
+}
+
+#[derive(Default)]
+pub(super) struct LaunchOptions {
+    pub core_id: Option<usize>,
+    pub dependencies: Vec<String>,
+    pub capabilities: &'static [&'static str],
+    pub priority: StartPriority,
 }
 
 pub struct PipelineBuilder<T> {
     clock: T,
     control: ControlBroadcast,
     tasks: IndexMap<String, Task>,
+    ready_timeout: Duration,
+    build_deadline: Option<Duration>,
+    propagate_panics: bool,
+    ready_capacity: usize,
+    devices: DeviceRegistry,
+    metrics: PipelineMetrics,
+    idle_timeout: Option<Duration>,
+    no_data_grace: Option<Duration>,
+    rotation_interval: Option<Duration>,
+    flush_interval: Option<Duration>,
+    log_summary: bool,
+    skew_warning: Option<Duration>,
+    max_duration: Option<(Duration, DurationMeasure)>,
+    max_latency: Option<Duration>,
+    /// The highest worst-case latency estimate of any path, see [`Self::with_max_latency`].
+    path_latency: Duration,
+    pool: Option<ThreadPool>,
+    completion: Completion,
+    outcomes: TaskOutcomes,
+    capabilities: HashSet<String>,
+    /// Every problem found while adding tasks, in the order they were found.
+    problems: Vec<BuildProblem>,
+    /// Tasks that were added after the first problem, and so weren't launched, along with
+    /// their start priority. Kept to check the tasks added after them.
+    rejected: IndexMap<String, StartPriority>,
+    context: TaskContext,
+    log_dir: Option<PathBuf>,
+    lifecycle: LifecycleEvents,
+    on_shutdown: ShutdownHook,
+    extensions: Extensions,
+    chosen_candidates: IndexMap<String, ChosenCandidate>,
+    source_ends: IndexMap<String, SourceEnd>,
+    endpoints: IndexMap<String, Endpoint>,
 }
 
 impl<T> PipelineBuilder<T> {
@@ -33,9 +205,403 @@ impl<T> PipelineBuilder<T> {
             clock,
             control: ControlBroadcast::default(),
             tasks: IndexMap::new(),
+            ready_timeout: DEFAULT_READY_TIMEOUT,
+            build_deadline: None,
+            propagate_panics: false,
+            ready_capacity: 1,
+            devices: DeviceRegistry::default(),
+            metrics: PipelineMetrics::default(),
+            idle_timeout: None,
+            no_data_grace: None,
+            rotation_interval: None,
+            flush_interval: None,
+            log_summary: false,
+            skew_warning: None,
+            max_duration: None,
+            max_latency: None,
+            path_latency: Duration::ZERO,
+            pool: None,
+            completion: Completion::default(),
+            outcomes: TaskOutcomes::default(),
+            capabilities: HashSet::new(),
+            problems: vec![],
+            rejected: IndexMap::new(),
+            context: TaskContext::default(),
+            log_dir: None,
+            lifecycle: LifecycleEvents::default(),
+            on_shutdown: ShutdownHook::default(),
+            extensions: Extensions::default(),
+            chosen_candidates: IndexMap::new(),
+            source_ends: IndexMap::new(),
+            endpoints: IndexMap::new(),
+        }
+    }
+
+    /// How long `build` waits for each individual task to signal that it is ready.
+    pub fn with_ready_timeout(mut self, timeout: Duration) -> Self {
+        self.ready_timeout = timeout;
+        self
+    }
+
+    /// A hard limit on the whole of `build`, covering the ready waits, the settle delay
+    /// and the completion monitor setup. Unlike the per-task ready timeout, this also
+    /// catches setup that hangs outside of waiting on a single task.
+    pub fn with_build_deadline(mut self, deadline: Duration) -> Self {
+        self.build_deadline = Some(deadline);
+        self
+    }
+
+    /// By default, a panicking task is caught and reported as a failure, so one misbehaving
+    /// task can't take down the rest of the app. Enabling propagation lets the panic unwind
+    /// the task thread normally instead, so `RUST_BACKTRACE` points at the panic site. The
+    /// panic is still reported through the completion channel, but the thread is lost, so
+    /// this is meant for debugging rather than production use.
+    pub fn with_panic_propagation(mut self, propagate: bool) -> Self {
+        self.propagate_panics = propagate;
+        self
+    }
+
+    /// The capacity of each task's ready channel. `build` consumes exactly one ready signal
+    /// per task, so the default of one slot is enough for tasks that signal once. Tasks that
+    /// signal ready again, e.g. after re-acquiring a device, need more room so a re-signal
+    /// sent before `build` has read the first doesn't block them. Once the pipeline is built
+    /// the ready channels are closed and further signals return an error, which tasks
+    /// should ignore. Applies to tasks spawned after this is set.
+    pub fn with_ready_capacity(mut self, capacity: usize) -> Self {
+        self.ready_capacity = capacity.max(1);
+        self
+    }
+
+    /// Shuts the pipeline down with [`MediaError::IdleTimeout`] if no items flow through
+    /// any metered edge for the given duration, e.g. when a device silently stops producing.
+    /// Time spent paused doesn't count towards the timeout. A timeout of zero turns the
+    /// watchdog off, as no pipeline could ever meet it.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = (!timeout.is_zero()).then_some(timeout);
+        self
+    }
+
+    /// Logs a prominent warning if, once the pipeline has been playing for `grace`, no items
+    /// have been sent on any metered edge, which usually means it's misconfigured, e.g. a
+    /// stage whose input never gets connected. Unlike [`Self::with_idle_timeout`] this only
+    /// checks once and doesn't stop the pipeline. Leave it off for pipelines that may
+    /// legitimately start out idle, such as ones waiting for a trigger.
+    pub fn with_no_data_warning(mut self, grace: Duration) -> Self {
+        self.no_data_grace = Some(grace);
+        self
+    }
+
+    /// Logs a one-line [`PipelineSummary`](crate::pipeline::metrics::PipelineSummary)
+    /// once the pipeline has shut down.
+    pub fn with_shutdown_summary(mut self, enabled: bool) -> Self {
+        self.log_summary = enabled;
+        self
+    }
+
+    /// Stops the pipeline once it has run for `duration`, e.g. to record for 10 seconds.
+    /// The tasks are shut down as with a normal stop and the completion reason is
+    /// [`CompletionReason::DurationLimitReached`], unless the pipeline was stopped for
+    /// another reason first.
+    pub fn with_max_duration(mut self, duration: Duration, measure: DurationMeasure) -> Self {
+        self.max_duration = Some((duration, measure));
+        self
+    }
+
+    /// Fails the build with [`MediaError::LatencyBudgetExceeded`] if the worst-case latency
+    /// estimate of any path exceeds `budget`, e.g. to catch a live pipeline that buffers so
+    /// much that it can fall seconds behind.
+    ///
+    /// The estimate is deliberately simple: a stage can have a full output edge queued up
+    /// behind each item, so it contributes its queue size times its expected time per item,
+    /// set with [`PipelinePathBuilder::with_item_time`]. A path's estimate is the sum over
+    /// its stages, and the longest path counts. Stages without an item time, tasks outside
+    /// of paths and paths started from a receiver don't know what came before them, so the
+    /// estimate can be too low, never too high.
+    pub fn with_max_latency(mut self, budget: Duration) -> Self {
+        self.max_latency = Some(budget);
+        self
+    }
+
+    /// Adds a stage with an output edge of `queue_size` items to a path whose latency
+    /// estimate so far is `upstream`, returning the path's new estimate.
+    pub(super) fn add_path_latency(
+        &mut self,
+        upstream: Duration,
+        item_time: Duration,
+        queue_size: usize,
+    ) -> Duration {
+        let latency =
+            upstream + item_time.saturating_mul(queue_size.try_into().unwrap_or(u32::MAX));
+        self.path_latency = self.path_latency.max(latency);
+        latency
+    }
+
+    /// Logs a warning when the clock drifts from the wall clock by more than `threshold`,
+    /// which shows up as audio desyncing over long recordings.
+    pub fn with_skew_warning(mut self, threshold: Duration) -> Self {
+        self.skew_warning = Some(threshold);
+        self
+    }
+
+    /// Runs the tasks on a pool of `threads` threads instead of a thread each, for large
+    /// graphs on constrained devices. A task occupies its thread until it returns, and one
+    /// that is still waiting for a thread never signals ready, so the pool has to be sized
+    /// to the number of long-lived tasks or `build` times out. Tasks spawned with
+    /// [`Self::spawn_source_pinned`] always get a thread of their own. Applies to tasks
+    /// spawned after this is set.
+    pub fn with_max_threads(mut self, threads: usize) -> Self {
+        self.pool = Some(ThreadPool::new(threads));
+        self
+    }
+
+    /// Additionally writes each task's logs to `<dir>/<task name>.log`, for field
+    /// diagnostics. Characters that aren't safe in a file name are replaced with `_`. The
+    /// logs are still passed on to the app's own subscriber, at the same level.
+    ///
+    /// Every task gets its own file writer, which formats and writes each event on the
+    /// task's thread. Under high log volume that slows the tasks down noticeably, so
+    /// keep this off unless the per-task logs are needed. Applies to tasks spawned after
+    /// this is set.
+    pub fn with_per_task_log_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.log_dir = Some(dir.into());
+        self
+    }
+
+    /// Calls `observer` with [`LifecycleEvent`]s as tasks launch, become ready and stop, and
+    /// when the pipeline shuts down, e.g. to update a UI. The observer runs on a thread of
+    /// its own, so it can't stall the pipeline: if it falls behind, events are dropped.
+    /// Applies to tasks spawned after this is set.
+    pub fn with_lifecycle_observer(
+        mut self,
+        observer: impl Fn(LifecycleEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.lifecycle = self.lifecycle.with_observer(observer);
+        self
+    }
+
+    /// Runs `hook` once the whole pipeline has stopped, with the report of how each task
+    /// ended, e.g. to flush a manifest after all files are closed. It runs exactly once,
+    /// once every task has finished, however the pipeline stopped: from [`Pipeline::shutdown`]
+    /// once the tasks are joined, or from the completion monitor when the tasks stop on their
+    /// own or fail. When a graceful shutdown stalls and the tasks are detached, it runs
+    /// straight away instead.
+    pub fn with_on_shutdown(mut self, hook: impl FnOnce(&ShutdownReport) + Send + 'static) -> Self {
+        self.on_shutdown = ShutdownHook::new(hook);
+        self
+    }
+
+    /// Attaches a value to the built pipeline, to be retrieved with
+    /// [`Pipeline::extension`], e.g. the path of the output file or a session id. This is
+    /// only for the caller's convenience: the pipeline and its tasks never look at it, see
+    /// [`Self::with_context`] for state that tasks share. Values are keyed by their type,
+    /// so inserting another value of the same type replaces the previous one.
+    pub fn insert_extension<V: Send + Sync + 'static>(mut self, value: V) -> Self {
+        self.extensions.insert(value);
+        self
+    }
+
+    /// Sets the state shared by all tasks, which source and sink tasks receive in
+    /// `run_with_context`, after their clock and control arguments. Closure tasks can get it
+    /// from [`Self::context`] instead. Applies to tasks added after this is set.
+    pub fn with_context<C: Send + Sync + 'static>(mut self, context: C) -> Self {
+        self.context = TaskContext::new(context);
+        self
+    }
+
+    /// The state set with [`Self::with_context`], if it is of type `C`.
+    pub fn context<C: Send + Sync + 'static>(&self) -> Option<Arc<C>> {
+        self.context.get()
+    }
+
+    pub(super) fn task_context(&self) -> TaskContext {
+        self.context.clone()
+    }
+
+    /// Registers a capability that tasks can require through `required_capabilities`,
+    /// e.g. a feature the app was compiled with. Capabilities have to be registered before
+    /// the tasks that require them are added.
+    pub fn with_capability(mut self, capability: impl Into<String>) -> Self {
+        self.capabilities.insert(capability.into());
+        self
+    }
+
+    /// Fails with the first mistake found in how the tasks added so far were put together:
+    /// a duplicate task name ([`MediaError::DuplicateTask`]) or control listener name
+    /// ([`MediaError::DuplicateControlListener`]), a dependency that wasn't
+    /// added before its dependent ([`MediaError::UnknownDependency`], which also rules out
+    /// cycles) or that has a low start priority ([`MediaError::LowPriorityDependency`]), a
+    /// capability that wasn't registered ([`MediaError::MissingCapability`]), or a source
+    /// with a queue size of zero ([`MediaError::ZeroQueueSize`]).
+    ///
+    /// Once that happens, no further tasks are launched and `build` fails with the same
+    /// error, so this only gives earlier feedback. Without any such mistakes, this fails
+    /// with [`MediaError::LatencyBudgetExceeded`] for a latency budget set with
+    /// [`Self::with_max_latency`] that the stages added so far exceed. See
+    /// [`Self::validate_all`] for every mistake rather than the first.
+    pub fn validate(&self) -> Result<(), MediaError> {
+        match self.problems.first() {
+            Some(problem) => Err(problem.error()),
+            None => self.latency_error().map_or(Ok(()), Err),
+        }
+    }
+
+    /// Like [`Self::validate`], but fails with every mistake found, in the order the tasks
+    /// were added, e.g. so that an editor for pipeline specs can highlight all of them.
+    pub fn validate_all(&self) -> Result<(), Vec<MediaError>> {
+        let errors = self
+            .problems
+            .iter()
+            .map(BuildProblem::error)
+            .chain(self.latency_error())
+            .collect::<Vec<_>>();
+
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        Err(errors)
+    }
+
+    fn latency_error(&self) -> Option<MediaError> {
+        let budget = self.max_latency?;
+
+        (self.path_latency > budget).then_some(MediaError::LatencyBudgetExceeded {
+            estimate: self.path_latency,
+            budget,
+        })
+    }
+
+    /// Automatically sends [`ControlMessage::Rotate`] at the given interval once built,
+    /// for sinks writing segmented output. An interval of zero sends none.
+    pub fn with_rotation_interval(mut self, interval: Duration) -> Self {
+        self.rotation_interval = (!interval.is_zero()).then_some(interval);
+        self
+    }
+
+    /// Automatically sends [`ControlMessage::Flush`] at the given interval once built, which
+    /// bounds how much buffered output a crash can lose. The interval is measured in wall
+    /// time rather than by the pipeline clock, so flushes carry on while paused. An interval
+    /// of zero sends none.
+    pub fn with_flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = (!interval.is_zero()).then_some(interval);
+        self
+    }
+
+    pub(super) fn control_signal(&mut self, name: String, acks: bool) -> PipelineControlSignal {
+        self.add_listener(name, acks)
+    }
+
+    /// Subscribes a task that has no control signal of its own, such as a sink spawned
+    /// with [`Self::spawn_task`], to the pipeline's [`ControlMessage`]s.
+    pub fn control_messages(&mut self, name: impl Into<String>) -> ControlMessages {
+        let name = name.into();
+        self.check_listener_name(&name);
+        self.control.add_message_listener(name)
+    }
+
+    fn add_listener(&mut self, name: String, acks: bool) -> PipelineControlSignal {
+        self.check_listener_name(&name);
+        self.control.add_listener(name, acks)
+    }
+
+    /// A second control listener under a name that isn't a duplicate task, which
+    /// `launch_task` reports, would leave one of the two tasks without its messages.
+    fn check_listener_name(&mut self, name: &str) {
+        if self.control.has_listener(name) && self.start_priority(name).is_none() {
+            self.problems
+                .push(BuildProblem::DuplicateControlListener(name.to_string()));
         }
     }
 
+    /// The pipeline clock, e.g. for a task to take a clone of and time things against
+    /// with [`PipelineClock::now`].
+    pub fn clock(&self) -> &T {
+        &self.clock
+    }
+
+    /// Carries on the path sealed as `endpoint` with [`PipelinePathBuilder::seal`], e.g. to
+    /// add a sink to it from another module. `O` has to be the type of the path's items.
+    /// If there is no such endpoint or it carries another type, `build` fails with
+    /// [`MediaError::UnknownEndpoint`] or [`MediaError::EndpointTypeMismatch`], and the
+    /// path returned here never receives anything.
+    pub fn reopen<O: Send + 'static>(
+        mut self,
+        endpoint: impl Into<String>,
+    ) -> PipelinePathBuilder<T, O> {
+        let name = endpoint.into();
+        let problem = match self.endpoints.shift_remove(&name) {
+            None => BuildProblem::UnknownEndpoint(name),
+            Some(endpoint) => match endpoint.receiver.downcast::<Receiver<O>>() {
+                Ok(receiver) => {
+                    let mut path = PipelinePathBuilder::new(self, *receiver);
+                    path.mode = endpoint.mode;
+                    path.latency = endpoint.latency;
+                    return path;
+                }
+                Err(receiver) => {
+                    let problem = BuildProblem::EndpointTypeMismatch {
+                        endpoint: name.clone(),
+                        sealed: endpoint.item_type,
+                        requested: std::any::type_name::<O>(),
+                    };
+                    // Keep it for reopening with the right type.
+                    self.endpoints.insert(
+                        name,
+                        Endpoint {
+                            receiver,
+                            ..endpoint
+                        },
+                    );
+                    problem
+                }
+            },
+        };
+
+        self.problems.push(problem);
+        PipelinePathBuilder::new(self, flume::bounded(0).1)
+    }
+
+    /// Whether a path has been sealed as `endpoint` and not reopened yet.
+    pub fn has_endpoint(&self, endpoint: &str) -> bool {
+        self.endpoints.contains_key(endpoint)
+    }
+
+    /// A run of more than `threshold` consecutive drops on an edge is counted as a drop burst
+    /// in the metrics. Applies to edges created afterwards.
+    pub fn with_drop_burst_threshold(mut self, threshold: u64) -> Self {
+        self.metrics.set_drop_burst_threshold(threshold);
+        self
+    }
+
+    /// Creates a bounded channel that is tracked in the pipeline's metrics.
+    pub fn edge<O: Send + 'static>(
+        &self,
+        name: impl Into<String>,
+        capacity: usize,
+    ) -> (MeteredSender<O>, Receiver<O>) {
+        self.metrics.edge(name, capacity)
+    }
+
+    /// Like [`Self::edge`], for an edge fed by the task called `task`, which is recorded in
+    /// the pipeline's [topology](PipelineMetrics::topology).
+    pub(super) fn task_edge<O: Send + 'static>(
+        &self,
+        task: &str,
+        name: impl Into<String>,
+        capacity: usize,
+    ) -> (MeteredSender<O>, Receiver<O>) {
+        let name = name.into();
+        let edge = self.metrics.edge(name.clone(), capacity);
+        self.metrics.produced_by(&name, task);
+        edge
+    }
+
+    /// Records `task` as the reader of `input` in the pipeline's
+    /// [topology](PipelineMetrics::topology).
+    pub(super) fn consumes<I: Send + 'static>(&self, task: &str, input: &Receiver<I>) {
+        self.metrics.consumed_by(input, task);
+    }
+
     pub fn source<O: Send + 'static, C: CloneFrom<T> + Send + 'static>(
         mut self,
         name: impl Into<String>,
@@ -43,18 +609,34 @@ impl<T> PipelineBuilder<T> {
     ) -> PipelinePathBuilder<T, O> {
         let name = name.into();
         let (output, next_input) = flume::bounded(task.queue_size());
+        if task.queue_size() == 0 {
+            self.problems
+                .push(BuildProblem::ZeroQueueSize(name.clone()));
+        }
         let clock = C::clone_from(&self.clock);
-        let control_signal = self.control.add_listener(name.clone());
+        let control_signal = self.add_listener(name.clone(), task.acks_control_messages());
+        let context = self.context.clone();
 
-        self.spawn_task(name, move |ready_signal| {
-            task.run(clock, ready_signal, control_signal);
+        let options = LaunchOptions {
+            capabilities: task.required_capabilities(),
+            ..Default::default()
+        };
+
+        self.launch_task(name, options, move |ready_signal| {
+            task.run_with_context(clock, ready_signal, control_signal, context);
             Ok(())
         });
 
-        PipelinePathBuilder {
-            pipeline: self,
-            next_input,
-        }
+        PipelinePathBuilder::new(self, next_input)
+    }
+
+    /// Starts a path from a channel the caller already owns, e.g. the output of a source
+    /// task spawned with [`Self::spawn_source`], or of another path.
+    pub fn path<O: Send + 'static>(
+        self,
+        input: impl Into<SingleConsumer<O>>,
+    ) -> PipelinePathBuilder<T, O> {
+        PipelinePathBuilder::new(self, input.into().into_inner())
     }
 
     pub fn spawn_source<C: CloneFrom<T> + Send + 'static>(
@@ -64,137 +646,2754 @@ impl<T> PipelineBuilder<T> {
     ) {
         let name = name.into();
         let clock = C::clone_from(&self.clock);
-        let control_signal = self.control.add_listener(name.clone());
+        let control_signal = self.add_listener(name.clone(), task.acks_control_messages());
+        let context = self.context.clone();
+        let (end, finished) = SourceEnd::new(name.clone());
+        self.source_ends.entry(name.clone()).or_insert(end);
+
+        let options = LaunchOptions {
+            capabilities: task.required_capabilities(),
+            ..Default::default()
+        };
+
+        self.launch_task(name, options, move |ready_signal| {
+            task.run_with_context(clock, ready_signal, control_signal, context);
+            finished.set();
+            Ok(())
+        });
+    }
+
+    /// Spawns a source whose items the sink acknowledges once it has written them, for
+    /// at-least-once delivery, see [`ack`](super::ack). The source is first resumed with
+    /// [`Resumable::resume_from`] at the sequence after `last_acked`, which the sink has to
+    /// keep across restarts, or at zero without one. `make` is given the source's output,
+    /// which holds on to up to `unacked_capacity` items until they're acknowledged. Returns
+    /// the sink's input, an edge named after the source, and the sender for its acks.
+    pub fn spawn_resumable_source<O, C, S>(
+        &mut self,
+        name: impl Into<String>,
+        last_acked: Option<Sequence>,
+        unacked_capacity: usize,
+        make: impl FnOnce(AckedOutput<O>) -> S,
+    ) -> (Receiver<Sequenced<O>>, AckSender)
+    where
+        O: Clone + Send + 'static,
+        C: CloneFrom<T> + Send + 'static,
+        S: PipelineSourceTask<Clock = C> + Resumable + 'static,
+    {
+        let name = name.into();
+        if unacked_capacity == 0 {
+            self.problems
+                .push(BuildProblem::ZeroQueueSize(name.clone()));
+        }
+
+        let start = last_acked.map_or(0, |sequence| sequence + 1);
+        let (output, input) = self.task_edge(&name, name.clone(), unacked_capacity);
+        let (ack_sender, acks) = ack_channel();
+        let buffer = UnackedBuffer::starting_at(start, unacked_capacity);
+        let source = make(AckedOutput::new(output, buffer, acks));
+
+        self.spawn_source(name, ResumingSource { source, start });
+
+        (input, ack_sender)
+    }
+
+    /// Lets a sink tell whether its input from the source task `source` ended because the
+    /// source finished or because it crashed, see [`SourceEnd`]. Only available for
+    /// sources added with [`Self::spawn_source`].
+    pub fn source_end(&self, source: &str) -> Option<SourceEnd> {
+        self.source_ends.get(source).cloned()
+    }
+
+    /// Adds a task that drives a [`StreamSource`]. An error yielded by the stream ends the
+    /// task and is reported as its result.
+    pub fn spawn_stream_source<S, O, E>(
+        &mut self,
+        name: impl Into<String>,
+        source: StreamSource<S, O>,
+    ) where
+        S: Stream<Item = Result<O, E>> + Send + 'static,
+        O: Send + 'static,
+        E: Display,
+    {
+        let name = name.into();
+        // Streams are driven without ever reading the control messages.
+        let control_signal = self.add_listener(name.clone(), false);
 
         self.spawn_task(name, move |ready_signal| {
-            task.run(clock, ready_signal, control_signal);
+            source.run(ready_signal, control_signal)
+        });
+    }
+
+    /// Like [`Self::spawn_source`], but only launches the task once something reads from
+    /// the returned receiver, which wraps the `output` the task sends into. This suits
+    /// optional, expensive branches of a large preconfigured pipeline.
+    ///
+    /// A lazy task counts as ready for `build` as soon as it is able to start, without
+    /// having started. Once launched, the task's own ready signal is no longer awaited, so
+    /// a failure to start only shows up as the task finishing. If the pipeline shuts down
+    /// before anything reads from the output, the task is never launched.
+    pub fn spawn_lazy_source<O, C: CloneFrom<T> + Send + 'static>(
+        &mut self,
+        name: impl Into<String>,
+        mut task: impl PipelineSourceTask<Clock = C> + 'static,
+        output: Receiver<O>,
+    ) -> LazyReceiver<O> {
+        let name = name.into();
+        let clock = C::clone_from(&self.clock);
+        let mut control_signal = self.add_listener(name.clone(), task.acks_control_messages());
+        let context = self.context.clone();
+        let (output, demand) = LazyReceiver::new(output);
+
+        let options = LaunchOptions {
+            capabilities: task.required_capabilities(),
+            ..Default::default()
+        };
+
+        self.launch_task(name, options, move |ready_signal| {
+            let _ = ready_signal.send(Ok(()));
+
+            if !control_signal.wait_for_demand(&demand) {
+                return Ok(());
+            }
+
+            // Kept alive so tasks that unwrap their ready signal don't panic.
+            let (ready_signal, _ready) = flume::unbounded();
+            task.run_with_context(clock, ready_signal, control_signal, context);
+            Ok(())
+        });
+
+        output
+    }
+
+    /// Like [`Self::spawn_source`], but binds the task's thread to the CPU core with the
+    /// given id, which helps real-time capture avoid scheduler-induced glitches.
+    ///
+    /// Pinning is best-effort: if the core doesn't exist or the OS refuses, a warning is
+    /// logged and the task runs unpinned. Pinning too many tasks can hurt overall
+    /// throughput, so reserve it for the few tasks that are genuinely latency-sensitive.
+    pub fn spawn_source_pinned<C: CloneFrom<T> + Send + 'static>(
+        &mut self,
+        name: impl Into<String>,
+        mut task: impl PipelineSourceTask<Clock = C> + 'static,
+        core_id: usize,
+    ) {
+        let name = name.into();
+        let clock = C::clone_from(&self.clock);
+        let control_signal = self.add_listener(name.clone(), task.acks_control_messages());
+        let context = self.context.clone();
+
+        let options = LaunchOptions {
+            core_id: Some(core_id),
+            capabilities: task.required_capabilities(),
+            ..Default::default()
+        };
+
+        self.launch_task(name, options, move |ready_signal| {
+            task.run_with_context(clock, ready_signal, control_signal, context);
+            Ok(())
+        });
+    }
+
+    /// Like [`Self::spawn_source`], but with the given start priority, e.g. so that a
+    /// recorder's preview can show as soon as the screen capture is ready while the
+    /// microphone is still starting up. Pair it with [`Self::with_lifecycle_observer`] to
+    /// react to the low-priority task becoming ready. No task can depend on a task with a
+    /// low start priority.
+    pub fn spawn_source_with_priority<C: CloneFrom<T> + Send + 'static>(
+        &mut self,
+        name: impl Into<String>,
+        mut task: impl PipelineSourceTask<Clock = C> + 'static,
+        priority: StartPriority,
+    ) {
+        let name = name.into();
+        let clock = C::clone_from(&self.clock);
+        let control_signal = self.add_listener(name.clone(), task.acks_control_messages());
+        let context = self.context.clone();
+
+        let options = LaunchOptions {
+            capabilities: task.required_capabilities(),
+            priority,
+            ..Default::default()
+        };
+
+        self.launch_task(name, options, move |ready_signal| {
+            task.run_with_context(clock, ready_signal, control_signal, context);
             Ok(())
         });
     }
 
+    /// Like [`Self::spawn_source`], but for a source that can use one of several devices,
+    /// e.g. the preferred microphone, falling back to the default one if it's busy. The
+    /// candidates are tried in order: each is created and run until it signals ready, and
+    /// if it can't be created or fails to become ready, the next one is tried instead.
+    /// `build` only fails if none of them start, with [`MediaError::NoCandidateStarted`]
+    /// listing why each failed. The candidate that started can be looked up with
+    /// [`Pipeline::chosen_candidate`], e.g. to show which device is recording.
+    ///
+    /// Each candidate gets an equal share of the [ready timeout](Self::with_ready_timeout),
+    /// so one that hangs while starting is asked to shut down and left behind in time for
+    /// the others to be tried. The candidates run on threads of their own and the task
+    /// relays its control signal to the one that started, so it's controlled under `name`
+    /// like any other source. Devices a candidate holds are reported under
+    /// `{name}/{candidate}`, and released when that candidate stops.
+    pub fn spawn_source_with_fallback<S, C>(
+        &mut self,
+        name: impl Into<String>,
+        candidates: Vec<(String, SourceFactory<S>)>,
+    ) where
+        S: PipelineSourceTask<Clock = C> + 'static,
+        C: CloneFrom<T> + Send + 'static,
+    {
+        let name = name.into();
+        let context = self.context.clone();
+        let devices = self.devices.clone();
+        let chosen = ChosenCandidate::default();
+        self.chosen_candidates.insert(name.clone(), chosen.clone());
+        // The candidate is only created once it's tried, so it can't be asked whether it
+        // acks its messages.
+        let mut control = self.add_listener(name.clone(), false);
+        let candidate_timeout = self.ready_timeout / candidates.len().max(1) as u32;
+
+        let candidates = candidates
+            .into_iter()
+            .map(|(candidate, factory)| (candidate, factory, C::clone_from(&self.clock)))
+            .collect::<Vec<_>>();
+
+        self.launch_task(
+            name.clone(),
+            LaunchOptions::default(),
+            move |ready_signal| {
+                let dispatcher = tracing::dispatcher::get_default(|d| d.clone());
+                let span = tracing::Span::current();
+                let mut errors = vec![];
+
+                for (index, (candidate, factory, clock)) in candidates.into_iter().enumerate() {
+                    let (relay, control_signal) = ControlRelay::new();
+                    let (candidate_ready, candidate_ready_rx) = flume::bounded(1);
+                    // Disconnects once the candidate has stopped.
+                    let (running, stopped) = flume::bounded::<()>(0);
+
+                    let thread = thread::spawn({
+                        let (dispatcher, span) = (dispatcher.clone(), span.clone());
+                        let devices = devices.clone();
+                        let context = context.clone();
+                        let task = format!("{name}/{candidate}");
+
+                        move || {
+                            let _running = running;
+                            tracing::dispatcher::with_default(&dispatcher, || {
+                                span.in_scope(|| {
+                                    let _devices = TaskDeviceScope::enter(task, devices);
+
+                                    match factory() {
+                                        Ok(mut source) => source.run_with_context(
+                                            clock,
+                                            candidate_ready,
+                                            control_signal,
+                                            context,
+                                        ),
+                                        Err(error) => {
+                                            let _ = candidate_ready.send(Err(error));
+                                        }
+                                    }
+                                })
+                            })
+                        }
+                    });
+
+                    let error = match candidate_ready_rx.recv_timeout(candidate_timeout) {
+                        Ok(Ok(())) => {
+                            let _ = chosen.0.set((index, candidate.clone()));
+                            let _ = ready_signal.send(Ok(()));
+                            relay.run(&mut control, &stopped);
+
+                            return thread
+                                .join()
+                                .map_err(|_| format!("candidate '{candidate}' panicked"));
+                        }
+                        Ok(Err(error)) => error.to_string(),
+                        Err(flume::RecvTimeoutError::Timeout) => {
+                            // Left to stop in its own time, so it can't hold up the others.
+                            relay.shutdown();
+                            errors.push(format!(
+                                "{candidate}: not ready within {candidate_timeout:?}"
+                            ));
+                            continue;
+                        }
+                        Err(flume::RecvTimeoutError::Disconnected) => {
+                            "exited before signalling ready".to_string()
+                        }
+                    };
+
+                    // Whatever the failed candidate holds is released before the next one
+                    // tries to open it.
+                    drop(relay);
+                    let error = match thread.join() {
+                        Ok(()) => error,
+                        Err(_) => "panicked".to_string(),
+                    };
+                    errors.push(format!("{candidate}: {error}"));
+                }
+
+                let _ =
+                    ready_signal.send(Err(MediaError::NoCandidateStarted { task: name, errors }));
+                Ok(())
+            },
+        );
+    }
+
     pub fn spawn_task(
         &mut self,
         name: impl Into<String>,
         launch: impl FnOnce(PipelineReadySignal) -> Result<(), String> + Send + 'static,
+    ) {
+        self.launch_task(name, LaunchOptions::default(), launch);
+    }
+
+    /// Like [`Self::spawn_task`], but only launches the task once all of `dependencies`
+    /// have signalled ready, e.g. for an encoder that needs the format its source
+    /// negotiated. If a dependency fails to become ready, the task is never launched and
+    /// `build` fails with [`MediaError::DependencyNotReady`].
+    ///
+    /// Dependencies have to be added to the builder before their dependents, or `build` fails
+    /// with [`MediaError::UnknownDependency`].
+    pub fn spawn_dependent_task(
+        &mut self,
+        name: impl Into<String>,
+        dependencies: impl IntoIterator<Item = impl Into<String>>,
+        launch: impl FnOnce(PipelineReadySignal) -> Result<(), String> + Send + 'static,
+    ) {
+        let options = LaunchOptions {
+            dependencies: dependencies.into_iter().map(Into::into).collect(),
+            ..Default::default()
+        };
+
+        self.launch_task(name, options, launch);
+    }
+
+    fn start_priority(&self, task: &str) -> Option<StartPriority> {
+        match self.tasks.get(task) {
+            Some(task) => Some(task.priority),
+            None => self.rejected.get(task).copied(),
+        }
+    }
+
+    pub(super) fn launch_task(
+        &mut self,
+        name: impl Into<String>,
+        options: LaunchOptions,
+        launch: impl FnOnce(PipelineReadySignal) -> Result<(), String> + Send + 'static,
     ) {
         let name = name.into();
+        let LaunchOptions {
+            core_id,
+            dependencies,
+            capabilities,
+            priority,
+        } = options;
+
+        if self.start_priority(&name).is_some() {
+            self.problems.push(BuildProblem::DuplicateTask(name));
+            return;
+        }
+
+        for capability in capabilities
+            .iter()
+            .filter(|capability| !self.capabilities.contains(**capability))
+        {
+            self.problems.push(BuildProblem::MissingCapability {
+                task: name.clone(),
+                capability: capability.to_string(),
+            });
+        }
+
+        for dependency in &dependencies {
+            let problem = match self.start_priority(dependency) {
+                Some(StartPriority::Normal) => continue,
+                Some(StartPriority::Low) => BuildProblem::LowPriorityDependency {
+                    task: name.clone(),
+                    dependency: dependency.clone(),
+                },
+                None => BuildProblem::UnknownDependency {
+                    task: name.clone(),
+                    dependency: dependency.clone(),
+                },
+            };
+            self.problems.push(problem);
+        }
 
-        if self.tasks.contains_key(&name) {
-            panic!("A task with the name {name} has already been added to the pipeline");
+        // The build is going to fail, so don't start anything else.
+        if !self.problems.is_empty() {
+            self.rejected.insert(name, priority);
+            return;
         }
 
-        let (ready_sender, ready_signal) = flume::bounded(1);
+        let (ready_sender, ready_signal) = flume::bounded(self.ready_capacity);
 
         let dispatcher = tracing::dispatcher::get_default(|d| d.clone());
         let span = tracing::error_span!("pipeline", task = &name);
 
         let (done_tx, done_rx) = tokio::sync::oneshot::channel::<Result<(), String>>();
 
-        let join_handle = thread::spawn({
+        let propagate_panics = self.propagate_panics;
+        record_panics();
+        let devices = self.devices.clone();
+        let log_dir = self.log_dir.clone();
+
+        self.outcomes.register(name.clone());
+        let lifecycle = self.lifecycle.clone();
+        let report = {
+            let name = name.clone();
+            let outcomes = self.outcomes.clone();
+            let completion = self.completion.clone();
+            let lifecycle = lifecycle.clone();
+
+            move |result: Result<(), String>| {
+                let reason = outcomes.record(&name, &result, &completion);
+                let _ = done_tx.send(result);
+                lifecycle.emit(LifecycleEvent::TaskStopped { task: name, reason });
+            }
+        };
+
+        let body = {
             let name = name.clone();
             move || {
+                let dispatcher = match log_dir {
+                    Some(dir) => task_dispatcher(&dir, &name, dispatcher),
+                    None => dispatcher,
+                };
+
                 tracing::dispatcher::with_default(&dispatcher, || {
-                    let result = span
-                        .in_scope(|| {
-                            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-                                info!("launching task '{name}'");
-                                let res = launch(ready_sender);
-                                info!("task '{name}' done");
-                                res
-                            }))
-                            .map_err(|e| {
-                                if let Some(s) = e.downcast_ref::<&'static str>() {
-                                    format!("Panicked: {s}")
-                                } else if let Some(s) = e.downcast_ref::<String>() {
-                                    format!("Panicked: {s}")
-                                } else {
-                                    format!("Panicked: Unknown error")
-                                }
+                    span.in_scope(|| {
+                        let run = || {
+                            // Pooled threads run other tasks first, whose panics were caught.
+                            take_panic_detail();
+                            // Dropped before the result is reported, including when unwinding.
+                            let _devices = TaskDeviceScope::enter(name.clone(), devices);
+
+                            if let Some(core_id) = core_id {
+                                pin_current_thread(core_id);
+                            }
+
+                            info!("launching task '{name}'");
+                            lifecycle.emit(LifecycleEvent::TaskLaunched { task: name.clone() });
+                            let res = launch(ready_sender);
+                            info!("task '{name}' done");
+                            res
+                        };
+
+                        if propagate_panics {
+                            let reporter = PanicReporter(Some(report));
+                            reporter.send(run());
+                            return;
+                        }
+
+                        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(run))
+                            .map_err(|e| match take_panic_detail() {
+                                Some(detail) => panic_error(&detail),
+                                None => panic_error(panic_message(e.as_ref())),
                             })
-                        })
-                        .and_then(|v| v);
-                    let _ = done_tx.send(result);
+                            .and_then(|v| v);
+                        report(result);
+                    })
                 });
             }
-        });
+        };
+
+        // Pinning applies to the whole thread, so pinned tasks don't share one.
+        let pool = self.pool.clone().filter(|_| core_id.is_none());
+        let spawn = move || match pool {
+            Some(pool) => pool.spawn(body),
+            None => TaskHandle::Thread(thread::spawn(body)),
+        };
+
+        let thread = if dependencies.is_empty() && priority == StartPriority::Normal {
+            TaskThread::Running(spawn())
+        } else {
+            TaskThread::Deferred {
+                dependencies,
+                spawn: Box::new(spawn),
+            }
+        };
 
         self.tasks.insert(
             name,
             Task {
                 ready_signal,
-                join_handle,
+                thread,
                 done_rx,
+                priority,
             },
         );
     }
 }
 
-impl<T: PipelineClock> PipelineBuilder<T> {
-    pub async fn build(
-        self,
-    ) -> Result<(Pipeline<T>, oneshot::Receiver<Result<(), String>>), MediaError> {
-        let Self {
-            clock,
-            control,
-            tasks,
-        } = self;
+/// Reports a task's result, including when the task panics and the thread unwinds
+/// through this guard without the panic being caught.
+struct PanicReporter<F: FnOnce(Result<(), String>)>(Option<F>);
 
-        if tasks.is_empty() {
-            return Err(MediaError::EmptyPipeline);
+impl<F: FnOnce(Result<(), String>)> PanicReporter<F> {
+    fn send(mut self, result: Result<(), String>) {
+        if let Some(report) = self.0.take() {
+            report(result);
         }
+    }
+}
 
-        let mut task_handles = IndexMap::new();
-
-        let mut stop_rx = vec![];
-        let mut task_names = vec![];
-
-        // TODO: Shut down tasks if launch failed.
-        for (name, task) in tasks.into_iter() {
-            // TODO: Wait for these in parallel?
-            tokio::time::timeout(Duration::from_secs(5), task.ready_signal.recv_async())
-                .await
-                .map_err(|_| MediaError::TaskLaunch(format!("task timed out: '{name}'")))?
-                .map_err(|e| MediaError::TaskLaunch(format!("{name} build / {e}")))??;
-
-            task_handles.insert(name.clone(), task.join_handle);
-            stop_rx.push(task.done_rx);
-            task_names.push(name);
+impl<F: FnOnce(Result<(), String>)> Drop for PanicReporter<F> {
+    fn drop(&mut self) {
+        if let Some(report) = self.0.take() {
+            if thread::panicking() {
+                let detail = take_panic_detail()
+                    .unwrap_or_else(|| "see the panic output for details".to_string());
+                report(Err(panic_error(&detail)));
+            }
         }
+    }
+}
 
-        tokio::time::sleep(Duration::from_millis(10)).await;
+thread_local! {
+    /// The message and location of the last panic on this thread, recorded by the hook
+    /// [`record_panics`] installs.
+    static LAST_PANIC: RefCell<Option<String>> = const { RefCell::new(None) };
+}
 
-        let (done_tx, done_rx) = oneshot::channel();
+/// Installs a panic hook, once per process, that records the message and location of each
+/// panic on the thread it happened on. A [`PanicReporter`] only runs while unwinding, when
+/// the payload is out of its reach. The hook installed before is still called, so the
+/// panic output is unchanged.
+fn record_panics() {
+    static INSTALL: Once = Once::new();
 
-        tokio::spawn(async move {
-            let (result, index, _) = futures::future::select_all(stop_rx).await;
-            let task_name = &task_names[index];
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let message = panic_message(info.payload());
+            let detail = match info.location() {
+                Some(location) => format!("{message} at {location}"),
+                None => message.to_string(),
+            };
+            // Panicking again in here would abort, e.g. while the thread is torn down.
+            let _ = LAST_PANIC.try_with(|last| {
+                if let Ok(mut last) = last.try_borrow_mut() {
+                    *last = Some(detail);
+                }
+            });
+
+            previous(info);
+        }));
+    });
+}
+
+fn take_panic_detail() -> Option<String> {
+    LAST_PANIC.with(RefCell::take)
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&'static str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Unknown error"
+    }
+}
+
+/// The error a task that panicked fails with. A panic while holding a lock poisons it, and
+/// the other tasks sharing that state then fail in confusing ways, so this points that out.
+fn panic_error(detail: &str) -> String {
+    warn!(
+        "Task panicked, so any shared state it held a lock on is now poisoned. Tasks that \
+         share it can lock it with task::recover_poisoned to carry on"
+    );
+
+    format!("Panicked: {detail} (locks it held on shared state may be poisoned)")
+}
+
+fn pin_current_thread(core_id: usize) {
+    let Some(core) = core_affinity::get_core_ids()
+        .and_then(|cores| cores.into_iter().find(|core| core.id == core_id))
+    else {
+        warn!("CPU core {core_id} is not available, running unpinned");
+        return;
+    };
+
+    if !core_affinity::set_for_current(core) {
+        warn!("Failed to pin to CPU core {core_id}, running unpinned");
+    }
+}
+
+impl<T: PipelineClock> PipelineBuilder<T> {
+    /// Builds the pipeline on the current Tokio runtime, failing with
+    /// [`MediaError::NoRuntime`] outside of one. See [`Self::build_on`].
+    pub async fn build(
+        self,
+    ) -> Result<(Pipeline<T>, oneshot::Receiver<Result<(), String>>), MediaError> {
+        let runtime = Handle::try_current().map_err(|_| MediaError::NoRuntime)?;
+        self.build_on(runtime).await
+    }
+
+    /// Builds the pipeline on the given runtime, which runs the launch as well as the
+    /// pipeline's background work: the completion monitor, the watchdogs and the metrics
+    /// reporting. The returned future can be awaited from anywhere, so this suits apps
+    /// with several runtimes or a dedicated media runtime. The runtime has to outlive the
+    /// pipeline, as nothing reports its completion otherwise.
+    pub async fn build_on(
+        self,
+        runtime: Handle,
+    ) -> Result<(Pipeline<T>, oneshot::Receiver<Result<(), String>>), MediaError> {
+        self.spawn_build(runtime, false).await
+    }
+
+    /// Like [`Self::build`], but returns as soon as every task has signalled ready, for
+    /// latency-sensitive startup such as showing a window straight away. The settle delay
+    /// and the watchdog setup then happen in the background, and [`Pipeline::ready`]
+    /// resolves once they're done.
+    ///
+    /// The pipeline can be controlled straight away: control signals and messages sent
+    /// during the settle window are delivered to the tasks immediately, as they're already
+    /// listening. Only the watchdogs, such as the idle timeout, start late.
+    pub async fn build_fast(
+        self,
+    ) -> Result<(Pipeline<T>, oneshot::Receiver<Result<(), String>>), MediaError> {
+        let runtime = Handle::try_current().map_err(|_| MediaError::NoRuntime)?;
+        self.spawn_build(runtime, true).await
+    }
+
+    /// Like [`Self::build`], but fails with every mistake found by [`Self::validate_all`]
+    /// rather than just the first, so they can all be fixed at once. The pipeline is only
+    /// started if there are none.
+    pub async fn build_validated(
+        self,
+    ) -> Result<(Pipeline<T>, oneshot::Receiver<Result<(), String>>), Vec<MediaError>> {
+        let problems = self.validate_all().err().unwrap_or_default();
+
+        // With any problems, this shuts down the tasks launched before the first one.
+        self.build().await.map_err(|error| {
+            if problems.is_empty() {
+                vec![error]
+            } else {
+                problems
+            }
+        })
+    }
+
+    async fn spawn_build(
+        self,
+        runtime: Handle,
+        settle_in_background: bool,
+    ) -> Result<(Pipeline<T>, oneshot::Receiver<Result<(), String>>), MediaError> {
+        let build = self.build_in(runtime.clone(), settle_in_background);
+
+        match runtime.spawn(build).await {
+            Ok(built) => built,
+            Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+            Err(_) => Err(MediaError::NoRuntime),
+        }
+    }
+
+    async fn build_in(
+        self,
+        runtime: Handle,
+        settle_in_background: bool,
+    ) -> Result<(Pipeline<T>, oneshot::Receiver<Result<(), String>>), MediaError> {
+        let invalid = self.validate().err();
+        let Self {
+            clock,
+            mut control,
+            tasks,
+            ready_timeout,
+            build_deadline,
+            metrics,
+            idle_timeout,
+            no_data_grace,
+            rotation_interval,
+            flush_interval,
+            devices,
+            log_summary,
+            skew_warning,
+            max_duration,
+            completion,
+            outcomes,
+            lifecycle,
+            on_shutdown,
+            extensions,
+            chosen_candidates,
+            ..
+        } = self;
+
+        if let Some(error) = invalid {
+            let task_handles = tasks
+                .into_iter()
+                .filter_map(|(name, task)| match task.thread {
+                    TaskThread::Running(handle) => Some((name, handle)),
+                    TaskThread::Deferred { .. } => None,
+                })
+                .collect();
 
-            let result = match result {
-                Ok(Err(error)) => Err(format!("Task '{task_name}' failed: {error}")),
-                Err(_) => Err(format!("Task '{task_name}' failed for unknown reason")),
-                _ => Ok(()),
+            shutdown_after_failed_launch(&mut control, task_handles).await;
+            return Err(error);
+        }
+
+        if tasks.is_empty() {
+            return Err(MediaError::EmptyPipeline);
+        }
+
+        let mut task_handles = IndexMap::new();
+
+        let mut ready_signals = vec![];
+        let mut stop_rx = vec![];
+        let mut task_names = vec![];
+        let mut optional = vec![];
+        // (dependent, dependency) pairs.
+        let mut dependents = vec![];
+
+        for (name, task) in tasks.into_iter() {
+            let deferred = match task.thread {
+                TaskThread::Running(join_handle) => {
+                    task_handles.insert(name.clone(), join_handle);
+                    None
+                }
+                TaskThread::Deferred {
+                    dependencies,
+                    spawn,
+                } => {
+                    dependents.extend(dependencies.into_iter().map(|d| (name.clone(), d)));
+                    Some(spawn)
+                }
             };
 
-            if let Err(e) = &result {
-                error!("{e}");
+            ready_signals.push((name.clone(), task.ready_signal, deferred));
+            stop_rx.push(task.done_rx);
+            optional.push(task.priority == StartPriority::Low);
+            task_names.push(name);
+        }
+
+        let mut not_ready = task_names
+            .iter()
+            .zip(&optional)
+            .filter(|(_, optional)| !**optional)
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        let (settled_tx, settled) = tokio::sync::watch::channel(false);
+        let is_shutdown = Arc::new(AtomicBool::new(false));
+        let task_order = task_names.iter().cloned().collect::<IndexSet<_>>();
+
+        let launch = {
+            let not_ready = &mut not_ready;
+            let task_handles = &mut task_handles;
+            let clock = clock.clone();
+            let metrics = metrics.clone();
+            let control = control.clone();
+            let completion = completion.clone();
+            let lifecycle = lifecycle.clone();
+            let on_shutdown = on_shutdown.clone();
+            let outcomes = outcomes.clone();
+            let is_shutdown = is_shutdown.clone();
+
+            async move {
+                let mut low_priority = vec![];
+
+                // TODO: Wait for these in parallel?
+                for ((name, ready_signal, deferred), (done_rx, optional)) in ready_signals
+                    .into_iter()
+                    .zip(stop_rx.iter_mut().zip(&optional))
+                {
+                    if *optional {
+                        low_priority.push((name, ready_signal, deferred));
+                        continue;
+                    }
+
+                    // Dependencies are added before their dependents, so by the time a
+                    // deferred task comes up they have all signalled ready.
+                    if let Some(spawn) = deferred {
+                        task_handles.insert(name.clone(), spawn());
+                    }
+
+                    let ready = wait_until_ready(&name, ready_signal, done_rx, ready_timeout).await;
+
+                    if let Err(error) = ready {
+                        let dependent = dependents.iter().find(|(_, d)| d == &name);
+
+                        return Err(match dependent {
+                            Some((task, dependency)) => MediaError::DependencyNotReady {
+                                task: task.clone(),
+                                dependency: dependency.clone(),
+                                detail: error.to_string(),
+                            },
+                            None => error,
+                        });
+                    }
+
+                    not_ready.retain(|n| n != &name);
+                    lifecycle.emit(LifecycleEvent::TaskReady { task: name });
+                }
+
+                if !low_priority.is_empty() {
+                    let mut ready_signals = vec![];
+
+                    for (name, ready_signal, deferred) in low_priority {
+                        if let Some(spawn) = deferred {
+                            task_handles.insert(name.clone(), spawn());
+                        }
+                        ready_signals.push((name, ready_signal));
+                    }
+
+                    tokio::spawn(wait_until_optional_ready(
+                        ready_signals,
+                        ready_timeout,
+                        lifecycle.clone(),
+                    ));
+                }
+
+                let (stop_request_tx, stop_request_rx) = flume::bounded(1);
+                let done_rx = spawn_completion_monitor(
+                    stop_rx,
+                    task_names,
+                    optional,
+                    stop_request_rx,
+                    completion.clone(),
+                    on_shutdown,
+                    outcomes,
+                    is_shutdown,
+                );
+
+                let settle = async move {
+                    let delay = clock.settle_delay();
+                    if !delay.is_zero() {
+                        tokio::time::sleep(delay).await;
+                    }
+
+                    if let Some(threshold) = skew_warning {
+                        spawn_skew_monitor(clock.clone(), threshold, stop_request_tx.clone());
+                    }
+
+                    if let Some((limit, measure)) = max_duration {
+                        spawn_duration_limit(
+                            clock.clone(),
+                            control.clone(),
+                            limit,
+                            measure,
+                            completion.clone(),
+                            stop_request_tx.clone(),
+                        );
+                    }
+
+                    if let Some(grace) = no_data_grace {
+                        spawn_no_data_check(
+                            clock.clone(),
+                            metrics.clone(),
+                            grace,
+                            stop_request_tx.clone(),
+                        );
+                    }
+
+                    if let Some(idle_timeout) = idle_timeout {
+                        spawn_idle_watchdog(
+                            clock,
+                            metrics,
+                            control,
+                            idle_timeout,
+                            completion,
+                            stop_request_tx,
+                        );
+                    }
+
+                    let _ = settled_tx.send(true);
+                };
+
+                if settle_in_background {
+                    tokio::spawn(settle);
+                } else {
+                    settle.await;
+                }
+
+                Ok::<_, MediaError>(done_rx)
             }
+        };
 
-            let _ = done_tx.send(result);
-        });
+        let launched = match build_deadline {
+            Some(deadline) => tokio::time::timeout(deadline, launch).await.ok(),
+            None => Some(launch.await),
+        };
+
+        let done_rx = match launched {
+            Some(Ok(done_rx)) => done_rx,
+            Some(Err(error)) => {
+                shutdown_after_failed_launch(&mut control, task_handles).await;
+                return Err(error);
+            }
+            None => {
+                shutdown_after_failed_launch(&mut control, task_handles).await;
+                return Err(MediaError::BuildTimeout(not_ready));
+            }
+        };
+
+        // Deferred tasks are spawned later than the others, so restore the order the tasks
+        // were added in, which the pipeline relies on for shutdown and reporting.
+        task_handles
+            .sort_by(|a, _, b, _| task_order.get_index_of(a).cmp(&task_order.get_index_of(b)));
+
+        if let Some(interval) = rotation_interval {
+            spawn_periodic_message(control.clone(), ControlMessage::Rotate, interval);
+        }
+
+        if let Some(interval) = flush_interval {
+            spawn_periodic_message(control.clone(), ControlMessage::Flush, interval);
+        }
 
         Ok((
             Pipeline {
                 clock,
+                runtime,
                 control,
                 task_handles,
-                is_shutdown: false,
+                metrics,
+                devices,
+                completion,
+                outcomes,
+                settled,
+                lifecycle,
+                on_shutdown,
+                metrics_exporter: Default::default(),
+                background_tasks: Default::default(),
+                started_at: std::time::Instant::now(),
+                finished_at: None,
+                log_summary,
+                paused: None,
+                extensions,
+                chosen_candidates,
+                is_shutdown,
             },
             done_rx,
         ))
     }
 }
 
+/// Waits for a task's ready signal, also watching for the task finishing so one that exits
+/// without signalling ready fails the build straight away rather than at the timeout.
+async fn wait_until_ready(
+    name: &str,
+    ready_signal: Receiver<Result<(), MediaError>>,
+    done_rx: &mut oneshot::Receiver<Result<(), String>>,
+    timeout: Duration,
+) -> Result<(), MediaError> {
+    tokio::select! {
+        biased;
+        ready = tokio::time::timeout(timeout, ready_signal.recv_async()) => match ready {
+            Ok(Ok(ready)) => ready,
+            // The ready signal was dropped, so the task is on its way out.
+            Ok(Err(_)) => match tokio::time::timeout(timeout, done_rx).await {
+                Ok(done) => Err(exited_before_ready(name, done)),
+                Err(_) => Err(MediaError::TaskLaunch(format!(
+                    "{name} dropped its ready signal"
+                ))),
+            },
+            Err(_) => Err(MediaError::TaskLaunch(format!("task timed out: '{name}'"))),
+        },
+        done = &mut *done_rx => Err(exited_before_ready(name, done)),
+    }
+}
+
+/// Waits for the tasks with a low start priority to become ready in the background. As
+/// they are optional, the ones that don't are only warned about.
+async fn wait_until_optional_ready(
+    tasks: Vec<(String, Receiver<Result<(), MediaError>>)>,
+    timeout: Duration,
+    lifecycle: LifecycleEvents,
+) {
+    for (name, ready_signal) in tasks {
+        match tokio::time::timeout(timeout, ready_signal.recv_async()).await {
+            Ok(Ok(Ok(()))) => lifecycle.emit(LifecycleEvent::TaskReady { task: name }),
+            Ok(Ok(Err(error))) => warn!("Optional task '{name}' failed to start: {error}"),
+            Ok(Err(_)) => warn!("Optional task '{name}' exited before signalling ready"),
+            Err(_) => warn!("Optional task '{name}' timed out becoming ready"),
+        }
+    }
+}
+
+fn exited_before_ready(
+    task: &str,
+    done: Result<Result<(), String>, oneshot::error::RecvError>,
+) -> MediaError {
+    let detail = match done {
+        Ok(Ok(())) => "returned without signalling ready".to_string(),
+        Ok(Err(error)) => error,
+        Err(_) => "exited for unknown reason".to_string(),
+    };
+
+    MediaError::TaskExitedBeforeReady {
+        task: task.to_string(),
+        detail,
+    }
+}
+
+/// Resolves with the result of the first task to finish, other than the optional ones, or
+/// with the first stop request sent by one of the pipeline's watchdogs. Then waits for the
+/// remaining tasks to finish to flag the pipeline as shut down and run the shutdown hook,
+/// if there is one.
+///
+/// The monitor runs on a thread of its own rather than on the runtime, so the tasks stay
+/// supervised and `done_rx` still resolves if the runtime shuts down before the pipeline.
+fn spawn_completion_monitor(
+    stop_rx: Vec<oneshot::Receiver<Result<(), String>>>,
+    task_names: Vec<String>,
+    optional: Vec<bool>,
+    stop_requests: Receiver<MediaError>,
+    completion: Completion,
+    on_shutdown: ShutdownHook,
+    outcomes: TaskOutcomes,
+    is_shutdown: Arc<AtomicBool>,
+) -> oneshot::Receiver<Result<(), String>> {
+    let (done_tx, done_rx) = oneshot::channel();
+
+    let monitor = async move {
+        let mut finished = stop_rx
+            .into_iter()
+            .enumerate()
+            .map(|(index, done_rx)| async move { (done_rx.await, index) })
+            .collect::<FuturesUnordered<_>>();
+
+        let result = loop {
+            tokio::select! {
+                Some((result, index)) = finished.next() => {
+                    // Optional tasks stopping, even with an error, doesn't stop the pipeline.
+                    if optional[index] {
+                        continue;
+                    }
+
+                    let task_name = &task_names[index];
+
+                    let result = match result {
+                        Ok(Err(error)) => Err(format!("Task '{task_name}' failed: {error}")),
+                        Err(_) => Err(format!("Task '{task_name}' failed for unknown reason")),
+                        _ => Ok(()),
+                    };
+
+                    completion.record(CompletionReason::TaskFinished {
+                        task: task_name.clone(),
+                        error: result.clone().err(),
+                    });
+
+                    break result;
+                }
+                // Only resolves if a watchdog asks for a stop; once every watchdog
+                // is gone the branch is disabled rather than completing.
+                Ok(reason) = stop_requests.recv_async() => break Err(reason.to_string()),
+                // Only optional tasks were left, and they have all stopped.
+                else => break Ok(()),
+            }
+        };
+
+        if let Err(e) = &result {
+            error!("{e}");
+        }
+
+        let _ = done_tx.send(result);
+
+        while finished.next().await.is_some() {}
+        is_shutdown.store(true, Ordering::Release);
+        on_shutdown.run(&outcomes);
+    };
+    thread::spawn(move || futures::executor::block_on(monitor));
+
+    done_rx
+}
+
+async fn shutdown_after_failed_launch(
+    control: &mut ControlBroadcast,
+    task_handles: IndexMap<String, TaskHandle>,
+) {
+    trace!("Launch failed, shutting down launched tasks");
+    control.broadcast(Control::Shutdown).await;
+    // Tasks that never became ready may never observe the shutdown signal,
+    // so detach them instead of blocking the failed build on a join.
+    drop(task_handles);
+}
+
 pub struct PipelinePathBuilder<Clock, PreviousOutput: Send> {
-    pipeline: PipelineBuilder<Clock>,
-    next_input: Receiver<PreviousOutput>,
+    pub(super) pipeline: PipelineBuilder<Clock>,
+    pub(super) next_input: Receiver<PreviousOutput>,
+    /// Applied to the next stage added to the path, then reset.
+    pub(super) queue_size: usize,
+    pub(super) backpressure: Backpressure,
+    pub(super) item_time: Duration,
+    /// Unlike the above, these carry over to every following stage.
+    pub(super) mode: PathMode,
+    /// The worst-case latency estimate up to the end of the path so far, see
+    /// [`PipelineBuilder::with_max_latency`].
+    pub(super) latency: Duration,
+}
+
+impl<Clock, PreviousOutput: Send> PipelinePathBuilder<Clock, PreviousOutput> {
+    pub(super) fn new(
+        pipeline: PipelineBuilder<Clock>,
+        next_input: Receiver<PreviousOutput>,
+    ) -> Self {
+        Self {
+            pipeline,
+            next_input,
+            queue_size: DEFAULT_QUEUE_SIZE,
+            backpressure: Backpressure::default(),
+            item_time: Duration::ZERO,
+            mode: PathMode::default(),
+            latency: Duration::ZERO,
+        }
+    }
+
+    /// Sets the capacity of the next stage's output edge.
+    pub fn with_queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size.max(1);
+        self
+    }
+
+    /// Sets how the next stage reacts to its output being full. With
+    /// [`Backpressure::Independent`], items are dropped and counted instead of waiting.
+    pub fn with_backpressure(mut self, backpressure: Backpressure) -> Self {
+        self.backpressure = backpressure;
+        self
+    }
+
+    /// Sets how long the next stage is expected to take to process an item, for the latency
+    /// estimate checked against [`PipelineBuilder::with_max_latency`]. Stages without one
+    /// don't add to the estimate.
+    pub fn with_item_time(mut self, item_time: Duration) -> Self {
+        self.item_time = item_time;
+        self
+    }
+
+    /// Has the items still queued on the edge the previous stage outputs to handed back if
+    /// a graceful shutdown stalls, see [`Pipeline::shutdown_graceful_with_leftovers`]. This
+    /// is opt-in per edge, as getting at the items means keeping a typed handle on each.
+    /// Does nothing for a path started from a receiver that isn't a metered edge.
+    pub fn recover_leftovers(self) -> Self
+    where
+        PreviousOutput: 'static,
+    {
+        self.pipeline.metrics.recover_leftovers(&self.next_input);
+        self
+    }
+
+    /// Ends the path for now, handing back the builder, which unlike the path doesn't carry
+    /// the type of the path's items and so is easier to pass around. The path can be
+    /// carried on with [`PipelineBuilder::reopen`] under the name `endpoint`. Until it is,
+    /// the last stage's items queue up, and if it never is they're dropped.
+    pub fn seal(self, endpoint: impl Into<String>) -> PipelineBuilder<Clock>
+    where
+        PreviousOutput: 'static,
+    {
+        let Self {
+            mut pipeline,
+            next_input,
+            mode,
+            latency,
+            ..
+        } = self;
+        let name = endpoint.into();
+
+        if pipeline.endpoints.contains_key(&name) {
+            pipeline
+                .problems
+                .push(BuildProblem::DuplicateEndpoint(name));
+            return pipeline;
+        }
+
+        pipeline.endpoints.insert(
+            name,
+            Endpoint {
+                receiver: Box::new(next_input),
+                item_type: std::any::type_name::<PreviousOutput>(),
+                mode,
+                latency,
+            },
+        );
+        pipeline
+    }
+
+    /// Ends the path, handing back the builder along with the path's output.
+    pub fn into_receiver(self) -> (PipelineBuilder<Clock>, SingleConsumer<PreviousOutput>) {
+        (self.pipeline, SingleConsumer::new(self.next_input))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
+    use super::*;
+    use crate::pipeline::{
+        clock::CloneInto,
+        completion::TaskOutcome,
+        control::PauseMode,
+        task::{recover_poisoned, FnSink},
+        testing::assert_pipeline_ok,
+        RealTimeClock,
+    };
+
+    #[tokio::test]
+    async fn dependents_of_a_failed_task_never_start() {
+        let started = Arc::new([AtomicBool::new(false), AtomicBool::new(false)]);
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+
+        builder.spawn_task("a", |ready| {
+            let _ = ready.send(Err(MediaError::TaskLaunch("no device".into())));
+            Err("no device".into())
+        });
+
+        for (index, (name, dependency)) in [("b", "a"), ("c", "b")].into_iter().enumerate() {
+            let started = started.clone();
+
+            builder.spawn_dependent_task(name, [dependency], move |ready| {
+                started[index].store(true, Ordering::SeqCst);
+                let _ = ready.send(Ok(()));
+                Ok(())
+            });
+        }
+
+        let error = builder.build().await.err().unwrap();
+
+        assert!(matches!(
+            error,
+            MediaError::DependencyNotReady { ref task, ref dependency, ref detail }
+                if task == "b" && dependency == "a" && detail.contains("no device")
+        ));
+        assert!(!started[0].load(Ordering::SeqCst));
+        assert!(!started[1].load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn task_exiting_before_ready_fails_fast() {
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        builder.spawn_task("quitter", |_ready| Ok(()));
+
+        let started = std::time::Instant::now();
+        let error = builder.build().await.err().unwrap();
+
+        assert!(matches!(
+            error,
+            MediaError::TaskExitedBeforeReady { ref task, .. } if task == "quitter"
+        ));
+        assert!(started.elapsed() < DEFAULT_READY_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn build_deadline_names_the_tasks_that_never_became_ready() {
+        let (hold_tx, hold_rx) = flume::bounded::<()>(1);
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new())
+            .with_ready_timeout(Duration::from_secs(60))
+            .with_build_deadline(Duration::from_millis(100));
+        builder.spawn_task("screen", |ready| {
+            let _ = ready.send(Ok(()));
+            Ok(())
+        });
+        builder.spawn_task("camera", move |_ready| {
+            // Holds on to its ready signal without ever sending it.
+            let _ = hold_rx.recv();
+            Ok(())
+        });
+
+        let error = builder.build().await.err().unwrap();
+        drop(hold_tx);
+
+        assert!(matches!(
+            error,
+            MediaError::BuildTimeout(ref not_ready) if not_ready == &["camera"]
+        ));
+    }
+
+    #[tokio::test]
+    async fn abandoned_task_does_not_hold_up_shutdown() {
+        // Stands in for a native call that ignores the shutdown signal.
+        let (release_tx, release_rx) = flume::bounded::<()>(1);
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        builder.spawn_task("wedged", move |ready| {
+            let _ = ready.send(Ok(()));
+            let _ = release_rx.recv();
+            Ok(())
+        });
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        pipeline.abandon_task("wedged").unwrap();
+        assert_eq!(pipeline.task_names().count(), 0);
+        assert!(matches!(
+            pipeline.abandon_task("wedged"),
+            Err(MediaError::UnknownTask(task)) if task == "wedged"
+        ));
+
+        tokio::time::timeout(Duration::from_secs(1), pipeline.shutdown())
+            .await
+            .unwrap()
+            .unwrap();
+        drop(release_tx);
+    }
+
+    #[tokio::test]
+    async fn tasks_keep_insertion_order() {
+        let (hold_tx, hold_rx) = flume::bounded::<()>(1);
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        let names = ["d", "b", "a", "c"];
+
+        for name in names {
+            let hold_rx = hold_rx.clone();
+            let launch = move |ready: PipelineReadySignal| {
+                let _ = ready.send(Ok(()));
+                let _ = hold_rx.recv();
+                Ok(())
+            };
+
+            // Deferred tasks are spawned late, which mustn't change their position.
+            if name == "b" {
+                builder.spawn_dependent_task(name, ["d"], launch);
+            } else {
+                builder.spawn_task(name, launch);
+            }
+        }
+
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+        assert_eq!(pipeline.task_names().collect::<Vec<_>>(), names);
+
+        drop(hold_tx);
+        pipeline.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn shutdown_report_has_each_tasks_outcome() {
+        let (failed_tx, failed_rx) = flume::bounded::<()>(1);
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+
+        builder.spawn_task("screen", |ready| {
+            let _ = ready.send(Ok(()));
+            Ok(())
+        });
+        builder.spawn_task("microphone", move |ready| {
+            let _ = ready.send(Ok(()));
+            let _ = failed_rx.recv();
+            Err("device disconnected".into())
+        });
+
+        let (mut pipeline, done_rx) = builder.build().await.unwrap();
+        assert!(done_rx.await.unwrap().is_ok());
+
+        drop(failed_tx);
+        pipeline.shutdown().await.unwrap();
+
+        let report = pipeline.shutdown_report();
+        assert_eq!(report.tasks["screen"], TaskOutcome::Finished);
+        assert_eq!(
+            report.tasks["microphone"],
+            TaskOutcome::Failed("device disconnected".into())
+        );
+        assert_eq!(
+            report.to_string(),
+            "screen: ok, microphone: failed (device disconnected)"
+        );
+        assert_eq!(
+            report.result(),
+            Err("Task 'microphone' failed: device disconnected".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn shutdown_hook_runs_once_when_tasks_stop_on_their_own() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let (ran_tx, ran_rx) = flume::bounded(1);
+
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new()).with_on_shutdown({
+            let runs = runs.clone();
+            move |report| {
+                runs.fetch_add(1, Ordering::SeqCst);
+                let _ = ran_tx.send(report.is_ok());
+            }
+        });
+        builder.spawn_task("screen", |ready| {
+            let _ = ready.send(Ok(()));
+            Ok(())
+        });
+
+        let (mut pipeline, done_rx) = builder.build().await.unwrap();
+        assert!(done_rx.await.unwrap().is_ok());
+        assert!(ran_rx.recv_async().await.unwrap());
+
+        pipeline.shutdown().await.unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn is_shutdown_flips_once_the_tasks_complete() {
+        let (stop_tx, stop_rx) = flume::bounded::<()>(1);
+        let (stopped_tx, stopped_rx) = flume::bounded(1);
+
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new())
+            .with_on_shutdown(move |_| drop(stopped_tx.send(())));
+        builder.spawn_task("screen", move |ready| {
+            let _ = ready.send(Ok(()));
+            let _ = stop_rx.recv();
+            Ok(())
+        });
+
+        let (mut pipeline, done_rx) = builder.build().await.unwrap();
+        assert!(!pipeline.is_shutdown());
+
+        drop(stop_tx);
+        assert!(done_rx.await.unwrap().is_ok());
+        // The hook runs right after the flag is set.
+        stopped_rx.recv_async().await.unwrap();
+
+        assert!(pipeline.is_shutdown());
+        assert!(matches!(
+            pipeline.play().await,
+            Err(MediaError::ShutdownPipeline)
+        ));
+        pipeline.shutdown().await.unwrap();
+    }
+
+    #[test]
+    fn pipeline_stays_supervised_after_its_runtime_shuts_down() {
+        let (stop_tx, stop_rx) = flume::bounded::<()>(1);
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+
+        builder.spawn_task("screen", move |ready| {
+            let _ = ready.send(Ok(()));
+            let _ = stop_rx.recv();
+            Err("device disconnected".into())
+        });
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (_pipeline, done_rx) = runtime.block_on(builder.build()).unwrap();
+        drop(runtime);
+
+        drop(stop_tx);
+        assert_eq!(
+            futures::executor::block_on(done_rx).unwrap(),
+            Err("Task 'screen' failed: device disconnected".into())
+        );
+    }
+
+    struct Suspendable(MeteredSender<usize>);
+
+    impl PipelineSourceTask for Suspendable {
+        type Clock = RealTimeClock<()>;
+
+        fn acks_control_messages(&self) -> bool {
+            true
+        }
+
+        fn run(
+            &mut self,
+            _: Self::Clock,
+            ready: PipelineReadySignal,
+            mut control: PipelineControlSignal,
+        ) {
+            let _ = ready.send(Ok(()));
+            let mut next = 0;
+
+            while let Some(Control::Play) = control.last() {
+                if let Some((message, ack)) = control.try_message_acked() {
+                    if message != ControlMessage::Suspend {
+                        ack.ignore();
+                        continue;
+                    }
+
+                    ack.ack();
+                    if !control.wait_for_resume() {
+                        return;
+                    }
+                }
+
+                let _ = self.0.send(next);
+                next += 1;
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rotate_atomic_writes_each_item_to_one_segment() {
+        let (stop_tx, stop_rx) = flume::bounded::<()>(1);
+        let segments = Arc::new(std::sync::Mutex::new(vec![vec![]]));
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        let (output, input) = builder.edge("frames", 16);
+        builder.spawn_source("camera", Suspendable(output));
+
+        // A source that can't suspend keeps its own edge busy throughout.
+        let (screen, screen_rx) = builder.edge("screen", 16);
+        builder.spawn_task("screen", {
+            let stop_rx = stop_rx.clone();
+            move |ready| {
+                let _ = ready.send(Ok(()));
+
+                while !stop_rx.is_disconnected() {
+                    let _ = screen.try_send(());
+                    thread::sleep(Duration::from_millis(1));
+                }
+
+                Ok(())
+            }
+        });
+        builder.spawn_task("preview", move |ready| {
+            let _ = ready.send(Ok(()));
+            while !stop_rx.is_disconnected() {
+                let _ = screen_rx.recv_timeout(Duration::from_millis(1));
+            }
+            Ok(())
+        });
+
+        let messages = builder.control_messages("sink");
+        builder.spawn_task("sink", {
+            let segments = segments.clone();
+            move |ready| {
+                let _ = ready.send(Ok(()));
+
+                loop {
+                    while let Some((message, ack)) = messages.try_recv_acked() {
+                        if message == ControlMessage::Rotate {
+                            segments.lock().unwrap().push(vec![]);
+                        }
+                        ack.ack();
+                    }
+
+                    match input.recv_timeout(Duration::from_millis(1)) {
+                        Ok(item) => segments.lock().unwrap().last_mut().unwrap().push(item),
+                        Err(flume::RecvTimeoutError::Timeout) => {}
+                        Err(flume::RecvTimeoutError::Disconnected) => return Ok(()),
+                    }
+                }
+            }
+        });
+
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+        pipeline.play().await.unwrap();
+
+        let last_segment_filled = || !segments.lock().unwrap().last().unwrap().is_empty();
+        wait_until(last_segment_filled).await;
+        pipeline
+            .rotate_atomic(Duration::from_secs(1))
+            .await
+            .unwrap();
+        wait_until(last_segment_filled).await;
+
+        drop(stop_tx);
+        pipeline.shutdown().await.unwrap();
+
+        let segments = segments.lock().unwrap();
+        assert_eq!(segments.len(), 2);
+        assert!(segments.iter().all(|segment| !segment.is_empty()));
+
+        let items = segments.concat();
+        assert_eq!(items, (0..items.len()).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn flushes_are_sent_at_the_flush_interval_even_while_paused() {
+        const INTERVAL: Duration = Duration::from_millis(20);
+        let (stop_tx, stop_rx) = flume::bounded::<()>(0);
+        let (flushes_tx, flushes_rx) = flume::unbounded();
+
+        let mut builder =
+            Pipeline::builder(RealTimeClock::<()>::new()).with_flush_interval(INTERVAL);
+        let messages = builder.control_messages("sink");
+        builder.spawn_task("sink", move |ready| {
+            let _ = ready.send(Ok(()));
+
+            loop {
+                while let Some(message) = messages.try_recv() {
+                    if message == ControlMessage::Flush {
+                        let _ = flushes_tx.send(());
+                    }
+                }
+
+                if let Err(flume::RecvTimeoutError::Disconnected) =
+                    stop_rx.recv_timeout(Duration::from_millis(1))
+                {
+                    return Ok(());
+                }
+            }
+        });
+
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+        pipeline.play().await.unwrap();
+        pipeline.pause(PauseMode::ClockOnly).await.unwrap();
+        // Only count the flushes sent while paused.
+        while flushes_rx.try_recv().is_ok() {}
+
+        // Flushes never come early, so three of them take at least two intervals after the
+        // first. How late they are depends on how busy the machine is, so the upper bound is
+        // generous, but still well short of what a much longer interval would take.
+        let paused = std::time::Instant::now();
+        for _ in 0..3 {
+            tokio::time::timeout(Duration::from_secs(5), flushes_rx.recv_async())
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        let elapsed = paused.elapsed();
+        assert!(
+            elapsed >= INTERVAL * 2 && elapsed < INTERVAL * 25,
+            "3 flushes took {elapsed:?}"
+        );
+
+        drop(stop_tx);
+        pipeline.shutdown().await.unwrap();
+    }
+
+    #[test]
+    fn a_zero_rotation_interval_sends_no_rotations() {
+        let builder = Pipeline::builder(RealTimeClock::<()>::new())
+            .with_rotation_interval(Duration::from_secs(60))
+            .with_rotation_interval(Duration::ZERO);
+
+        assert_eq!(builder.rotation_interval, None);
+    }
+
+    #[test]
+    fn a_zero_flush_interval_sends_no_flushes() {
+        let builder = Pipeline::builder(RealTimeClock::<()>::new())
+            .with_flush_interval(Duration::from_millis(20))
+            .with_flush_interval(Duration::ZERO);
+
+        assert_eq!(builder.flush_interval, None);
+    }
+
+    struct Numbers {
+        output: AckedOutput<u32>,
+        resumed_at: Arc<Mutex<Option<Sequence>>>,
+    }
+
+    impl Resumable for Numbers {
+        fn resume_from(&mut self, sequence: Sequence) -> Result<(), MediaError> {
+            *self.resumed_at.lock().unwrap() = Some(sequence);
+            Ok(())
+        }
+    }
+
+    impl PipelineSourceTask for Numbers {
+        type Clock = RealTimeClock<()>;
+
+        fn run(&mut self, _: Self::Clock, ready: PipelineReadySignal, _: PipelineControlSignal) {
+            let _ = ready.send(Ok(()));
+
+            for n in 0..5 {
+                if self.output.send(n).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn resumable_source_resumes_after_the_last_ack_and_waits_for_acks() {
+        let resumed_at = Arc::new(Mutex::new(None));
+        let (received_tx, received_rx) = flume::unbounded();
+
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        let (input, acks) =
+            builder.spawn_resumable_source("numbers", Some(9), 2, |output| Numbers {
+                output,
+                resumed_at: resumed_at.clone(),
+            });
+        builder.spawn_task("sink", move |ready| {
+            let _ = ready.send(Ok(()));
+
+            // With room for two unacked items, the source can only get ahead of the acks
+            // by two.
+            for sequenced in input.iter() {
+                let _ = received_tx.send((sequenced.sequence, sequenced.item));
+                acks.ack(sequenced.sequence);
+            }
+
+            Ok(())
+        });
+
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+        let received = tokio::time::timeout(
+            Duration::from_secs(5),
+            received_rx.stream().take(5).collect::<Vec<_>>(),
+        )
+        .await
+        .unwrap();
+        pipeline.shutdown().await.unwrap();
+
+        assert_eq!(*resumed_at.lock().unwrap(), Some(10));
+        assert_eq!(received, vec![(10, 0), (11, 1), (12, 2), (13, 3), (14, 4)]);
+    }
+
+    struct Counter {
+        output: flume::Sender<u32>,
+        crash: bool,
+    }
+
+    impl PipelineSourceTask for Counter {
+        type Clock = RealTimeClock<()>;
+
+        fn run(
+            &mut self,
+            _: Self::Clock,
+            ready: PipelineReadySignal,
+            mut control: PipelineControlSignal,
+        ) {
+            let _ = ready.send(Ok(()));
+            if control.blocking_last() != Some(Control::Play) {
+                return;
+            }
+
+            for i in 0..3 {
+                let _ = self.output.send(i);
+            }
+            if self.crash {
+                panic!("device lost");
+            }
+        }
+    }
+
+    async fn sink_result_after_source(crash: bool) -> (Vec<u32>, Result<(), MediaError>) {
+        let (output, input) = flume::bounded(8);
+        let (result_tx, result_rx) = flume::bounded(1);
+
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        builder.spawn_source("counter", Counter { output, crash });
+        let end = builder.source_end("counter").unwrap();
+        builder.spawn_task("sink", move |ready| {
+            let _ = ready.send(Ok(()));
+
+            let mut items = vec![];
+            let result = loop {
+                match end.recv(&input) {
+                    Ok(Some(item)) => items.push(item),
+                    Ok(None) => break Ok(()),
+                    Err(error) => break Err(error),
+                }
+            };
+            let _ = result_tx.send((items, result));
+            Ok(())
+        });
+
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+        pipeline.play().await.unwrap();
+
+        let received = result_rx.recv_async().await.unwrap();
+        let _ = pipeline.shutdown().await;
+        received
+    }
+
+    #[tokio::test]
+    async fn sink_sees_a_finished_source_as_end_of_stream() {
+        let (items, result) = sink_result_after_source(false).await;
+
+        assert_eq!(items, vec![0, 1, 2]);
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn sink_sees_a_crashed_source_as_an_error() {
+        let (items, result) = sink_result_after_source(true).await;
+
+        assert_eq!(items, vec![0, 1, 2]);
+        assert!(matches!(result, Err(MediaError::SourceCrashed(source)) if source == "counter"));
+    }
+
+    #[tokio::test]
+    async fn stalled_drain_hands_back_leftovers_of_marked_edges() {
+        let (input_tx, input_rx) = flume::bounded(8);
+
+        let (builder, _output) = Pipeline::builder(RealTimeClock::<()>::new())
+            .path(input_rx)
+            .map("double", |n: u32| n * 2)
+            .recover_leftovers()
+            .with_queue_size(1)
+            .map("halve", |n: u32| n / 2)
+            .into_receiver();
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        // Nothing reads the output, so "halve" fills its edge with one item and blocks
+        // sending the next, leaving the rest queued on "double".
+        for n in 0..4 {
+            input_tx.send(n).unwrap();
+        }
+        drop(input_tx);
+        wait_until(|| {
+            let metrics = pipeline.metrics();
+            let double = &metrics.edges["double"];
+            double.sent == 4 && double.depth == 2
+        })
+        .await;
+
+        let (result, mut leftovers) = pipeline
+            .shutdown_graceful_with_leftovers(Duration::from_millis(50), |_| {})
+            .await;
+
+        assert!(matches!(result, Err(MediaError::DrainStalled(_))));
+        assert_eq!(leftovers.edges().collect::<Vec<_>>(), vec!["double"]);
+        assert_eq!(leftovers.take::<u32>("double"), Some(vec![4, 6]));
+        assert!(leftovers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn stats_subscriptions_end_when_a_drain_stalls() {
+        let (input_tx, input_rx) = flume::bounded(8);
+
+        let (builder, _output) = Pipeline::builder(RealTimeClock::<()>::new())
+            .path(input_rx)
+            .with_queue_size(1)
+            .map("double", |n: u32| n * 2)
+            .into_receiver();
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+        let stats = pipeline.subscribe_stats(Duration::from_millis(5));
+
+        // Nothing reads the output, so "double" blocks sending its second item.
+        for n in 0..4 {
+            input_tx.send(n).unwrap();
+        }
+        drop(input_tx);
+        wait_until(|| pipeline.metrics().edges["double"].depth == 1).await;
+
+        let result = pipeline
+            .shutdown_graceful(Duration::from_millis(50), |_| {})
+            .await;
+        assert!(matches!(result, Err(MediaError::DrainStalled(_))));
+
+        let disconnected = tokio::time::timeout(Duration::from_secs(5), async {
+            while stats.recv_async().await.is_ok() {}
+        })
+        .await;
+        assert!(
+            disconnected.is_ok(),
+            "the stats sampler outlived the shutdown"
+        );
+    }
+
+    struct SlowToStop(Duration);
+
+    impl PipelineSourceTask for SlowToStop {
+        type Clock = RealTimeClock<()>;
+
+        fn run(
+            &mut self,
+            _: Self::Clock,
+            ready: PipelineReadySignal,
+            mut control: PipelineControlSignal,
+        ) {
+            let _ = ready.send(Ok(()));
+
+            // Never played, so the first signal is the shutdown.
+            let _ = control.blocking_last();
+            thread::sleep(self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn shutdown_report_names_the_task_slowest_to_stop() {
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        builder.spawn_source("screen", SlowToStop(Duration::ZERO));
+        builder.spawn_source("encoder", SlowToStop(Duration::from_millis(50)));
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        pipeline.shutdown().await.unwrap();
+
+        let report = pipeline.shutdown_report();
+        assert_pipeline_ok(&report);
+        let (task, latency) = report.slowest_to_stop().unwrap();
+        assert_eq!(task, "encoder");
+        assert!(latency >= Duration::from_millis(50));
+        assert!(report.stop_latencies["screen"] < latency);
+    }
+
+    #[tokio::test]
+    async fn control_acks_are_only_awaited_from_tasks_that_ack() {
+        let (stop_tx, stop_rx) = flume::bounded::<()>(0);
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        // Never reads its control messages.
+        builder.spawn_source("screen", SlowToStop(Duration::ZERO));
+
+        let messages = builder.control_messages("encoder");
+        builder.spawn_task("encoder", move |ready| {
+            let _ = ready.send(Ok(()));
+
+            while !matches!(
+                stop_rx.recv_timeout(Duration::from_millis(1)),
+                Err(flume::RecvTimeoutError::Disconnected)
+            ) {
+                while let Some((_, ack)) = messages.try_recv_acked() {
+                    ack.ack();
+                }
+            }
+
+            Ok(())
+        });
+
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+        pipeline
+            .send_control_ack(ControlMessage::Flush, Duration::from_secs(1))
+            .await
+            .unwrap();
+
+        drop(stop_tx);
+        pipeline.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn fn_sink_writes_out_its_buffer_on_flush() {
+        let (input_tx, input_rx) = flume::bounded(8);
+        let buffered = Arc::new(Mutex::new(vec![]));
+        let written = Arc::new(Mutex::new(vec![]));
+
+        let sink = {
+            let (buffer, unflushed) = (buffered.clone(), buffered.clone());
+            let written = written.clone();
+            FnSink::with_flush(
+                move |n: u32| {
+                    buffer.lock().unwrap().push(n);
+                    Ok(())
+                },
+                move || {
+                    written
+                        .lock()
+                        .unwrap()
+                        .append(&mut unflushed.lock().unwrap());
+                    Ok(())
+                },
+            )
+        };
+        let builder = Pipeline::builder(RealTimeClock::<()>::new())
+            .path(input_rx)
+            .fn_sink("file", sink);
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        for n in 0..3 {
+            input_tx.send(n).unwrap();
+        }
+        wait_until(|| buffered.lock().unwrap().len() == 3).await;
+
+        // The sink only acks once it has flushed, so the items are written by now.
+        pipeline
+            .send_control_ack(ControlMessage::Flush, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(*written.lock().unwrap(), vec![0, 1, 2]);
+
+        drop(input_tx);
+        pipeline.shutdown().await.unwrap();
+    }
+
+    struct Follower(Arc<Mutex<Vec<Control>>>);
+
+    impl PipelineSourceTask for Follower {
+        type Clock = RealTimeClock<()>;
+
+        fn run(
+            &mut self,
+            _: Self::Clock,
+            ready: PipelineReadySignal,
+            mut control: PipelineControlSignal,
+        ) {
+            let _ = ready.send(Ok(()));
+
+            while let Some(value) = control.blocking_last() {
+                self.0.lock().unwrap().push(value);
+                if value == Control::Shutdown {
+                    return;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn idle_pipeline_is_shut_down_with_an_idle_timeout() {
+        const TIMEOUT: Duration = Duration::from_millis(50);
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new()).with_idle_timeout(TIMEOUT);
+        builder.spawn_source("screen", Follower(Default::default()));
+        let (mut pipeline, done_rx) = builder.build().await.unwrap();
+        pipeline.play().await.unwrap();
+
+        let error = tokio::time::timeout(Duration::from_secs(5), done_rx)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(error, MediaError::IdleTimeout(TIMEOUT).to_string());
+        assert_eq!(
+            pipeline.completion_reason(),
+            Some(CompletionReason::IdleTimeout(TIMEOUT))
+        );
+        let _ = pipeline.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn paused_pipeline_is_not_idle() {
+        const TIMEOUT: Duration = Duration::from_millis(20);
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new()).with_idle_timeout(TIMEOUT);
+        builder.spawn_source("screen", Follower(Default::default()));
+        let (mut pipeline, done_rx) = builder.build().await.unwrap();
+        pipeline.play().await.unwrap();
+        pipeline.pause(PauseMode::ClockOnly).await.unwrap();
+
+        // Many times the timeout. A busy machine can only make this check weaker.
+        tokio::time::sleep(TIMEOUT * 10).await;
+        assert_eq!(pipeline.completion_reason(), None);
+
+        // Once resumed, the watchdog is still there to fire.
+        pipeline.resume().await.unwrap();
+        let result = tokio::time::timeout(Duration::from_secs(5), done_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(result.is_err());
+        let _ = pipeline.shutdown().await;
+    }
+
+    #[test]
+    fn a_zero_idle_timeout_turns_the_watchdog_off() {
+        let builder = Pipeline::builder(RealTimeClock::<()>::new())
+            .with_idle_timeout(Duration::from_secs(1))
+            .with_idle_timeout(Duration::ZERO);
+
+        assert_eq!(builder.idle_timeout, None);
+    }
+
+    async fn follower_pipeline(
+        name: &str,
+    ) -> (Pipeline<RealTimeClock<()>>, Arc<Mutex<Vec<Control>>>) {
+        let controls = Arc::new(Mutex::new(vec![]));
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        builder.spawn_source(name, Follower(controls.clone()));
+
+        (builder.build().await.unwrap().0, controls)
+    }
+
+    #[tokio::test]
+    async fn every_event_stream_ends_after_the_shutdown() {
+        let (mut pipeline, _) = follower_pipeline("screen").await;
+        let first = pipeline.events();
+        let second = pipeline.events();
+
+        pipeline.shutdown().await.unwrap();
+
+        let expected = vec![
+            LifecycleEvent::TaskStopped {
+                task: "screen".to_string(),
+                reason: TaskOutcome::Stopped,
+            },
+            LifecycleEvent::PipelineShutdown,
+        ];
+        assert_eq!(first.collect::<Vec<_>>().await, expected);
+        assert_eq!(second.collect::<Vec<_>>().await, expected);
+        assert_eq!(pipeline.events().collect::<Vec<_>>().await, vec![]);
+    }
+
+    #[tokio::test]
+    async fn linked_pipelines_start_and_stop_together() {
+        let (mut audio, audio_controls) = follower_pipeline("audio").await;
+        let (mut video, video_controls) = follower_pipeline("video").await;
+
+        // Linked both ways, so neither forwards what it receives back to the other.
+        audio.link_control(&video);
+        video.link_control(&audio);
+
+        audio.play().await.unwrap();
+        audio.shutdown().await.unwrap();
+        video.shutdown().await.unwrap();
+
+        for controls in [audio_controls, video_controls] {
+            assert_eq!(
+                *controls.lock().unwrap(),
+                vec![Control::Play, Control::Shutdown]
+            );
+        }
+    }
+
+    struct MissingMicrophone;
+
+    impl PipelineSourceTask for MissingMicrophone {
+        type Clock = RealTimeClock<()>;
+
+        fn run(&mut self, _: Self::Clock, ready: PipelineReadySignal, _: PipelineControlSignal) {
+            let _ = ready.send(Err(MediaError::Any("no microphone".into())));
+        }
+    }
+
+    struct Microphone {
+        busy: bool,
+    }
+
+    impl PipelineSourceTask for Microphone {
+        type Clock = RealTimeClock<()>;
+
+        fn run(
+            &mut self,
+            _: Self::Clock,
+            ready: PipelineReadySignal,
+            mut control: PipelineControlSignal,
+        ) {
+            if self.busy {
+                let _ = ready.send(Err(MediaError::Any("busy".into())));
+                return;
+            }
+
+            let _ = ready.send(Ok(()));
+            while let Some(Control::Play) = control.blocking_last() {}
+        }
+    }
+
+    #[tokio::test]
+    async fn source_with_fallback_starts_the_first_candidate_that_becomes_ready() {
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        builder.spawn_source_with_fallback(
+            "microphone",
+            vec![
+                (
+                    "usb".to_string(),
+                    Box::new(|| Err(MediaError::DeviceUnreachable("usb".into())))
+                        as SourceFactory<Microphone>,
+                ),
+                (
+                    "headset".to_string(),
+                    Box::new(|| Ok(Microphone { busy: true })),
+                ),
+                (
+                    "built-in".to_string(),
+                    Box::new(|| Ok(Microphone { busy: false })),
+                ),
+            ],
+        );
+
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        assert_eq!(
+            pipeline.chosen_candidate("microphone"),
+            Some((2, "built-in"))
+        );
+        pipeline.shutdown().await.unwrap();
+    }
+
+    /// Like [`Microphone`], but `hangs` never signals ready and waits to be shut down.
+    struct StuckMicrophone {
+        hangs: bool,
+        messages: Arc<Mutex<Vec<ControlMessage>>>,
+    }
+
+    impl PipelineSourceTask for StuckMicrophone {
+        type Clock = RealTimeClock<()>;
+
+        fn run(
+            &mut self,
+            _: Self::Clock,
+            ready: PipelineReadySignal,
+            mut control: PipelineControlSignal,
+        ) {
+            if !self.hangs {
+                let _ = ready.send(Ok(()));
+            }
+
+            while control
+                .blocking_last()
+                .is_some_and(|value| value != Control::Shutdown)
+            {
+                while let Some(message) = control.try_message() {
+                    self.messages.lock().unwrap().push(message);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn source_with_fallback_moves_on_from_a_hanging_candidate() {
+        let messages = Arc::new(Mutex::new(vec![]));
+        let candidate = |hangs| {
+            let messages = messages.clone();
+            Box::new(move || Ok(StuckMicrophone { hangs, messages }))
+                as SourceFactory<StuckMicrophone>
+        };
+
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new())
+            .with_ready_timeout(Duration::from_millis(400));
+        builder.spawn_source_with_fallback(
+            "microphone",
+            vec![
+                ("usb".to_string(), candidate(true)),
+                ("built-in".to_string(), candidate(false)),
+            ],
+        );
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        assert_eq!(
+            pipeline.chosen_candidate("microphone"),
+            Some((1, "built-in"))
+        );
+
+        // Controlled under its own name, and relayed to the candidate that started.
+        pipeline.play().await.unwrap();
+        pipeline.set_source_rate("microphone", 15.0).unwrap();
+        wait_until(|| !messages.lock().unwrap().is_empty()).await;
+        assert_eq!(
+            *messages.lock().unwrap(),
+            vec![ControlMessage::SetRate(15.0)]
+        );
+
+        pipeline.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn source_with_fallback_listens_under_the_checked_name() {
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        let _messages = builder.control_messages("microphone");
+        builder.spawn_source_with_fallback(
+            "microphone",
+            vec![(
+                "built-in".to_string(),
+                Box::new(|| Ok(Microphone { busy: false })) as SourceFactory<Microphone>,
+            )],
+        );
+
+        let errors = builder.build_validated().await.err().unwrap();
+
+        assert!(matches!(
+            errors.as_slice(),
+            [MediaError::DuplicateControlListener(listener)] if listener == "microphone"
+        ));
+    }
+
+    #[tokio::test]
+    async fn low_priority_task_failing_to_start_leaves_pipeline_running() {
+        let (stop_tx, stop_rx) = flume::bounded::<()>(1);
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+
+        builder.spawn_task("screen", move |ready| {
+            let _ = ready.send(Ok(()));
+            let _ = stop_rx.recv();
+            Ok(())
+        });
+        builder.spawn_source_with_priority("microphone", MissingMicrophone, StartPriority::Low);
+
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+        wait_until(|| {
+            matches!(
+                pipeline.shutdown_report().tasks["microphone"],
+                TaskOutcome::Failed(_)
+            )
+        })
+        .await;
+        assert!(pipeline.completion_reason().is_none());
+
+        drop(stop_tx);
+        pipeline.shutdown().await.unwrap();
+    }
+
+    struct HardwareEncoder(Arc<AtomicBool>);
+
+    impl PipelineSourceTask for HardwareEncoder {
+        type Clock = RealTimeClock<()>;
+
+        fn run(&mut self, _: Self::Clock, ready: PipelineReadySignal, _: PipelineControlSignal) {
+            self.0.store(true, Ordering::SeqCst);
+            let _ = ready.send(Ok(()));
+        }
+
+        fn required_capabilities(&self) -> &'static [&'static str] {
+            &["hardware-encoder"]
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_capability_fails_before_launch() {
+        let started = Arc::new(AtomicBool::new(false));
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new()).with_capability("gpu");
+        builder.spawn_source("encoder", HardwareEncoder(started.clone()));
+
+        let error = builder.build().await.err().unwrap();
+
+        assert!(matches!(
+            error,
+            MediaError::MissingCapability { ref task, ref capability }
+                if task == "encoder" && capability == "hardware-encoder"
+        ));
+        assert!(!started.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn panic_while_holding_shared_state_can_be_recovered_from() {
+        let state = Arc::new(Mutex::new(0));
+        let (go_tx, go_rx) = flume::bounded::<()>(1);
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        builder.spawn_task("counter", {
+            let state = state.clone();
+            move |ready| {
+                let _ = ready.send(Ok(()));
+                let _ = go_rx.recv();
+
+                let _count = state.lock().unwrap();
+                panic!("boom");
+            }
+        });
+
+        let (_pipeline, done_rx) = builder.build().await.unwrap();
+        go_tx.send(()).unwrap();
+
+        let error = done_rx.await.unwrap().unwrap_err();
+        assert!(
+            error.contains("boom") && error.contains("poisoned"),
+            "{error}"
+        );
+
+        *recover_poisoned(&state) += 1;
+        assert!(!state.is_poisoned());
+        assert_eq!(*state.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn panics_are_reported_with_their_message_and_location() {
+        for propagate in [false, true] {
+            let mut builder =
+                Pipeline::builder(RealTimeClock::<()>::new()).with_panic_propagation(propagate);
+            builder.spawn_task("counter", |ready| {
+                let _ = ready.send(Ok(()));
+                panic!("boom");
+            });
+
+            let (_pipeline, done_rx) = builder.build().await.unwrap();
+
+            let error = done_rx.await.unwrap().unwrap_err();
+            assert!(
+                error.contains("boom at") && error.contains(file!()),
+                "{error}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn set_source_rate_changes_the_cadence_of_a_paced_source() {
+        use crate::sources::{IterPacing, IterSource};
+
+        let (frames_tx, frames_rx) = flume::bounded(16);
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        builder.spawn_source(
+            "frames",
+            IterSource::new(0.., frames_tx)
+                .with_pacing(IterPacing::Interval(Duration::from_secs(3600))),
+        );
+
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+        pipeline.play().await.unwrap();
+
+        assert!(matches!(
+            pipeline.set_source_rate("frames", 0.0),
+            Err(MediaError::InvalidSourceRate(_))
+        ));
+        assert!(matches!(
+            pipeline.set_source_rate("camera", 30.0),
+            Err(MediaError::UnknownTask(_))
+        ));
+
+        pipeline.set_source_rate("frames", 100.0).unwrap();
+        let first = tokio::time::timeout(Duration::from_secs(1), frames_rx.recv_async()).await;
+        assert_eq!(first.unwrap().unwrap(), 0);
+
+        pipeline.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_source_rate_tells_a_full_queue_from_an_unknown_task() {
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        // Never reads its control messages.
+        builder.spawn_source("screen", SlowToStop(Duration::ZERO));
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        let error = loop {
+            if let Err(error) = pipeline.set_source_rate("screen", 30.0) {
+                break error;
+            }
+        };
+        assert!(matches!(error, MediaError::ControlQueueFull(task) if task == "screen"));
+        assert!(matches!(
+            pipeline.set_source_rate("camera", 30.0),
+            Err(MediaError::UnknownTask(_))
+        ));
+
+        pipeline.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn extensions_are_kept_by_type() {
+        struct SessionId(u32);
+
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new())
+            .insert_extension(SessionId(1))
+            .insert_extension(PathBuf::from("out.mp4"))
+            .insert_extension(SessionId(2));
+        builder.spawn_task("task", |ready| {
+            let _ = ready.send(Ok(()));
+            Ok(())
+        });
+
+        let (pipeline, _done_rx) = builder.build().await.unwrap();
+
+        assert_eq!(pipeline.extension::<SessionId>().unwrap().0, 2);
+        assert_eq!(
+            pipeline.extension::<PathBuf>(),
+            Some(&PathBuf::from("out.mp4"))
+        );
+        assert!(pipeline.extension::<String>().is_none());
+    }
+
+    #[tokio::test]
+    async fn over_buffered_path_exceeds_latency_budget() {
+        let (_frames_tx, frames_rx) = flume::bounded::<u32>(1);
+        let (builder, _output) = Pipeline::builder(RealTimeClock::<()>::new())
+            .with_max_latency(Duration::from_millis(100))
+            .path(frames_rx)
+            .with_queue_size(64)
+            .with_item_time(Duration::from_millis(2))
+            .map("scale", |frame| frame)
+            .map("convert", |frame| frame)
+            .into_receiver();
+
+        let error = builder.build().await.err().unwrap();
+
+        assert!(matches!(
+            error,
+            MediaError::LatencyBudgetExceeded { estimate, .. }
+                if estimate == Duration::from_millis(128)
+        ));
+    }
+
+    #[tokio::test]
+    async fn build_validated_reports_every_problem() {
+        let started = Arc::new(AtomicBool::new(false));
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        builder.spawn_task("sink", |ready| {
+            let _ = ready.send(Ok(()));
+            Ok(())
+        });
+        builder.spawn_source("encoder", HardwareEncoder(started.clone()));
+        builder.spawn_task("sink", |_| Ok(()));
+        builder.spawn_dependent_task("muxer", ["encoder", "audio"], |_| Ok(()));
+
+        let errors = builder.build_validated().await.err().unwrap();
+
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                MediaError::MissingCapability { task, .. },
+                MediaError::DuplicateTask(duplicate),
+                MediaError::UnknownDependency { task: dependent, dependency },
+            ] if task == "encoder"
+                && duplicate == "sink"
+                && dependent == "muxer"
+                && dependency == "audio"
+        ));
+        assert!(!started.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn a_second_control_listener_under_a_name_fails_the_build() {
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        let _messages = builder.control_messages("screen");
+        builder.spawn_source("screen", SlowToStop(Duration::ZERO));
+        builder.spawn_source("camera", SlowToStop(Duration::ZERO));
+        builder.spawn_source("camera", SlowToStop(Duration::ZERO));
+
+        let errors = builder.build_validated().await.err().unwrap();
+
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                MediaError::DuplicateControlListener(listener),
+                MediaError::DuplicateTask(task),
+            ] if listener == "screen" && task == "camera"
+        ));
+    }
+
+    #[tokio::test]
+    async fn summary_totals_the_items_each_task_sent() {
+        let (input_tx, input_rx) = flume::bounded(8);
+        let (builder, output) = Pipeline::builder(RealTimeClock::<()>::new())
+            .path(input_rx)
+            .map("double", |n: u32| n * 2)
+            .filter("multiples_of_four", |n| n % 4 == 0)
+            .into_receiver();
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        for n in 0..4 {
+            input_tx.send(n).unwrap();
+        }
+        drop(input_tx);
+        assert_eq!(output.iter().collect::<Vec<_>>(), vec![0, 4]);
+        pipeline.shutdown().await.unwrap();
+
+        let summary = pipeline.summary();
+        assert_eq!(summary.tasks["double"], 4);
+        assert_eq!(summary.tasks["multiples_of_four"], 2);
+        assert!(summary.to_string().contains("double: 4"));
+    }
+
+    fn core_pipeline(input: Receiver<u32>) -> PipelineBuilder<RealTimeClock<()>> {
+        Pipeline::builder(RealTimeClock::<()>::new())
+            .path(input)
+            .map("double", |n| n * 2)
+            .seal("doubled")
+    }
+
+    #[tokio::test]
+    async fn sealed_path_can_be_reopened_elsewhere() {
+        let (input_tx, input_rx) = flume::bounded(8);
+        let builder = core_pipeline(input_rx);
+        assert!(builder.has_endpoint("doubled"));
+
+        let (builder, output) = builder
+            .reopen::<u32>("doubled")
+            .map("increment", |n| n + 1)
+            .into_receiver();
+        assert!(!builder.has_endpoint("doubled"));
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        for n in 0..3 {
+            input_tx.send(n).unwrap();
+        }
+        drop(input_tx);
+
+        assert_eq!(output.iter().collect::<Vec<_>>(), vec![1, 3, 5]);
+        pipeline.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reopening_with_the_wrong_type_fails_the_build() {
+        let (_input_tx, input_rx) = flume::bounded(8);
+
+        let (builder, _output) = core_pipeline(input_rx)
+            .reopen::<String>("doubled")
+            .into_receiver();
+        let errors = builder.build_validated().await.err().unwrap();
+
+        assert!(matches!(
+            errors.as_slice(),
+            [MediaError::EndpointTypeMismatch { endpoint, sealed: "u32", requested }]
+                if endpoint == "doubled" && requested.ends_with("String")
+        ));
+    }
+
+    /// Waits for `condition` to hold, rather than sleeping for a fixed time that a busy
+    /// machine may need more than.
+    async fn wait_until(mut condition: impl FnMut() -> bool) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !condition() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("the condition didn't hold within 5s");
+    }
+
+    /// A clock that only moves when told to, like the ones deterministic tests use, and
+    /// that takes the given time to settle.
+    #[derive(Clone, Default)]
+    struct ManualClock(Arc<AtomicUsize>, Duration);
+
+    impl PipelineClock for ManualClock {
+        type Instant = usize;
+        type Duration = Duration;
+
+        fn start(&mut self) {}
+
+        fn stop(&mut self) {}
+
+        fn running(&self) -> bool {
+            true
+        }
+
+        fn now(&self) -> usize {
+            self.0.load(Ordering::SeqCst)
+        }
+
+        fn elapsed_between(earlier: usize, later: usize) -> Duration {
+            Duration::from_millis(later.saturating_sub(earlier) as u64)
+        }
+
+        fn settle_delay(&self) -> Duration {
+            self.1
+        }
+    }
+
+    impl CloneInto<ManualClock> for ManualClock {
+        fn clone_into(&self) -> ManualClock {
+            self.clone()
+        }
+    }
+
+    /// Runs until the pipeline is shut down, on a [`ManualClock`].
+    struct UntilShutdown;
+
+    impl PipelineSourceTask for UntilShutdown {
+        type Clock = ManualClock;
+
+        fn run(
+            &mut self,
+            _: Self::Clock,
+            ready: PipelineReadySignal,
+            mut control: PipelineControlSignal,
+        ) {
+            let _ = ready.send(Ok(()));
+            while control
+                .blocking_last()
+                .is_some_and(|value| value != Control::Shutdown)
+            {}
+        }
+    }
+
+    async fn limited_pipeline(
+        limit: Duration,
+    ) -> (
+        Pipeline<ManualClock>,
+        oneshot::Receiver<Result<(), String>>,
+        Arc<AtomicUsize>,
+    ) {
+        let clock = ManualClock::default();
+        let time = clock.0.clone();
+        let mut builder = Pipeline::builder(clock).with_max_duration(limit, DurationMeasure::Clock);
+        builder.spawn_source("screen", UntilShutdown);
+        let (pipeline, done_rx) = builder.build().await.unwrap();
+        (pipeline, done_rx, time)
+    }
+
+    #[tokio::test]
+    async fn duration_limit_stops_the_pipeline_on_clock_time() {
+        let (mut pipeline, done_rx, time) = limited_pipeline(Duration::from_millis(100)).await;
+
+        // Only the pipeline clock counts, so wall time passing doesn't reach the limit.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(pipeline.completion_reason(), None);
+
+        time.store(100, Ordering::SeqCst);
+        let result = tokio::time::timeout(Duration::from_secs(5), done_rx)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            pipeline.completion_reason(),
+            Some(CompletionReason::DurationLimitReached)
+        );
+
+        // The limit stopped the pipeline first, so shutting it down doesn't change why.
+        pipeline.shutdown().await.unwrap();
+        assert_eq!(
+            pipeline.completion_reason(),
+            Some(CompletionReason::DurationLimitReached)
+        );
+    }
+
+    #[tokio::test]
+    async fn duration_limit_does_not_override_an_earlier_shutdown() {
+        let (mut pipeline, _done_rx, time) = limited_pipeline(Duration::from_millis(100)).await;
+
+        pipeline.shutdown().await.unwrap();
+        time.store(100, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert_eq!(
+            pipeline.completion_reason(),
+            Some(CompletionReason::Stopped)
+        );
+    }
+
+    #[tokio::test]
+    async fn sampling_counts_more_intervals_than_fit_in_a_u32() {
+        let clock = ManualClock::default();
+        let time = clock.0.clone();
+        let (input_tx, input_rx) = flume::bounded(8);
+        let (path, samples, stats) = Pipeline::builder(clock).path(input_rx).sample_every(
+            "snapshots",
+            Duration::from_nanos(1),
+            DurationMeasure::Clock,
+        );
+        let (builder, output) = path.into_receiver();
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        input_tx.send(0u32).unwrap();
+        assert_eq!(output.recv(), Ok(0));
+        assert_eq!(samples.recv(), Ok(0));
+
+        // Five seconds is five billion intervals of a nanosecond.
+        time.store(5000, Ordering::SeqCst);
+        input_tx.send(1).unwrap();
+        assert_eq!(output.recv(), Ok(1));
+        assert_eq!(samples.recv(), Ok(1));
+        assert_eq!(stats.expected(), 5_000_000_001);
+        assert_eq!(stats.emitted(), 2);
+
+        drop(input_tx);
+        pipeline.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn builds_wait_for_the_settle_delay_of_their_clock() {
+        let build = |settle_delay| {
+            let mut builder = Pipeline::builder(ManualClock(Default::default(), settle_delay));
+            builder.spawn_task("task", |ready| {
+                let _ = ready.send(Ok(()));
+                Ok(())
+            });
+            builder.build()
+        };
+
+        // Builds wait out their clock's settle delay rather than the default one, so one on a
+        // clock that takes an hour to settle can't finish, while one that needs none can.
+        let unsettled =
+            tokio::time::timeout(Duration::from_millis(100), build(Duration::from_secs(3600)))
+                .await;
+        assert!(unsettled.is_err());
+
+        let (mut pipeline, _done_rx) = build(Duration::ZERO).await.unwrap();
+        pipeline.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn failure_is_reported_for_the_failing_task() {
+        const TASKS: usize = 16;
+
+        for failing in 0..TASKS {
+            let (hold_tx, hold_rx) = flume::bounded::<()>(1);
+            let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+
+            // Names don't follow the insertion order, so a mix-up of indices shows.
+            let name = |i: usize| format!("task-{}", (i * 7) % TASKS);
+
+            for i in 0..TASKS {
+                let hold_rx = hold_rx.clone();
+                let task_name = name(i);
+
+                builder.spawn_task(name(i), move |ready| {
+                    let _ = ready.send(Ok(()));
+
+                    if i == failing {
+                        return Err(format!("{task_name} broke"));
+                    }
+
+                    let _ = hold_rx.recv();
+                    Ok(())
+                });
+            }
+
+            let (mut pipeline, done_rx) = builder.build().await.unwrap();
+            let error = done_rx.await.unwrap().unwrap_err();
+
+            assert_eq!(
+                error,
+                format!("Task '{}' failed: {} broke", name(failing), name(failing))
+            );
+            assert_eq!(
+                pipeline.completion_reason(),
+                Some(CompletionReason::TaskFinished {
+                    task: name(failing),
+                    error: Some(error),
+                })
+            );
+
+            drop(hold_tx);
+            pipeline.shutdown().await.unwrap();
+        }
+    }
 }
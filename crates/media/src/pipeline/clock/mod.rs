@@ -1,15 +1,108 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
 mod real_time;
 mod recorded;
 
 pub use real_time::*;
 pub use recorded::*;
 
+/// How long `build` gives a pipeline on a wall-clock-based clock to settle once every task
+/// is ready, see [`PipelineClock::settle_delay`].
+pub const SETTLE_DELAY: Duration = Duration::from_millis(10);
+
 pub trait PipelineClock: Clone + Send + 'static {
+    /// A point in the clock's time, see [`Self::now`]. Clocks based on the wall clock use
+    /// [`RunningTime`].
+    type Instant: Copy + Ord + fmt::Debug + Send + 'static;
+
+    /// The time between two of the clock's instants, convertible to and from
+    /// [`std::time::Duration`] so stages can be configured in plain durations.
+    type Duration: Copy + Ord + fmt::Debug + Send + From<Duration> + Into<Duration> + 'static;
+
     fn start(&mut self);
 
     fn stop(&mut self);
 
     fn running(&self) -> bool;
+
+    /// The current time on the clock, which stands still while the clock is stopped. Stages
+    /// and tasks timing things against the pipeline clock, e.g. windows, should use this
+    /// so time spent paused doesn't count.
+    fn now(&self) -> Self::Instant;
+
+    /// The time from `earlier` to `later`, or zero if `later` comes first.
+    fn elapsed_between(earlier: Self::Instant, later: Self::Instant) -> Self::Duration;
+
+    /// The time from `earlier` to [`Self::now`].
+    fn elapsed_since(&self, earlier: Self::Instant) -> Duration {
+        Self::elapsed_between(earlier, self.now()).into()
+    }
+
+    /// How far the clock's notion of elapsed time has drifted from the wall clock, for
+    /// clocks that track it. For clocks handed out to several tasks, this is the largest
+    /// skew across all of them.
+    fn wall_clock_skew(&self) -> Option<Duration> {
+        None
+    }
+
+    /// How long `build` waits once every task is ready, before setting up the watchdogs,
+    /// for the tasks to get going. Clocks that aren't driven by the wall clock, e.g. ones
+    /// stepped by tests, should return zero, so building doesn't take real time that
+    /// means nothing to them.
+    fn settle_delay(&self) -> Duration {
+        SETTLE_DELAY
+    }
+}
+
+/// The time a wall-clock-based [`PipelineClock`] has been running for, not counting the
+/// time it spent stopped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RunningTime(pub Duration);
+
+impl RunningTime {
+    pub fn saturating_duration_since(self, earlier: Self) -> Duration {
+        self.0.saturating_sub(earlier.0)
+    }
+}
+
+/// Tracks the [`RunningTime`] of a clock that has nothing better than the wall clock to go
+/// by. Clones share their state, like clones of the clocks themselves.
+#[derive(Debug, Clone, Default)]
+struct RunningTimer(Arc<Mutex<RunningTimerState>>);
+
+#[derive(Debug, Default)]
+struct RunningTimerState {
+    resumed_at: Option<Instant>,
+    before_resuming: Duration,
+}
+
+impl RunningTimer {
+    fn start(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.resumed_at.get_or_insert_with(Instant::now);
+    }
+
+    fn stop(&self) {
+        let mut state = self.0.lock().unwrap();
+
+        if let Some(resumed_at) = state.resumed_at.take() {
+            state.before_resuming += resumed_at.elapsed();
+        }
+    }
+
+    fn now(&self) -> RunningTime {
+        let state = self.0.lock().unwrap();
+        let since_resuming = state
+            .resumed_at
+            .map(|resumed_at| resumed_at.elapsed())
+            .unwrap_or_default();
+
+        RunningTime(state.before_resuming + since_resuming)
+    }
 }
 
 // TODO: Move to utils mod?
@@ -1,10 +1,10 @@
 use std::sync::{
     atomic::{AtomicBool, AtomicU64, Ordering},
-    Arc, RwLock,
+    Arc, Mutex, RwLock, Weak,
 };
 use std::time::{Duration, Instant};
 
-use super::{CloneInto, PipelineClock};
+use super::{CloneInto, PipelineClock, RunningTime, RunningTimer};
 
 pub trait LocalTimestamp: Sized + Clone {
     fn elapsed_since(&self, other: &Self) -> Duration;
@@ -39,8 +39,15 @@ pub struct RealTimeClock<T: LocalTimestamp> {
     // We could store the `Duration` here, but that would be more expensive than using an atomic integer.
     resume_offset_nanoseconds: Arc<AtomicU64>,
     running: Arc<AtomicBool>,
+    running_time: RunningTimer,
+    // Skew of each clock cloned from the same root, as nanoseconds or `NO_SKEW`. Weak, so
+    // the skews of clocks that were dropped don't pile up.
+    skews: Arc<Mutex<Vec<Weak<AtomicU64>>>>,
+    local_skew: Arc<AtomicU64>,
 }
 
+const NO_SKEW: u64 = u64::MAX;
+
 impl<Source: LocalTimestamp, Target: LocalTimestamp> CloneInto<RealTimeClock<Target>>
     for RealTimeClock<Source>
 {
@@ -49,13 +56,21 @@ impl<Source: LocalTimestamp, Target: LocalTimestamp> CloneInto<RealTimeClock<Tar
             global_start_time,
             resume_offset_nanoseconds,
             running,
+            running_time,
+            skews,
             ..
         } = self.clone();
 
+        let local_skew = Arc::new(AtomicU64::new(NO_SKEW));
+        skews.lock().unwrap().push(Arc::downgrade(&local_skew));
+
         RealTimeClock {
             global_start_time,
             resume_offset_nanoseconds,
             running,
+            running_time,
+            skews,
+            local_skew,
             local_start_time: None,
             first_local_timestamp: None,
         }
@@ -80,6 +95,9 @@ impl<T: LocalTimestamp> RealTimeClock<T> {
             first_local_timestamp: None,
             resume_offset_nanoseconds: Arc::new(AtomicU64::new(0)),
             running: Arc::new(AtomicBool::new(false)),
+            running_time: RunningTimer::default(),
+            skews: Arc::new(Mutex::new(vec![])),
+            local_skew: Arc::new(AtomicU64::new(NO_SKEW)),
         }
     }
 
@@ -120,9 +138,21 @@ impl<T: LocalTimestamp> RealTimeClock<T> {
             let local_start_time = self.local_start_time.as_ref().unwrap();
             let first_local_timestamp = self.first_local_timestamp.as_ref().unwrap();
 
+            let local_elapsed = local.elapsed_since(first_local_timestamp);
+            let wall_elapsed = now.duration_since(*local_start_time);
+            let skew = if local_elapsed > wall_elapsed {
+                local_elapsed - wall_elapsed
+            } else {
+                wall_elapsed - local_elapsed
+            };
+            self.local_skew.store(
+                skew.as_nanos().try_into().unwrap_or(NO_SKEW - 1),
+                Ordering::Release,
+            );
+
             let total_offset =
                 local_start_time.duration_since(*global_start_time) + self.resume_offset();
-            let elapsed_time = local.elapsed_since(first_local_timestamp) + total_offset;
+            let elapsed_time = local_elapsed + total_offset;
             let timestamp = elapsed_time.as_micros().try_into().unwrap();
 
             Some(timestamp)
@@ -134,6 +164,9 @@ impl<T: LocalTimestamp> RealTimeClock<T> {
 }
 
 impl PipelineClock for RealTimeClock<()> {
+    type Instant = RunningTime;
+    type Duration = Duration;
+
     fn running(&self) -> bool {
         self.running.load(Ordering::Acquire)
     }
@@ -144,6 +177,7 @@ impl PipelineClock for RealTimeClock<()> {
 
             let now = Instant::now();
             *start_time = now;
+            self.running_time.start();
             self.set_running(true);
         }
     }
@@ -151,10 +185,52 @@ impl PipelineClock for RealTimeClock<()> {
     fn stop(&mut self) {
         if self.running() {
             self.set_running(false);
+            self.running_time.stop();
 
             let now = Instant::now();
             let start_time = self.global_start_time.read().unwrap();
             self.update_resume_offset(now - *start_time);
         }
     }
+
+    fn now(&self) -> RunningTime {
+        self.running_time.now()
+    }
+
+    fn elapsed_between(earlier: RunningTime, later: RunningTime) -> Duration {
+        later.saturating_duration_since(earlier)
+    }
+
+    fn wall_clock_skew(&self) -> Option<Duration> {
+        let mut skews = self.skews.lock().unwrap();
+        skews.retain(|skew| skew.strong_count() > 0);
+
+        skews
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|skew| skew.load(Ordering::Acquire))
+            .filter(|nanos| *nanos != NO_SKEW)
+            .max()
+            .map(Duration::from_nanos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skews_of_dropped_clocks_are_pruned() {
+        let root = RealTimeClock::<()>::new();
+        let kept = CloneInto::<RealTimeClock<Instant>>::clone_into(&root);
+        kept.local_skew.store(5, Ordering::Release);
+
+        for _ in 0..8 {
+            let dropped = CloneInto::<RealTimeClock<Instant>>::clone_into(&root);
+            dropped.local_skew.store(1_000, Ordering::Release);
+        }
+
+        assert_eq!(root.wall_clock_skew(), Some(Duration::from_nanos(5)));
+        assert_eq!(root.skews.lock().unwrap().len(), 1);
+    }
 }
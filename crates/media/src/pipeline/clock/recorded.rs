@@ -1,13 +1,17 @@
-use std::sync::{
-    atomic::{AtomicBool, AtomicU32, Ordering},
-    Arc,
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
-use super::{CloneInto, PipelineClock};
+use super::{CloneInto, PipelineClock, RunningTime, RunningTimer};
 
 #[derive(Debug, Clone)]
 pub struct RecordedClock {
     running: Arc<AtomicBool>,
+    running_time: RunningTimer,
     start_frame_offset: Arc<AtomicU32>,
     total_frame_duration: f64,
 }
@@ -16,6 +20,7 @@ impl RecordedClock {
     pub fn new(duration: f64, fps: u32) -> Self {
         Self {
             running: Arc::new(AtomicBool::new(false)),
+            running_time: RunningTimer::default(),
             start_frame_offset: Arc::new(AtomicU32::new(0)),
             total_frame_duration: duration * f64::from(fps),
         }
@@ -41,16 +46,30 @@ impl CloneInto<RecordedClock> for RecordedClock {
     }
 }
 
+// The clock's time is how long playback has been running, independent of the playhead.
 impl PipelineClock for RecordedClock {
+    type Instant = RunningTime;
+    type Duration = Duration;
+
     fn running(&self) -> bool {
         self.running.load(Ordering::Acquire)
     }
 
     fn start(&mut self) {
+        self.running_time.start();
         self.set_running(true);
     }
 
     fn stop(&mut self) {
         self.set_running(false);
+        self.running_time.stop();
+    }
+
+    fn now(&self) -> RunningTime {
+        self.running_time.now()
+    }
+
+    fn elapsed_between(earlier: RunningTime, later: RunningTime) -> Duration {
+        later.saturating_duration_since(earlier)
     }
 }
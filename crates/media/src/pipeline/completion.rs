@@ -0,0 +1,215 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use indexmap::IndexMap;
+
+/// Why a pipeline stopped. Whichever cause comes first is the one that's recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompletionReason {
+    /// The pipeline was shut down by its owner.
+    Stopped,
+    /// A task finished on its own, successfully or with an error.
+    TaskFinished { task: String, error: Option<String> },
+    /// No items flowed for the configured idle timeout.
+    IdleTimeout(Duration),
+    /// The pipeline ran for its configured maximum duration.
+    DurationLimitReached,
+}
+
+/// How a [`PipelineBuilder::with_max_duration`](super::builder::PipelineBuilder::with_max_duration)
+/// limit is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DurationMeasure {
+    /// Only count time the pipeline clock is running, so pauses don't count.
+    #[default]
+    Clock,
+    /// Count wall time from when the clock first started, including pauses.
+    Wall,
+}
+
+/// The reason along with when it was recorded, which is when the pipeline started stopping.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Completion(Arc<Mutex<Option<(CompletionReason, Instant)>>>);
+
+impl Completion {
+    /// Records the reason unless one was already recorded, returning whether it was.
+    pub fn record(&self, reason: CompletionReason) -> bool {
+        let mut current = self.0.lock().unwrap();
+
+        if current.is_some() {
+            return false;
+        }
+
+        *current = Some((reason, Instant::now()));
+        true
+    }
+
+    pub fn reason(&self) -> Option<CompletionReason> {
+        Some(self.0.lock().unwrap().as_ref()?.0.clone())
+    }
+
+    pub fn recorded_at(&self) -> Option<Instant> {
+        Some(self.0.lock().unwrap().as_ref()?.1)
+    }
+}
+
+/// How a single task ended, see [`ShutdownReport`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskOutcome {
+    /// The task hadn't finished when the report was taken, e.g. as it was detached or
+    /// never launched.
+    Running,
+    /// The task finished on its own before the pipeline was stopped.
+    Finished,
+    /// The task finished after the pipeline was stopped.
+    Stopped,
+    /// The task returned an error or panicked.
+    Failed(String),
+}
+
+impl TaskOutcome {
+    fn new(result: &Result<(), String>, stopping: bool) -> Self {
+        match result {
+            Ok(()) if stopping => Self::Stopped,
+            Ok(()) => Self::Finished,
+            Err(error) => Self::Failed(error.clone()),
+        }
+    }
+}
+
+/// How each of a pipeline's tasks ended, in the order they were added to the builder,
+/// see [`Pipeline::shutdown_report`](super::Pipeline::shutdown_report).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    pub tasks: IndexMap<String, TaskOutcome>,
+    /// How long each task that was still running when the pipeline started stopping took
+    /// to finish after that, in the order they finished, e.g. to see which sink is slow to
+    /// flush. Tasks that are still running aren't included.
+    pub stop_latencies: IndexMap<String, Duration>,
+}
+
+impl ShutdownReport {
+    /// The task that took longest to finish once the pipeline started stopping.
+    pub fn slowest_to_stop(&self) -> Option<(&str, Duration)> {
+        self.stop_latencies
+            .iter()
+            .max_by_key(|(_, latency)| **latency)
+            .map(|(task, latency)| (task.as_str(), *latency))
+    }
+
+    /// The first failure, formatted like the completion channel's error.
+    pub fn result(&self) -> Result<(), String> {
+        match self.tasks.iter().find_map(|(task, outcome)| match outcome {
+            TaskOutcome::Failed(error) => Some((task, error)),
+            _ => None,
+        }) {
+            Some((task, error)) => Err(format!("Task '{task}' failed: {error}")),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.result().is_ok()
+    }
+}
+
+/// Formats as e.g. `screen: ok, microphone: failed (device disconnected)`.
+impl fmt::Display for ShutdownReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (task, outcome)) in self.tasks.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+
+            match outcome {
+                TaskOutcome::Running => write!(f, "{task}: running")?,
+                TaskOutcome::Finished | TaskOutcome::Stopped => write!(f, "{task}: ok")?,
+                TaskOutcome::Failed(error) => write!(f, "{task}: failed ({error})")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Where every task records how it ended, written from the task's own thread so that the
+/// outcomes are complete as soon as the tasks are joined.
+#[derive(Debug, Clone, Default)]
+pub(super) struct TaskOutcomes(Arc<Mutex<ShutdownReport>>);
+
+impl TaskOutcomes {
+    pub fn register(&self, task: String) {
+        self.0
+            .lock()
+            .unwrap()
+            .tasks
+            .insert(task, TaskOutcome::Running);
+    }
+
+    /// The tasks that haven't finished yet.
+    pub fn running(&self) -> Vec<String> {
+        let report = self.0.lock().unwrap();
+
+        report
+            .tasks
+            .iter()
+            .filter(|(_, outcome)| **outcome == TaskOutcome::Running)
+            .map(|(task, _)| task.clone())
+            .collect()
+    }
+
+    /// Records a task's result. Tasks that finish once the pipeline has a completion
+    /// reason count as stopped rather than finished.
+    pub fn record(
+        &self,
+        task: &str,
+        result: &Result<(), String>,
+        completion: &Completion,
+    ) -> TaskOutcome {
+        let stopping_since = completion.recorded_at();
+        let outcome = TaskOutcome::new(result, stopping_since.is_some());
+        let mut report = self.0.lock().unwrap();
+
+        if let Some(current) = report.tasks.get_mut(task) {
+            *current = outcome.clone();
+
+            if let Some(since) = stopping_since {
+                report
+                    .stop_latencies
+                    .insert(task.to_string(), since.elapsed());
+            }
+        }
+
+        outcome
+    }
+
+    pub fn report(&self) -> ShutdownReport {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+type ShutdownCallback = Box<dyn FnOnce(&ShutdownReport) + Send>;
+
+/// The hook set with
+/// [`PipelineBuilder::with_on_shutdown`](super::builder::PipelineBuilder::with_on_shutdown),
+/// shared by everything that can see the pipeline through to the end so that whichever
+/// does so first runs it, and only once.
+#[derive(Clone, Default)]
+pub(super) struct ShutdownHook(Arc<Mutex<Option<ShutdownCallback>>>);
+
+impl ShutdownHook {
+    pub fn new(hook: impl FnOnce(&ShutdownReport) + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(Some(Box::new(hook)))))
+    }
+
+    pub fn run(&self, outcomes: &TaskOutcomes) {
+        let hook = self.0.lock().unwrap().take();
+
+        if let Some(hook) = hook {
+            hook(&outcomes.report());
+        }
+    }
+}
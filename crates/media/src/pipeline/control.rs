@@ -1,5 +1,13 @@
-use flume::{Receiver, Sender, TryRecvError};
-use indexmap::IndexMap;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use flume::{Receiver, Sender, TryRecvError, TrySendError};
+use indexmap::{map::Entry, IndexMap, IndexSet};
 use tracing::{debug, error};
 
 use crate::pipeline::MediaError;
@@ -10,12 +18,206 @@ pub enum Control {
     Shutdown,
 }
 
+/// One-off instructions for tasks. Unlike [`Control`], these don't change a task's state and
+/// are delivered on a separate channel, so tasks that don't handle a message can ignore it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlMessage {
+    /// Finish the current output segment and continue writing into a new one. To keep each
+    /// segment independently decodable, sinks should start the new segment on a keyframe
+    /// (e.g. by asking the encoder to emit one), rather than cutting mid-GOP.
+    Rotate,
+    /// Release the device and stop reading from it until [`ControlMessage::Resume`], see
+    /// [`PauseMode::Suspend`]. Items already captured should still be sent on.
+    Suspend,
+    /// Re-acquire the device released for [`ControlMessage::Suspend`] and carry on capturing,
+    /// within [`MAX_RESUME_LATENCY`].
+    Resume,
+    /// Produce this many items per second from now on, e.g. a lower frame rate to save
+    /// bandwidth, without restarting. Sent to a single source with
+    /// [`Pipeline::set_source_rate`](super::Pipeline::set_source_rate), which checks it's
+    /// within [`MAX_SOURCE_RATE`]. Sources that can't change their rate log that they're
+    /// ignoring it.
+    SetRate(f64),
+    /// Write out anything buffered internally, e.g. to the file on disk, so a crash loses at
+    /// most what was buffered since. Sent periodically by pipelines built with
+    /// [`PipelineBuilder::with_flush_interval`](super::builder::PipelineBuilder::with_flush_interval).
+    /// Sinks that don't buffer ignore it.
+    Flush,
+}
+
+/// The highest rate [`Pipeline::set_source_rate`](super::Pipeline::set_source_rate)
+/// accepts, in items per second.
+pub const MAX_SOURCE_RATE: f64 = 1000.0;
+
+/// How long a suspended source may take to produce items again once resumed, e.g. to
+/// reopen a device. Sources that need longer to re-acquire their device shouldn't suspend.
+pub const MAX_RESUME_LATENCY: Duration = Duration::from_millis(500);
+
+/// How [`Pipeline::pause`](super::Pipeline::pause) pauses the pipeline.
+///
+/// Either way, items that are already queued stay queued and are processed as usual, and
+/// as the clock is stopped the time spent paused doesn't show up in the timestamps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PauseMode {
+    /// Only stop the clock. Sources keep their devices open and reading, so resuming is
+    /// immediate but a paused pipeline still draws power.
+    #[default]
+    ClockOnly,
+    /// Also send sources [`ControlMessage::Suspend`], so they release their devices until
+    /// resumed, e.g. for long idle periods on battery. Resuming may then take up to
+    /// [`MAX_RESUME_LATENCY`]. Sources that can't suspend ignore the message and pause as
+    /// with [`PauseMode::ClockOnly`].
+    Suspend,
+}
+
+/// Identifies one [`Pipeline::send_control_ack`](super::Pipeline::send_control_ack) call.
+pub type CorrelationId = u64;
+
+/// How a task answered a message sent with acks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AckReply {
+    Handled,
+    Ignored,
+    Dropped,
+}
+
+/// Confirms to the sender of a control message that it has been handled, see
+/// [`ControlMessages::try_recv_acked`]. Messages sent without waiting for acks come with
+/// an ack that does nothing. Dropping an ack without calling [`Self::ack`] or
+/// [`Self::ignore`] makes the sender give up on the task straight away rather than at its
+/// timeout.
+#[derive(Debug)]
+pub struct ControlAck(
+    Option<(
+        CorrelationId,
+        String,
+        Sender<(CorrelationId, String, AckReply)>,
+    )>,
+);
+
+impl ControlAck {
+    /// The id of the call waiting for this ack, or `None` if nothing is waiting.
+    pub fn id(&self) -> Option<CorrelationId> {
+        self.0.as_ref().map(|(id, _, _)| *id)
+    }
+
+    pub fn ack(mut self) {
+        self.reply(AckReply::Handled);
+    }
+
+    /// Tells the sender that the message doesn't apply to this task, e.g.
+    /// [`ControlMessage::SetRate`] for a source that can't change its rate. That counts as
+    /// handled when waiting for acks, but not as having done anything, so e.g. a source
+    /// that ignores [`ControlMessage::Suspend`] isn't expected to go quiet.
+    pub fn ignore(mut self) {
+        self.reply(AckReply::Ignored);
+    }
+
+    fn reply(&mut self, reply: AckReply) {
+        if let Some((id, task, acks)) = self.0.take() {
+            let _ = acks.send((id, task, reply));
+        }
+    }
+}
+
+impl Drop for ControlAck {
+    fn drop(&mut self) {
+        self.reply(AckReply::Dropped);
+    }
+}
+
+/// Which of the tasks that ack their messages a message sent with acks goes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum AckAudience {
+    All,
+    /// The sources that opted in with
+    /// [`PipelineSourceTask::acks_control_messages`](super::task::PipelineSourceTask::acks_control_messages).
+    Sources,
+    /// The tasks subscribed with
+    /// [`PipelineBuilder::control_messages`](super::builder::PipelineBuilder::control_messages),
+    /// which are usually sinks.
+    Subscribers,
+}
+
+/// How the tasks answered a message sent with [`ControlBroadcast::collect_acks`]. Tasks
+/// that ignored it are in neither list.
+#[derive(Debug, Default)]
+pub(super) struct Acks {
+    pub handled: Vec<String>,
+    /// Tasks that dropped their ack, didn't answer in time, or never received the message
+    /// as their message queue was full.
+    pub failed: Vec<String>,
+}
+
+#[derive(Debug)]
+struct Delivery {
+    message: ControlMessage,
+    ack: ControlAck,
+}
+
+pub struct ControlMessages {
+    receiver: Receiver<Delivery>,
+}
+
+impl ControlMessages {
+    /// Takes the next message, acknowledging it on receipt for tasks that don't ack
+    /// their messages once handled.
+    pub fn try_recv(&self) -> Option<ControlMessage> {
+        let (message, ack) = self.try_recv_acked()?;
+        ack.ack();
+        Some(message)
+    }
+
+    /// Takes the next message along with its ack, which the task calls once the message
+    /// has been handled, e.g. once a flush has completed.
+    pub fn try_recv_acked(&self) -> Option<(ControlMessage, ControlAck)> {
+        let delivery = self.receiver.try_recv().ok()?;
+        Some((delivery.message, delivery.ack))
+    }
+}
+
 pub struct PipelineControlSignal {
     last_value: Option<Control>,
     receiver: Receiver<Control>,
+    messages: ControlMessages,
 }
 
 impl PipelineControlSignal {
+    pub fn try_message(&self) -> Option<ControlMessage> {
+        self.messages.try_recv()
+    }
+
+    /// See [`ControlMessages::try_recv_acked`].
+    pub fn try_message_acked(&self) -> Option<(ControlMessage, ControlAck)> {
+        self.messages.try_recv_acked()
+    }
+
+    /// Blocks a suspended task until [`ControlMessage::Resume`], ignoring any other message
+    /// that arrives meanwhile apart from another [`ControlMessage::Suspend`], which is
+    /// acknowledged as the task is suspended already. Returns false instead if the pipeline
+    /// shuts down first.
+    pub fn wait_for_resume(&mut self) -> bool {
+        loop {
+            let event = flume::Selector::new()
+                .recv(&self.messages.receiver, Err)
+                .recv(&self.receiver, Ok)
+                .wait();
+
+            match event {
+                Err(Ok(Delivery { message, ack })) => match message {
+                    ControlMessage::Resume => {
+                        ack.ack();
+                        return true;
+                    }
+                    ControlMessage::Suspend => ack.ack(),
+                    _ => ack.ignore(),
+                },
+                Err(Err(_)) | Ok(Ok(Control::Shutdown)) | Ok(Err(_)) => return false,
+                Ok(Ok(control)) => self.last_value = Some(control),
+            }
+        }
+    }
+
     pub fn last(&mut self) -> Option<Control> {
         self.blocking_last_if(false)
     }
@@ -24,6 +226,57 @@ impl PipelineControlSignal {
         self.blocking_last_if(true)
     }
 
+    /// Waits for the next control signal without blocking the thread, for tasks that drive
+    /// async code. Returns `None` once the pipeline is gone.
+    pub async fn changed(&mut self) -> Option<Control> {
+        self.last_value = self.receiver.recv_async().await.ok();
+        self.last_value
+    }
+
+    /// The latest control signal without ever blocking, for tasks that mostly wait on
+    /// their input rather than the control signal.
+    pub(super) fn peek(&mut self) -> Option<Control> {
+        match self.receiver.try_recv() {
+            Ok(control) => self.last_value = Some(control),
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => self.last_value = None,
+        }
+
+        self.last_value
+    }
+
+    /// Blocks until `demand` receives, keeping any control signal that arrives meanwhile for
+    /// the task. Returns false instead if the pipeline shuts down first.
+    pub(super) fn wait_for_demand(&mut self, demand: &Receiver<()>) -> bool {
+        let mut demand_open = true;
+
+        loop {
+            let control = if demand_open {
+                let event = flume::Selector::new()
+                    .recv(demand, |demand| Err(demand.is_ok()))
+                    .recv(&self.receiver, Ok)
+                    .wait();
+
+                match event {
+                    Ok(control) => control,
+                    Err(true) => return true,
+                    // Nobody can demand the output anymore, so just wait for the shutdown.
+                    Err(false) => {
+                        demand_open = false;
+                        continue;
+                    }
+                }
+            } else {
+                self.receiver.recv()
+            };
+
+            match control {
+                Ok(Control::Shutdown) | Err(_) => return false,
+                Ok(control) => self.last_value = Some(control),
+            }
+        }
+    }
+
     pub fn blocking_last_if(&mut self, should_block: bool) -> Option<Control> {
         match self.last_value {
             Some(Control::Play) if !should_block => {
@@ -50,26 +303,291 @@ impl PipelineControlSignal {
     }
 }
 
+/// Feeds a [`PipelineControlSignal`] of its own from another one, for a task that runs one
+/// of several tasks under its name, see
+/// [`PipelineBuilder::spawn_source_with_fallback`](super::builder::PipelineBuilder::spawn_source_with_fallback).
+/// Each of them gets a signal before it's known which one will use it, and only the
+/// relay of the one that does is run.
+pub(super) struct ControlRelay {
+    controls: Sender<Control>,
+    messages: Sender<Delivery>,
+}
+
+impl ControlRelay {
+    /// Sized like the channels of [`ControlBroadcast::add_listener`].
+    pub fn new() -> (Self, PipelineControlSignal) {
+        let (controls, receiver) = flume::bounded(1);
+        let (messages, messages_rx) = flume::bounded(8);
+
+        let signal = PipelineControlSignal {
+            last_value: None,
+            receiver,
+            messages: ControlMessages {
+                receiver: messages_rx,
+            },
+        };
+
+        (Self { controls, messages }, signal)
+    }
+
+    /// Passes everything `from` receives on until `until` disconnects, which is when the
+    /// task on the receiving end has stopped, or until the pipeline is gone. Like with a
+    /// broadcast, messages the task isn't keeping up with are dropped.
+    pub fn run(self, from: &mut PipelineControlSignal, until: &Receiver<()>) {
+        loop {
+            let event = flume::Selector::new()
+                .recv(until, |_| None)
+                .recv(&from.messages.receiver, |delivery| Some(Err(delivery)))
+                .recv(&from.receiver, |control| Some(Ok(control)))
+                .wait();
+
+            match event {
+                Some(Ok(Ok(control))) => {
+                    from.last_value = Some(control);
+                    if self.controls.send(control).is_err() {
+                        break;
+                    }
+                }
+                Some(Err(Ok(delivery))) => {
+                    if let Err(TrySendError::Disconnected(_)) = self.messages.try_send(delivery) {
+                        break;
+                    }
+                }
+                Some(Ok(Err(_)) | Err(Err(_))) | None => break,
+            }
+        }
+    }
+
+    /// Asks the task on the receiving end to stop, e.g. one that is being given up on,
+    /// without waiting for it.
+    pub fn shutdown(self) {
+        let _ = self.controls.try_send(Control::Shutdown);
+    }
+}
+
 /// An extremely naive broadcast channel. Sends values synchronously to all receivers,
 /// might block if one receiver takes too long to receive value.
 #[derive(Debug, Default, Clone)]
 pub(super) struct ControlBroadcast {
     listeners: IndexMap<String, Sender<Control>>,
+    messages: IndexMap<String, Sender<Delivery>>,
+    /// The listeners that read their messages and ack them, which are the only ones
+    /// [`Self::collect_acks`] waits for. Tasks that never read their messages would
+    /// otherwise always time out. Split up by [`AckAudience`].
+    acking_sources: IndexSet<String>,
+    subscribers: IndexSet<String>,
+    next_correlation_id: Arc<AtomicU64>,
+    /// The listeners of broadcasts linked with [`Self::link`], which get the controls and
+    /// messages sent to every listener but aren't addressable by name.
+    linked_listeners: Vec<Sender<Control>>,
+    linked_messages: Vec<Sender<Delivery>>,
 }
 
 impl ControlBroadcast {
-    pub fn add_listener(&mut self, name: String) -> PipelineControlSignal {
+    /// `acks` is whether the task reads its control messages and acks them, see
+    /// [`PipelineSourceTask::acks_control_messages`](super::task::PipelineSourceTask::acks_control_messages).
+    pub fn add_listener(&mut self, name: String, acks: bool) -> PipelineControlSignal {
         let (sender, receiver) = flume::bounded(1);
-        self.listeners.insert(name, sender);
+        let (messages_tx, messages) = flume::bounded(8);
+
+        if acks && !self.messages.contains_key(&name) {
+            self.acking_sources.insert(name.clone());
+        }
+
+        // A second listener under the same name fails the build, see
+        // `PipelineBuilder::add_listener`, so keep the first rather than cutting off the
+        // task that holds it.
+        self.listeners.entry(name.clone()).or_insert(sender);
+        self.messages.entry(name).or_insert(messages_tx);
+
         PipelineControlSignal {
             last_value: None,
             receiver,
+            messages: ControlMessages { receiver: messages },
+        }
+    }
+
+    pub fn add_message_listener(&mut self, name: String) -> ControlMessages {
+        let (sender, receiver) = flume::bounded(8);
+
+        // Like in `add_listener`, the first listener under a name is kept. Subscribing to
+        // the messages is what these tasks are for, so they're expected to read and ack them.
+        if let Entry::Vacant(entry) = self.messages.entry(name.clone()) {
+            entry.insert(sender);
+            self.subscribers.insert(name);
+        }
+
+        ControlMessages { receiver }
+    }
+
+    /// Whether a listener named `name` has been added, with either of the methods above.
+    pub fn has_listener(&self, name: &str) -> bool {
+        // Every listener also gets messages.
+        self.messages.contains_key(name)
+    }
+
+    /// Also sends the controls and messages broadcast to every listener to the listeners
+    /// of `other`, see [`Pipeline::link_control`](super::Pipeline::link_control). Only the
+    /// listeners `other` has itself are linked, and those already known are skipped, so
+    /// nothing is delivered twice even when broadcasts are linked both ways.
+    pub fn link(&mut self, other: &ControlBroadcast) {
+        for listener in other.listeners.values() {
+            let mut linked = self.listeners.values().chain(&self.linked_listeners);
+            if !linked.any(|known| known.same_channel(listener)) {
+                self.linked_listeners.push(listener.clone());
+            }
+        }
+
+        for listener in other.messages.values() {
+            let mut linked = self.messages.values().chain(&self.linked_messages);
+            if !linked.any(|known| known.same_channel(listener)) {
+                self.linked_messages.push(listener.clone());
+            }
+        }
+    }
+
+    /// Sends the message to every listener without waiting. Listeners that aren't keeping up
+    /// with their messages (usually because they ignore them) miss it. Returns whether any
+    /// listener is still connected.
+    pub fn message(&self, message: ControlMessage) -> bool {
+        let mut connected = false;
+
+        for listener in self.messages.values().chain(&self.linked_messages) {
+            let delivery = Delivery {
+                message,
+                ack: ControlAck(None),
+            };
+
+            match listener.try_send(delivery) {
+                Err(TrySendError::Disconnected(_)) => {}
+                _ => connected = true,
+            }
+        }
+
+        connected
+    }
+
+    /// Sends the message to the listener named `task` without waiting. Fails with
+    /// [`MediaError::UnknownTask`] if there is no such listener or it's gone, and with
+    /// [`MediaError::ControlQueueFull`] if it isn't keeping up with its messages.
+    pub fn message_to(&self, task: &str, message: ControlMessage) -> Result<(), MediaError> {
+        let Some(listener) = self.messages.get(task) else {
+            return Err(MediaError::UnknownTask(task.to_string()));
+        };
+
+        let delivery = Delivery {
+            message,
+            ack: ControlAck(None),
+        };
+
+        match listener.try_send(delivery) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Disconnected(_)) => Err(MediaError::UnknownTask(task.to_string())),
+            Err(TrySendError::Full(_)) => Err(MediaError::ControlQueueFull(task.to_string())),
+        }
+    }
+
+    /// Sends the message to every listener that acks its messages and waits for all of them
+    /// to acknowledge it, failing with [`MediaError::ControlAckTimeout`] naming the ones that
+    /// didn't in time. A listener whose message queue is full never receives the message, so
+    /// it fails too. The other listeners aren't sent the message at all.
+    pub async fn message_acked(
+        &self,
+        message: ControlMessage,
+        timeout: Duration,
+    ) -> Result<(), MediaError> {
+        let acks = self.collect_acks(AckAudience::All, message, timeout).await;
+
+        if !acks.failed.is_empty() {
+            return Err(MediaError::ControlAckTimeout(acks.failed));
+        }
+
+        Ok(())
+    }
+
+    /// Sends the message to the listeners in `audience` that ack their messages, and waits
+    /// up to `timeout` for each of them to answer.
+    pub async fn collect_acks(
+        &self,
+        audience: AckAudience,
+        message: ControlMessage,
+        timeout: Duration,
+    ) -> Acks {
+        let id = self.next_correlation_id.fetch_add(1, Ordering::Relaxed);
+        let (acks_tx, replies) = flume::unbounded();
+        let mut acks = Acks::default();
+        let mut pending = vec![];
+
+        let addressed = self.messages.iter().filter(|(name, _)| match audience {
+            AckAudience::All => {
+                self.acking_sources.contains(*name) || self.subscribers.contains(*name)
+            }
+            AckAudience::Sources => self.acking_sources.contains(*name),
+            AckAudience::Subscribers => self.subscribers.contains(*name),
+        });
+
+        for (name, listener) in addressed {
+            let delivery = Delivery {
+                message,
+                ack: ControlAck(Some((id, name.clone(), acks_tx.clone()))),
+            };
+
+            match listener.try_send(delivery) {
+                Err(TrySendError::Disconnected(_)) => {}
+                Err(TrySendError::Full(_)) => acks.failed.push(name.clone()),
+                Ok(()) => pending.push(name.clone()),
+            }
         }
+
+        // Once every ack is gone, waiting any longer is pointless.
+        drop(acks_tx);
+
+        let deadline = tokio::time::Instant::now() + timeout;
+
+        while !pending.is_empty() {
+            let (task, reply) = match tokio::time::timeout_at(deadline, replies.recv_async()).await
+            {
+                Ok(Ok((reply_id, task, reply))) if reply_id == id => (task, reply),
+                Ok(Ok(_)) => continue,
+                Ok(Err(_)) | Err(_) => break,
+            };
+
+            pending.retain(|name| name != &task);
+            match reply {
+                AckReply::Handled => acks.handled.push(task),
+                AckReply::Ignored => {}
+                AckReply::Dropped => acks.failed.push(task),
+            }
+        }
+
+        acks.failed.extend(pending);
+        acks
     }
 
     pub async fn broadcast(&mut self, value: Control) {
-        for (_, listener) in self.listeners.iter() {
+        for listener in self.listeners.values().chain(&self.linked_listeners) {
             let _ = listener.send_async(value).await;
         }
     }
 }
+
+/// Sends `message` to every listener once per `period`, until all of them have gone away.
+pub(super) fn spawn_periodic_message(
+    control: ControlBroadcast,
+    message: ControlMessage,
+    period: Duration,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        // The first tick completes immediately.
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            if !control.message(message) {
+                break;
+            }
+        }
+    });
+}
@@ -0,0 +1,156 @@
+//! Tracking of the OS devices held by pipeline tasks, so they're released on every exit path.
+//!
+//! A source acquiring an exclusive device (a camera, a microphone) calls [`hold_device`] with
+//! a closure that releases it. The release runs when the returned [`DeviceGuard`] is dropped,
+//! and if the guard is leaked or the task panics before dropping it, the pipeline runs the
+//! release itself once the task's thread is done. This avoids devices staying locked
+//! ("camera in use") after a task crashes.
+
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+
+use indexmap::IndexMap;
+use tracing::warn;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeldDevice {
+    pub task: String,
+    pub device: String,
+}
+
+struct Held {
+    device: HeldDevice,
+    release: Box<dyn FnOnce() + Send>,
+}
+
+#[derive(Clone, Default)]
+pub(super) struct DeviceRegistry {
+    held: Arc<Mutex<IndexMap<u64, Held>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl DeviceRegistry {
+    pub fn held_devices(&self) -> Vec<HeldDevice> {
+        self.lock()
+            .values()
+            .map(|held| held.device.clone())
+            .collect()
+    }
+
+    /// Releases whatever the task didn't release itself.
+    pub fn release_task(&self, task: &str) {
+        let leftover = {
+            let mut held = self.lock();
+            let ids = held
+                .iter()
+                .filter(|(_, held)| held.device.task == task)
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>();
+
+            ids.into_iter()
+                .filter_map(|id| held.shift_remove(&id))
+                .collect::<Vec<_>>()
+        };
+
+        for held in leftover {
+            warn!(
+                "Task '{task}' exited while holding device '{}', releasing it",
+                held.device.device
+            );
+            (held.release)();
+        }
+    }
+
+    fn register(&self, device: HeldDevice, release: Box<dyn FnOnce() + Send>) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.lock().insert(id, Held { device, release });
+        id
+    }
+
+    fn release(&self, id: u64) {
+        // Taken out first so the release runs without the lock held.
+        let held = self.lock().shift_remove(&id);
+
+        if let Some(held) = held {
+            (held.release)();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, IndexMap<u64, Held>> {
+        // Releasing devices has to keep working after a task panicked while holding the lock.
+        self.held.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+thread_local! {
+    static CURRENT_TASK: RefCell<Option<(String, DeviceRegistry)>> = const { RefCell::new(None) };
+}
+
+/// The name of the task running on the current thread, if it's a pipeline task.
+pub(super) fn current_task() -> Option<String> {
+    CURRENT_TASK.with(|current| Some(current.borrow().as_ref()?.0.clone()))
+}
+
+/// Makes [`hold_device`] calls on the current thread register with the task, and releases
+/// anything the task still holds when dropped, including while unwinding from a panic.
+pub(super) struct TaskDeviceScope {
+    task: String,
+    registry: DeviceRegistry,
+}
+
+impl TaskDeviceScope {
+    pub fn enter(task: String, registry: DeviceRegistry) -> Self {
+        CURRENT_TASK.with(|current| {
+            *current.borrow_mut() = Some((task.clone(), registry.clone()));
+        });
+
+        Self { task, registry }
+    }
+}
+
+impl Drop for TaskDeviceScope {
+    fn drop(&mut self) {
+        CURRENT_TASK.with(|current| current.borrow_mut().take());
+        self.registry.release_task(&self.task);
+    }
+}
+
+/// Releases a device when dropped. See the [module docs](self).
+#[must_use = "the device is released as soon as the guard is dropped"]
+pub struct DeviceGuard {
+    id: u64,
+    registry: DeviceRegistry,
+}
+
+impl Drop for DeviceGuard {
+    fn drop(&mut self) {
+        self.registry.release(self.id);
+    }
+}
+
+/// Registers a device held by the current task, running `release` when the returned guard
+/// is dropped or, at the latest, when the task exits. Outside a pipeline task the guard
+/// still releases the device, but the device isn't reported by the pipeline.
+pub fn hold_device(
+    device: impl Into<String>,
+    release: impl FnOnce() + Send + 'static,
+) -> DeviceGuard {
+    let (task, registry) = CURRENT_TASK
+        .with(|current| current.borrow().clone())
+        .unwrap_or_default();
+
+    let id = registry.register(
+        HeldDevice {
+            task,
+            device: device.into(),
+        },
+        Box::new(release),
+    );
+
+    DeviceGuard { id, registry }
+}
@@ -0,0 +1,153 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use indexmap::IndexMap;
+
+use crate::pipeline::{
+    completion::{Completion, CompletionReason, TaskOutcome, TaskOutcomes},
+    lifecycle::{LifecycleEvent, LifecycleEvents},
+    metrics::{EdgeSnapshot, EdgeTopology, PipelineMetrics},
+    PipelineClock,
+};
+
+/// Everything the pipeline can tell about itself at one point in time, for investigating
+/// e.g. a stuck recording on a user's machine, see
+/// [`Pipeline::diagnostics`](super::Pipeline::diagnostics). Formats as a multi-line dump.
+#[derive(Debug, Clone)]
+pub struct PipelineDiagnostics {
+    /// How long the pipeline has been running, or ran for once it has shut down.
+    pub uptime: Duration,
+    pub completion: Option<CompletionReason>,
+    /// Every task, in the order they were added, with how it ended so far.
+    pub tasks: IndexMap<String, TaskOutcome>,
+    pub edges: IndexMap<String, EdgeSnapshot>,
+    /// Which task feeds and which reads each edge, see
+    /// [`PipelineMetrics::topology`].
+    pub topology: IndexMap<String, EdgeTopology>,
+    pub clock_skew: Option<Duration>,
+    /// The latest lifecycle events, oldest first.
+    pub recent_events: Vec<LifecycleEvent>,
+}
+
+impl fmt::Display for PipelineDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Pipeline up for {:.2}s, ", self.uptime.as_secs_f64())?;
+        match &self.completion {
+            Some(reason) => writeln!(f, "stopped: {reason:?}")?,
+            None => writeln!(f, "running")?,
+        }
+        match self.clock_skew {
+            Some(skew) => writeln!(f, "Clock skew: {skew:?}")?,
+            None => writeln!(f, "Clock skew: unknown")?,
+        }
+
+        writeln!(f, "Tasks:")?;
+        for (task, outcome) in &self.tasks {
+            match outcome {
+                TaskOutcome::Running => writeln!(f, "  {task}: running")?,
+                TaskOutcome::Finished => writeln!(f, "  {task}: finished")?,
+                TaskOutcome::Stopped => writeln!(f, "  {task}: stopped")?,
+                TaskOutcome::Failed(error) => writeln!(f, "  {task}: failed ({error})")?,
+            }
+        }
+
+        writeln!(f, "Edges:")?;
+        for (name, edge) in &self.edges {
+            let topology = self.topology.get(name).cloned().unwrap_or_default();
+            writeln!(
+                f,
+                "  {name} ({topology}): {} queued (peak {}), {} sent, {} dropped (longest run {}, {} bursts), {} skipped late, {} skipped warming up",
+                edge.depth,
+                edge.peak_depth,
+                edge.sent,
+                edge.dropped,
+                edge.longest_drop_run,
+                edge.drop_bursts,
+                edge.skipped_late,
+                edge.skipped_warmup
+            )?;
+        }
+
+        write!(f, "Recent events:")?;
+        for event in &self.recent_events {
+            write!(f, "\n  {event:?}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The parts of a pipeline that [`PipelineDiagnostics`] are taken from, shared so that they
+/// can be taken from outside the pipeline, e.g. by a signal handler.
+pub(super) struct DiagnosticsSource<T> {
+    pub clock: T,
+    pub metrics: PipelineMetrics,
+    pub completion: Completion,
+    pub outcomes: TaskOutcomes,
+    pub lifecycle: LifecycleEvents,
+    pub started_at: Instant,
+    pub finished_at: Option<Instant>,
+}
+
+impl<T: PipelineClock> DiagnosticsSource<T> {
+    pub fn capture(&self) -> PipelineDiagnostics {
+        let finished_at = self.finished_at.unwrap_or_else(Instant::now);
+
+        PipelineDiagnostics {
+            uptime: finished_at - self.started_at,
+            completion: self.completion.reason(),
+            tasks: self.outcomes.report().tasks,
+            edges: self.metrics.snapshot().edges,
+            topology: self.metrics.topology(),
+            clock_skew: self.clock.wall_clock_skew(),
+            recent_events: self.lifecycle.recent(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::{
+        completion::TaskOutcome, lifecycle::LifecycleEvent, metrics::EdgeTopology, Pipeline,
+        RealTimeClock,
+    };
+
+    #[tokio::test]
+    async fn diagnostics_cover_tasks_edges_and_recent_events() {
+        let (input_tx, input_rx) = flume::bounded(8);
+
+        let (builder, output) = Pipeline::builder(RealTimeClock::<()>::new())
+            .path(input_rx)
+            .map("double", |n: u32| n * 2)
+            .into_receiver();
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        input_tx.send(1).unwrap();
+        output.recv().unwrap();
+
+        let diagnostics = pipeline.diagnostics();
+        assert_eq!(diagnostics.tasks["double"], TaskOutcome::Running);
+        assert_eq!(diagnostics.edges["double"].sent, 1);
+        assert_eq!(
+            diagnostics.topology["double"],
+            EdgeTopology {
+                producer: Some("double".to_string()),
+                consumer: None,
+            }
+        );
+        assert!(diagnostics
+            .recent_events
+            .contains(&LifecycleEvent::TaskReady {
+                task: "double".to_string()
+            }));
+        assert!(diagnostics.to_string().contains("  double: running"));
+        assert!(diagnostics
+            .to_string()
+            .contains("  double (double -> ?): 0 queued"));
+
+        drop(input_tx);
+        pipeline.shutdown().await.unwrap();
+    }
+}
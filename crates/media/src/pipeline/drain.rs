@@ -0,0 +1,101 @@
+use std::any::Any;
+
+use indexmap::IndexMap;
+
+use crate::pipeline::metrics::MetricsSnapshot;
+
+/// Items that were still queued when a graceful shutdown stalled, keyed by edge, see
+/// [`Pipeline::shutdown_graceful_with_leftovers`](super::Pipeline::shutdown_graceful_with_leftovers).
+/// Only edges marked with
+/// [`PipelinePathBuilder::recover_leftovers`](super::builder::PipelinePathBuilder::recover_leftovers)
+/// are included, and only if they had items left.
+#[derive(Default)]
+pub struct Leftovers(pub(super) IndexMap<String, Box<dyn Any + Send>>);
+
+impl Leftovers {
+    /// Takes the items left on `edge`, which carries items of type `T`. Returns `None` if it
+    /// had none left, or isn't an edge of `T`s.
+    pub fn take<T: 'static>(&mut self, edge: &str) -> Option<Vec<T>> {
+        if !self.0.get(edge)?.is::<Vec<T>>() {
+            return None;
+        }
+
+        let items = self.0.shift_remove(edge)?;
+        items.downcast().ok().map(|items| *items)
+    }
+
+    /// The edges that still have items to take.
+    pub fn edges(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// How far a graceful shutdown has got in flushing the items left on each metered edge.
+#[derive(Debug, Clone, Default)]
+pub struct DrainProgress {
+    pub edges: IndexMap<String, EdgeDrain>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EdgeDrain {
+    /// Items queued on the edge when the drain started.
+    pub initial: usize,
+    /// Items still queued on the edge.
+    pub remaining: usize,
+}
+
+impl EdgeDrain {
+    /// Upstream stages keep forwarding while draining, so an edge can temporarily hold
+    /// more than it started with. That is reported as nothing drained yet.
+    pub fn drained(&self) -> usize {
+        self.initial.saturating_sub(self.remaining)
+    }
+}
+
+impl DrainProgress {
+    pub(super) fn between(initial: &MetricsSnapshot, current: &MetricsSnapshot) -> Self {
+        Self {
+            edges: current
+                .edges
+                .iter()
+                .map(|(name, edge)| {
+                    let drain = EdgeDrain {
+                        initial: initial.edges.get(name).map_or(0, |edge| edge.depth),
+                        remaining: edge.depth,
+                    };
+
+                    (name.clone(), drain)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn initial(&self) -> usize {
+        self.edges.values().map(|edge| edge.initial).sum()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.edges.values().map(|edge| edge.remaining).sum()
+    }
+
+    pub fn drained(&self) -> usize {
+        self.initial().saturating_sub(self.remaining())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// The edge holding the most undrained items.
+    pub fn most_backed_up(&self) -> Option<&str> {
+        self.edges
+            .iter()
+            .filter(|(_, edge)| edge.remaining > 0)
+            .max_by_key(|(_, edge)| edge.remaining)
+            .map(|(name, _)| name.as_str())
+    }
+}
@@ -0,0 +1,37 @@
+use std::fmt;
+
+use crate::pipeline::completion::CompletionReason;
+
+/// Something that makes a pipeline unhealthy, see [`HealthReport`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HealthIssue {
+    /// A task finished with an error, which stops the pipeline.
+    TaskFailed { task: String, error: String },
+    /// A task's thread has exited while the pipeline is still meant to be running.
+    TaskExited(String),
+    /// The pipeline has stopped for a reason other than a task failing.
+    Stopped(CompletionReason),
+}
+
+impl fmt::Display for HealthIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TaskFailed { task, error } => write!(f, "task '{task}' failed: {error}"),
+            Self::TaskExited(task) => write!(f, "task '{task}' exited"),
+            Self::Stopped(reason) => write!(f, "pipeline stopped: {reason:?}"),
+        }
+    }
+}
+
+/// The reasons a pipeline is unhealthy, if any,
+/// see [`Pipeline::health_report`](super::Pipeline::health_report).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HealthReport {
+    pub issues: Vec<HealthIssue>,
+}
+
+impl HealthReport {
+    pub fn is_healthy(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use flume::{Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError};
+
+/// The output of a task spawned with
+/// [`PipelineBuilder::spawn_lazy_source`](super::builder::PipelineBuilder::spawn_lazy_source).
+/// The task is launched the first time this is read from, so a branch nobody reads from
+/// never acquires its device.
+pub struct LazyReceiver<T> {
+    receiver: Receiver<T>,
+    demand: Sender<()>,
+}
+
+impl<T> LazyReceiver<T> {
+    pub(super) fn new(receiver: Receiver<T>) -> (Self, Receiver<()>) {
+        let (demand, demand_rx) = flume::bounded(1);
+
+        (Self { receiver, demand }, demand_rx)
+    }
+
+    fn demand(&self) {
+        let _ = self.demand.try_send(());
+    }
+
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.demand();
+        self.receiver.recv()
+    }
+
+    pub async fn recv_async(&self) -> Result<T, RecvError> {
+        self.demand();
+        self.receiver.recv_async().await
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.demand();
+        self.receiver.recv_timeout(timeout)
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.demand();
+        self.receiver.try_recv()
+    }
+
+    /// Launches the task and hands over the underlying receiver, e.g. to start a path
+    /// from it with [`PipelineBuilder::path`](super::builder::PipelineBuilder::path).
+    pub fn into_receiver(self) -> Receiver<T> {
+        self.demand();
+        self.receiver
+    }
+}
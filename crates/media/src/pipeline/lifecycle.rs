@@ -0,0 +1,143 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use flume::{Receiver, Sender, TrySendError};
+use tracing::{trace, warn};
+
+use crate::pipeline::completion::TaskOutcome;
+
+/// Events that can't be handed to the observer yet are dropped beyond this many.
+const LIFECYCLE_EVENT_CAPACITY: usize = 64;
+/// How many of the latest events are kept for [`LifecycleEvents::recent`].
+const RECENT_LIFECYCLE_EVENTS: usize = 32;
+
+/// A point in the life of a pipeline, see
+/// [`PipelineBuilder::with_lifecycle_observer`](super::builder::PipelineBuilder::with_lifecycle_observer).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LifecycleEvent {
+    /// The task's thread has started running it.
+    TaskLaunched {
+        task: String,
+    },
+    /// The task signalled ready during `build`.
+    TaskReady {
+        task: String,
+    },
+    TaskStopped {
+        task: String,
+        reason: TaskOutcome,
+    },
+    /// All tasks have been joined, or detached if they failed to drain.
+    PipelineShutdown,
+}
+
+/// The streams handed out by [`LifecycleEvents::subscribe`], along with whether each is
+/// currently dropping events for falling behind.
+#[derive(Debug, Default)]
+struct Subscribers {
+    senders: Vec<(Sender<LifecycleEvent>, bool)>,
+    /// Set once the pipeline has shut down, after which no more events are expected.
+    closed: bool,
+    /// The latest events, for [`LifecycleEvents::recent`].
+    recent: VecDeque<LifecycleEvent>,
+}
+
+/// Hands events to the observer on a thread of its own, so that the tasks never wait on
+/// it. The thread exits once the builder, the pipeline and all of its tasks are gone.
+/// Subscribers are shared by every clone, so they get the events of all tasks.
+#[derive(Debug, Clone, Default)]
+pub(super) struct LifecycleEvents {
+    observer: Option<Sender<LifecycleEvent>>,
+    subscribers: Arc<Mutex<Subscribers>>,
+}
+
+impl LifecycleEvents {
+    /// Replaces the observer, keeping the subscribers.
+    pub fn with_observer(&self, observer: impl Fn(LifecycleEvent) + Send + Sync + 'static) -> Self {
+        let (events, observed) = flume::bounded(LIFECYCLE_EVENT_CAPACITY);
+
+        thread::spawn(move || {
+            for event in observed.iter() {
+                observer(event);
+            }
+        });
+
+        Self {
+            observer: Some(events),
+            subscribers: self.subscribers.clone(),
+        }
+    }
+
+    /// A channel of the events from now on, which ends after
+    /// [`LifecycleEvent::PipelineShutdown`]. A subscriber that falls behind by more than
+    /// [`LIFECYCLE_EVENT_CAPACITY`] events misses the newer ones until it catches up.
+    pub fn subscribe(&self) -> Receiver<LifecycleEvent> {
+        let (events, subscribed) = flume::bounded(LIFECYCLE_EVENT_CAPACITY);
+        let mut subscribers = self.subscribers.lock().unwrap();
+
+        if !subscribers.closed {
+            subscribers.senders.push((events, false));
+        }
+
+        subscribed
+    }
+
+    /// The latest events, oldest first, e.g. for [`super::Pipeline::diagnostics`].
+    pub fn recent(&self) -> Vec<LifecycleEvent> {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .recent
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    pub fn emit(&self, event: LifecycleEvent) {
+        self.notify_subscribers(&event);
+
+        let Some(events) = &self.observer else {
+            return;
+        };
+
+        if let Err(TrySendError::Full(event)) = events.try_send(event) {
+            trace!("Lifecycle observer is falling behind, dropping {event:?}");
+        }
+    }
+
+    fn notify_subscribers(&self, event: &LifecycleEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+
+        if subscribers.recent.len() == RECENT_LIFECYCLE_EVENTS {
+            subscribers.recent.pop_front();
+        }
+        subscribers.recent.push_back(event.clone());
+
+        subscribers
+            .senders
+            .retain_mut(|(events, lagging)| match events.try_send(event.clone()) {
+                Ok(()) => {
+                    *lagging = false;
+                    true
+                }
+                Err(TrySendError::Full(event)) => {
+                    if !*lagging {
+                        warn!("Lifecycle event subscriber is falling behind, dropping {event:?}");
+                    }
+                    *lagging = true;
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+
+        // Dropping the senders ends the subscribers' streams once they have taken the
+        // events already queued.
+        if *event == LifecycleEvent::PipelineShutdown {
+            subscribers.closed = true;
+            subscribers.senders.clear();
+        }
+    }
+}
@@ -0,0 +1,99 @@
+/// Declares a linear pipeline, expanding to the equivalent [`PipelineBuilder`] calls.
+///
+/// Each stage is a path method called without its name, optionally prefixed with
+/// `"name" =>`. Unnamed stages are numbered in order, e.g. `map_1`, `filter_2`. Options in
+/// brackets after a stage configure its output edge. The result is whatever the last stage
+/// returns: a [`PipelineBuilder`] ready for `.build()` when the chain ends in a sink, or a
+/// [`PipelinePathBuilder`] otherwise.
+///
+/// ```ignore
+/// let builder = pipeline! {
+///     clock: RealTimeClock::<()>::new(),
+///     input: frames_rx,
+///     map(|frame| scale(frame)) [queue_size = 8, backpressure = Backpressure::Independent],
+///     "drop_black" => filter(|frame| !frame.is_black()),
+///     connect_to_receiver(encoder_tx),
+/// };
+/// ```
+///
+/// [`PipelineBuilder`]: crate::pipeline::builder::PipelineBuilder
+/// [`PipelinePathBuilder`]: crate::pipeline::builder::PipelinePathBuilder
+#[macro_export]
+macro_rules! pipeline {
+    (clock: $clock:expr, input: $input:expr, $($stages:tt)*) => {{
+        #[allow(unused_mut, unused_variables)]
+        let mut index = 0usize;
+        let path = $crate::pipeline::Pipeline::builder($clock).path($input);
+        $crate::pipeline!(@stages index, path, $($stages)*)
+    }};
+    (@stages $index:ident, $path:expr, $name:literal => $stage:ident ($($arg:expr),* $(,)?)
+        $([$($option:ident = $value:expr),* $(,)?])? $(, $($rest:tt)*)?) => {{
+        let path = $crate::pipeline!(@options $path $(, $($option = $value),*)?);
+        let path = path.$stage($name, $($arg),*);
+        $crate::pipeline!(@stages $index, path, $($($rest)*)?)
+    }};
+    (@stages $index:ident, $path:expr, $stage:ident ($($arg:expr),* $(,)?)
+        $([$($option:ident = $value:expr),* $(,)?])? $(, $($rest:tt)*)?) => {{
+        $index += 1;
+        let name = format!("{}_{}", stringify!($stage), $index);
+        let path = $crate::pipeline!(@options $path $(, $($option = $value),*)?);
+        let path = path.$stage(name, $($arg),*);
+        $crate::pipeline!(@stages $index, path, $($($rest)*)?)
+    }};
+    (@stages $index:ident, $path:expr, ) => {
+        $path
+    };
+    (@options $path:expr) => {
+        $path
+    };
+    (@options $path:expr, queue_size = $value:expr $(, $($rest:tt)*)?) => {
+        $crate::pipeline!(@options $path.with_queue_size($value) $(, $($rest)*)?)
+    };
+    (@options $path:expr, backpressure = $value:expr $(, $($rest:tt)*)?) => {
+        $crate::pipeline!(@options $path.with_backpressure($value) $(, $($rest)*)?)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::pipeline::{stages::Backpressure, RealTimeClock};
+
+    #[tokio::test]
+    async fn stages_are_named_and_configured_in_order() {
+        let (input_tx, input_rx) = flume::bounded(16);
+
+        let (builder, _output) = crate::pipeline! {
+            clock: RealTimeClock::<()>::new(),
+            input: input_rx,
+            map(|n: u32| n + 1),
+            "double" => map(|n: u32| n * 2),
+            filter(|_: &u32| true) [queue_size = 3, backpressure = Backpressure::Independent],
+        }
+        .into_receiver();
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        // Nothing reads the output, so the last edge fills up to its queue size and drops
+        // the rest instead of blocking.
+        for n in 0..10 {
+            input_tx.send(n).unwrap();
+        }
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while pipeline.metrics().edges["filter_2"].dropped < 7 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("the last edge never dropped the items beyond its queue size");
+
+        let metrics = pipeline.metrics();
+        assert_eq!(
+            metrics.edges.keys().collect::<Vec<_>>(),
+            ["map_1", "double", "filter_2"]
+        );
+        assert_eq!(metrics.edges["filter_2"].depth, 3);
+        drop(input_tx);
+        pipeline.shutdown().await.unwrap();
+    }
+}
@@ -0,0 +1,780 @@
+use std::{
+    any::Any,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use flume::{
+    r#async::RecvFut, Receiver, RecvError, RecvTimeoutError, SendError, SendTimeoutError, Sender,
+    TryRecvError, TrySendError,
+};
+use indexmap::{IndexMap, IndexSet};
+#[cfg(feature = "debug-clones")]
+use std::sync::atomic::AtomicBool;
+use tracing::{info, warn};
+
+use crate::{
+    pipeline::{device::current_task, drain::Leftovers},
+    MediaError,
+};
+
+const DEFAULT_DROP_BURST_THRESHOLD: u64 = 10;
+/// How often a send blocked on a full edge checks whether the consumer is still there, as
+/// the receiver the metrics retain keeps the channel from disconnecting on its own.
+const DISCONNECT_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+/// The longest an async send waits before retrying a full edge.
+const ASYNC_SEND_MAX_BACKOFF: Duration = Duration::from_millis(5);
+/// An edge is warned about once it has had this many times more items cloned onto it than
+/// it forwarded, as the clones that weren't forwarded were made for nothing.
+#[cfg(feature = "debug-clones")]
+const CLONE_WARNING_FACTOR: u64 = 2;
+/// How many clones an edge needs before it's warned about, so the first few items don't
+/// skew the ratio.
+#[cfg(feature = "debug-clones")]
+const CLONE_WARNING_MIN: u64 = 100;
+
+#[derive(Debug, Default)]
+struct EdgeCounters {
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    peak_depth: AtomicUsize,
+    current_drop_run: AtomicU64,
+    longest_drop_run: AtomicU64,
+    drop_bursts: AtomicU64,
+    skipped_late: AtomicU64,
+    skipped_warmup: AtomicU64,
+    burst_threshold: u64,
+    /// The task sending into the edge. Set when the edge is created for a stage, and
+    /// otherwise by the first item sent from a pipeline task.
+    producer: OnceLock<String>,
+    /// The task reading from the edge, if a stage or sink added by the builder reads it.
+    consumer: OnceLock<String>,
+    #[cfg(feature = "debug-clones")]
+    name: String,
+    #[cfg(feature = "debug-clones")]
+    clones: AtomicU64,
+    #[cfg(feature = "debug-clones")]
+    clone_warned: AtomicBool,
+}
+
+struct Edge {
+    counters: Arc<EdgeCounters>,
+    depth: Box<dyn Fn() -> usize + Send + Sync>,
+    /// The retained `Arc<Receiver<T>>`, for draining the edge.
+    receiver: Box<dyn Any + Send + Sync>,
+    /// Takes the queued items as a boxed `Vec<T>`, for edges marked with
+    /// [`PipelineMetrics::recover_leftovers`]. `None` if nothing is queued.
+    leftovers: Option<Box<dyn Fn() -> Option<Box<dyn Any + Send>> + Send + Sync>>,
+}
+
+impl Edge {
+    /// Whether `receiver` reads from this edge.
+    fn reads<T: Send + 'static>(&self, receiver: &Receiver<T>) -> bool {
+        self.receiver
+            .downcast_ref::<Arc<Receiver<T>>>()
+            .is_some_and(|retained| retained.same_channel(receiver))
+    }
+}
+
+/// Counters for every edge created through [`PipelineMetrics::edge`]. These are always
+/// collected, as they only cost a few atomic operations per item.
+#[derive(Clone)]
+pub struct PipelineMetrics {
+    edges: Arc<Mutex<IndexMap<String, Edge>>>,
+    drop_burst_threshold: u64,
+}
+
+impl Default for PipelineMetrics {
+    fn default() -> Self {
+        Self {
+            edges: Default::default(),
+            drop_burst_threshold: DEFAULT_DROP_BURST_THRESHOLD,
+        }
+    }
+}
+
+impl PipelineMetrics {
+    /// A run of more than this many consecutive drops on an edge counts as a drop burst.
+    /// Applies to edges created afterwards.
+    pub fn set_drop_burst_threshold(&mut self, threshold: u64) {
+        self.drop_burst_threshold = threshold;
+    }
+
+    /// Creates a bounded channel whose sender records how many items went through it.
+    pub fn edge<T: Send + 'static>(
+        &self,
+        name: impl Into<String>,
+        capacity: usize,
+    ) -> (MeteredSender<T>, Receiver<T>) {
+        let name = name.into();
+        let (inner, receiver) = flume::bounded(capacity);
+        let counters = Arc::new(EdgeCounters {
+            burst_threshold: self.drop_burst_threshold,
+            #[cfg(feature = "debug-clones")]
+            name: name.clone(),
+            ..Default::default()
+        });
+
+        // Hold on to a receiver rather than a sender, so that the metrics don't keep the
+        // channel open for the consumer and can still read the depth once the producer is done.
+        // It's shared rather than cloned, as senders count the receivers to detect disconnects.
+        let retained = Arc::new(receiver.clone());
+        let mut edges = self.edges.lock().unwrap();
+
+        // A second edge with the same name would take over the first one's metrics.
+        debug_assert!(
+            !edges.contains_key(&name),
+            "An edge named {name} has already been created"
+        );
+
+        edges.insert(
+            name,
+            Edge {
+                counters: counters.clone(),
+                depth: Box::new({
+                    let retained = retained.clone();
+                    move || retained.len()
+                }),
+                receiver: Box::new(retained),
+                leftovers: None,
+            },
+        );
+
+        (MeteredSender { inner, counters }, receiver)
+    }
+
+    /// Records `task` as the producer of the edge called `edge`, see [`Self::topology`].
+    pub(super) fn produced_by(&self, edge: &str, task: &str) {
+        if let Some(edge) = self.edges.lock().unwrap().get(edge) {
+            let _ = edge.counters.producer.set(task.to_string());
+        }
+    }
+
+    /// Records `task` as the consumer of the edge `receiver` reads from, see
+    /// [`Self::topology`]. Receivers that aren't fed by a metered edge are left alone.
+    pub(super) fn consumed_by<T: Send + 'static>(&self, receiver: &Receiver<T>, task: &str) {
+        let edges = self.edges.lock().unwrap();
+
+        if let Some(edge) = edges.values().find(|edge| edge.reads(receiver)) {
+            let _ = edge.counters.consumer.set(task.to_string());
+        }
+    }
+
+    /// Which task feeds and which task reads each edge, as far as is known. Tasks the
+    /// caller spawned with a receiver of their own are only known as producers once they
+    /// have sent an item, and never as consumers.
+    pub fn topology(&self) -> IndexMap<String, EdgeTopology> {
+        let edges = self.edges.lock().unwrap();
+
+        edges
+            .iter()
+            .map(|(name, edge)| {
+                let topology = EdgeTopology {
+                    producer: edge.counters.producer.get().cloned(),
+                    consumer: edge.counters.consumer.get().cloned(),
+                };
+
+                (name.clone(), topology)
+            })
+            .collect()
+    }
+
+    /// The edges fed by any of `tasks`, and the edges fed by the tasks reading from those,
+    /// and so on, as far as the [topology](Self::topology) is known.
+    pub(super) fn downstream_of(&self, tasks: &[String]) -> IndexSet<String> {
+        let edges = self.edges.lock().unwrap();
+        let mut tasks = tasks.iter().cloned().collect::<IndexSet<_>>();
+        let mut downstream = IndexSet::new();
+
+        loop {
+            let reached = edges
+                .iter()
+                .filter(|(name, _)| !downstream.contains(*name))
+                .filter(|(_, edge)| {
+                    edge.counters
+                        .producer
+                        .get()
+                        .is_some_and(|producer| tasks.contains(producer))
+                })
+                .map(|(name, edge)| (name.clone(), edge.counters.consumer.get().cloned()))
+                .collect::<Vec<_>>();
+
+            if reached.is_empty() {
+                return downstream;
+            }
+
+            for (name, consumer) in reached {
+                downstream.insert(name);
+                tasks.extend(consumer);
+            }
+        }
+    }
+
+    /// Marks the edge `receiver` reads from, so its queued items are handed back by
+    /// [`Pipeline::shutdown_graceful_with_leftovers`](super::Pipeline::shutdown_graceful_with_leftovers)
+    /// rather than lost. Receivers that aren't fed by a metered edge are left alone, with a
+    /// warning.
+    pub(super) fn recover_leftovers<T: Send + 'static>(&self, receiver: &Receiver<T>) {
+        let mut edges = self.edges.lock().unwrap();
+        let edge = edges.values_mut().find(|edge| edge.reads(receiver));
+
+        let Some(edge) = edge else {
+            warn!("Can't recover leftovers from a path that isn't fed by a metered edge");
+            return;
+        };
+
+        let retained = edge
+            .receiver
+            .downcast_ref::<Arc<Receiver<T>>>()
+            .unwrap()
+            .clone();
+        edge.leftovers = Some(Box::new(move || {
+            let items = retained.try_iter().collect::<Vec<_>>();
+            (!items.is_empty()).then(|| Box::new(items) as Box<dyn Any + Send>)
+        }));
+    }
+
+    /// Takes the items queued on every edge marked with [`Self::recover_leftovers`].
+    pub(super) fn take_leftovers(&self) -> Leftovers {
+        let edges = self.edges.lock().unwrap();
+
+        Leftovers(
+            edges
+                .iter()
+                .filter_map(|(name, edge)| {
+                    let items = edge.leftovers.as_ref().and_then(|take| take())?;
+                    Some((name.clone(), items))
+                })
+                .collect(),
+        )
+    }
+
+    /// Takes the items left on an edge whose senders are all gone, see
+    /// [`Pipeline::drain_edge`](super::Pipeline::drain_edge).
+    pub fn drain<T: Send + 'static>(
+        &self,
+        name: &str,
+    ) -> Result<impl Iterator<Item = T>, MediaError> {
+        let edges = self.edges.lock().unwrap();
+        let receiver = edges
+            .get(name)
+            .and_then(|edge| edge.receiver.downcast_ref::<Arc<Receiver<T>>>())
+            .ok_or_else(|| MediaError::UnknownEdge(name.to_string()))?;
+
+        if !receiver.is_disconnected() {
+            return Err(MediaError::EdgeStillLive(name.to_string()));
+        }
+
+        // With no senders left this ends once the queued items are taken, rather than blocking.
+        Ok(Receiver::clone(receiver).into_iter())
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let edges = self.edges.lock().unwrap();
+
+        MetricsSnapshot {
+            taken_at: Instant::now(),
+            edges: edges
+                .iter()
+                .map(|(name, edge)| {
+                    let counters = &edge.counters;
+
+                    (
+                        name.clone(),
+                        EdgeSnapshot {
+                            sent: counters.sent.load(Ordering::Relaxed),
+                            dropped: counters.dropped.load(Ordering::Relaxed),
+                            depth: (edge.depth)(),
+                            peak_depth: counters.peak_depth.load(Ordering::Relaxed),
+                            longest_drop_run: counters.longest_drop_run.load(Ordering::Relaxed),
+                            drop_bursts: counters.drop_bursts.load(Ordering::Relaxed),
+                            skipped_late: counters.skipped_late.load(Ordering::Relaxed),
+                            skipped_warmup: counters.skipped_warmup.load(Ordering::Relaxed),
+                            #[cfg(feature = "debug-clones")]
+                            debug: EdgeDebugSnapshot {
+                                clones: counters.clones.load(Ordering::Relaxed),
+                            },
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The sending half of a metered edge. Counts every item sent, and every item that
+/// couldn't be sent by [`MeteredSender::try_send`] because the edge was full.
+pub struct MeteredSender<T> {
+    inner: Sender<T>,
+    counters: Arc<EdgeCounters>,
+}
+
+impl<T> Clone for MeteredSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            counters: self.counters.clone(),
+        }
+    }
+}
+
+impl<T> MeteredSender<T> {
+    /// Blocks while the edge is full, until there is room or the consumer is gone.
+    pub fn send(&self, mut item: T) -> Result<(), SendError<T>> {
+        loop {
+            if self.is_disconnected() {
+                return Err(SendError(item));
+            }
+
+            match self.inner.send_timeout(item, DISCONNECT_CHECK_INTERVAL) {
+                Ok(()) => break,
+                Err(SendTimeoutError::Timeout(unsent)) => item = unsent,
+                Err(SendTimeoutError::Disconnected(unsent)) => return Err(SendError(unsent)),
+            }
+        }
+
+        self.record_sent();
+        Ok(())
+    }
+
+    /// Waits while the edge is full, until there is room or the consumer is gone. A pending
+    /// flume send can't be cancelled without losing its item, so this retries with a short
+    /// backoff rather than awaiting one.
+    pub async fn send_async(&self, mut item: T) -> Result<(), SendError<T>> {
+        let mut backoff = Duration::from_millis(1);
+
+        loop {
+            if self.is_disconnected() {
+                return Err(SendError(item));
+            }
+
+            match self.inner.try_send(item) {
+                Ok(()) => break,
+                Err(TrySendError::Full(unsent)) => item = unsent,
+                Err(TrySendError::Disconnected(unsent)) => return Err(SendError(unsent)),
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(ASYNC_SEND_MAX_BACKOFF);
+        }
+
+        self.record_sent();
+        Ok(())
+    }
+
+    /// A full edge counts the item as dropped, so callers that want to retry
+    /// instead of dropping should use [`MeteredSender::send`].
+    pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+        if self.is_disconnected() {
+            return Err(TrySendError::Disconnected(item));
+        }
+
+        match self.inner.try_send(item) {
+            Ok(()) => {
+                self.record_sent();
+                Ok(())
+            }
+            Err(TrySendError::Full(item)) => {
+                self.record_dropped();
+                Err(TrySendError::Full(item))
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// The metrics retain a receiver of their own, so the edge counts as disconnected once
+    /// that is the only one left. Flume doesn't see this as a disconnect, which is why the
+    /// blocking sends check it themselves.
+    pub fn is_disconnected(&self) -> bool {
+        self.inner.receiver_count() <= 1
+    }
+
+    /// Clones `item` to send it on this edge, e.g. for a stage that forks its input. With
+    /// the `debug-clones` feature, the clones are counted in the `debug` section of the
+    /// edge's [`EdgeSnapshot`], and a warning is logged if the edge forwards far
+    /// fewer items than were cloned for it, which points at frames being copied for
+    /// nothing. Without the feature, this is just a clone.
+    pub fn clone_item<I: Clone>(&self, item: &I) -> I {
+        #[cfg(feature = "debug-clones")]
+        self.record_clone();
+
+        item.clone()
+    }
+
+    #[cfg(feature = "debug-clones")]
+    fn record_clone(&self) {
+        let counters = &self.counters;
+        let clones = counters.clones.fetch_add(1, Ordering::Relaxed) + 1;
+        let sent = counters.sent.load(Ordering::Relaxed);
+
+        if clones >= CLONE_WARNING_MIN
+            && clones > sent.saturating_mul(CLONE_WARNING_FACTOR)
+            && !counters.clone_warned.swap(true, Ordering::Relaxed)
+        {
+            warn!(
+                "Edge '{}' had {clones} items cloned for it but only forwarded {sent}",
+                counters.name
+            );
+        }
+    }
+
+    /// Counts an item the producer skipped instead of sending because it was too late to
+    /// be useful, separately from the drops of a full edge.
+    pub fn record_skipped_late(&self) {
+        self.counters.skipped_late.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts an item the producer discarded during a warmup, see
+    /// [`PipelinePathBuilder::warmup`](super::builder::PipelinePathBuilder::warmup).
+    pub fn record_skipped_warmup(&self) {
+        self.counters.skipped_warmup.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn record_dropped(&self) {
+        let counters = &self.counters;
+        counters.dropped.fetch_add(1, Ordering::Relaxed);
+
+        let run = counters.current_drop_run.fetch_add(1, Ordering::Relaxed) + 1;
+        counters.longest_drop_run.fetch_max(run, Ordering::Relaxed);
+        if run == counters.burst_threshold + 1 {
+            counters.drop_bursts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn record_sent(&self) {
+        if self.counters.producer.get().is_none() {
+            if let Some(task) = current_task() {
+                let _ = self.counters.producer.set(task);
+            }
+        }
+
+        self.counters.sent.fetch_add(1, Ordering::Relaxed);
+        self.counters.current_drop_run.store(0, Ordering::Relaxed);
+        self.counters
+            .peak_depth
+            .fetch_max(self.inner.len(), Ordering::Relaxed);
+    }
+}
+
+/// The output of a path, handed out by
+/// [`PipelinePathBuilder::into_receiver`](super::builder::PipelinePathBuilder::into_receiver)
+/// and the stages that fork off a second output. Unlike a flume [`Receiver`] this can't be
+/// cloned, as cloned receivers share the items between them rather than each getting every
+/// item, so a clone taken by accident silently makes items go missing from the path. Fork
+/// the path with a stage such as
+/// [`PipelinePathBuilder::split`](super::builder::PipelinePathBuilder::split) instead.
+#[derive(Debug)]
+pub struct SingleConsumer<T>(Receiver<T>);
+
+impl<T> SingleConsumer<T> {
+    pub(super) fn new(receiver: Receiver<T>) -> Self {
+        Self(receiver)
+    }
+
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.0.recv()
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.0.try_recv()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.0.recv_timeout(timeout)
+    }
+
+    pub fn recv_async(&self) -> RecvFut<'_, T> {
+        self.0.recv_async()
+    }
+
+    pub fn iter(&self) -> flume::Iter<'_, T> {
+        self.0.iter()
+    }
+
+    pub fn try_iter(&self) -> flume::TryIter<'_, T> {
+        self.0.try_iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Only for the stages that take over the consumer, such as
+    /// [`PipelineBuilder::zip`](super::builder::PipelineBuilder::zip), which never clone it.
+    pub(super) fn into_inner(self) -> Receiver<T> {
+        self.0
+    }
+}
+
+/// Lets the stages that take a path's output also take a channel the caller owns, such as
+/// the output of a source task. There is deliberately no way back to a [`Receiver`].
+impl<T> From<Receiver<T>> for SingleConsumer<T> {
+    fn from(receiver: Receiver<T>) -> Self {
+        Self::new(receiver)
+    }
+}
+
+impl<T> IntoIterator for SingleConsumer<T> {
+    type Item = T;
+    type IntoIter = flume::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricsSnapshot {
+    pub taken_at: Instant,
+    pub edges: IndexMap<String, EdgeSnapshot>,
+}
+
+impl MetricsSnapshot {
+    pub fn total_sent(&self) -> u64 {
+        self.edges.values().map(|edge| edge.sent).sum()
+    }
+
+    pub fn total_dropped(&self) -> u64 {
+        self.edges.values().map(|edge| edge.dropped).sum()
+    }
+
+    /// The rates of each edge between `prev` and this snapshot, e.g. for showing live
+    /// throughput. A counter that went down, because its edge was recreated, is treated as
+    /// having restarted from zero rather than as a negative rate.
+    pub fn rate_since(&self, prev: &MetricsSnapshot) -> RateSnapshot {
+        let elapsed = self.taken_at.saturating_duration_since(prev.taken_at);
+        let secs = elapsed.as_secs_f64();
+
+        let per_sec = |current: u64, previous: u64| {
+            // A lower count means the counter was reset in between.
+            let delta = current.checked_sub(previous).unwrap_or(current);
+
+            if secs > 0.0 {
+                delta as f64 / secs
+            } else {
+                0.0
+            }
+        };
+
+        RateSnapshot {
+            elapsed,
+            edges: self
+                .edges
+                .iter()
+                .map(|(name, edge)| {
+                    let prev = prev.edges.get(name).copied().unwrap_or_default();
+
+                    (
+                        name.clone(),
+                        EdgeRate {
+                            sent_per_sec: per_sec(edge.sent, prev.sent),
+                            dropped_per_sec: per_sec(edge.dropped, prev.dropped),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Per-edge rates between two snapshots, see [`MetricsSnapshot::rate_since`].
+#[derive(Debug, Clone)]
+pub struct RateSnapshot {
+    pub elapsed: Duration,
+    pub edges: IndexMap<String, EdgeRate>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EdgeRate {
+    pub sent_per_sec: f64,
+    pub dropped_per_sec: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EdgeSnapshot {
+    pub sent: u64,
+    pub dropped: u64,
+    /// Items currently queued on the edge.
+    pub depth: usize,
+    pub peak_depth: usize,
+    /// The most consecutive items dropped without one getting through.
+    pub longest_drop_run: u64,
+    /// How many runs of drops were longer than the burst threshold. A few scattered drops
+    /// are normal under load; bursts point at a stalled consumer.
+    pub drop_bursts: u64,
+    /// Items skipped by the edge's producer for missing their deadline, see
+    /// [`PathMode::RealTime`](super::stages::PathMode::RealTime).
+    pub skipped_late: u64,
+    /// Items discarded by the edge's producer while warming up, see
+    /// [`PipelinePathBuilder::warmup`](super::builder::PipelinePathBuilder::warmup).
+    pub skipped_warmup: u64,
+    #[cfg(feature = "debug-clones")]
+    pub debug: EdgeDebugSnapshot,
+}
+
+/// The tasks on either end of an edge, see [`PipelineMetrics::topology`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EdgeTopology {
+    pub producer: Option<String>,
+    pub consumer: Option<String>,
+}
+
+impl fmt::Display for EdgeTopology {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let task = |task: &Option<String>| task.clone().unwrap_or_else(|| "?".to_string());
+        write!(f, "{} -> {}", task(&self.producer), task(&self.consumer))
+    }
+}
+
+/// Instrumentation that is only collected with the `debug-clones` feature.
+#[cfg(feature = "debug-clones")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EdgeDebugSnapshot {
+    /// Items cloned to be sent on the edge, see [`MeteredSender::clone_item`].
+    pub clones: u64,
+}
+
+/// A post-mortem of a pipeline run. The counters live in the pipeline rather than its
+/// tasks, so a summary taken after the tasks are joined still includes their final items.
+#[derive(Debug, Clone)]
+pub struct PipelineSummary {
+    pub duration: Duration,
+    pub edges: IndexMap<String, EdgeSnapshot>,
+    /// Items sent by each task, summed over the edges it produces into according to the
+    /// [topology](PipelineMetrics::topology). Edges without a known producer aren't counted.
+    pub tasks: IndexMap<String, u64>,
+}
+
+impl PipelineSummary {
+    pub fn total_sent(&self) -> u64 {
+        self.edges.values().map(|edge| edge.sent).sum()
+    }
+
+    pub fn total_dropped(&self) -> u64 {
+        self.edges.values().map(|edge| edge.dropped).sum()
+    }
+
+    pub fn peak_depth(&self) -> usize {
+        self.edges
+            .values()
+            .map(|edge| edge.peak_depth)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Average items per second across all edges.
+    pub fn throughput(&self) -> f64 {
+        let secs = self.duration.as_secs_f64();
+
+        if secs > 0.0 {
+            self.total_sent() as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for PipelineSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} items in {:.2}s ({:.1}/s), {} dropped, peak queue depth {}",
+            self.total_sent(),
+            self.duration.as_secs_f64(),
+            self.throughput(),
+            self.total_dropped(),
+            self.peak_depth()
+        )?;
+
+        for (i, (task, sent)) in self.tasks.iter().enumerate() {
+            let separator = if i == 0 { " | by task: " } else { ", " };
+            write!(f, "{separator}{task}: {sent}")?;
+        }
+
+        for (i, (name, edge)) in self.edges.iter().enumerate() {
+            let separator = if i == 0 { " | " } else { ", " };
+            write!(
+                f,
+                "{separator}{name}: {} sent, {} dropped",
+                edge.sent, edge.dropped
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Receives periodic metrics snapshots from a running pipeline,
+/// see [`Pipeline::spawn_metrics_exporter`](super::Pipeline::spawn_metrics_exporter).
+pub trait MetricsExporter: Send + 'static {
+    fn report(&self, snapshot: &MetricsSnapshot);
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+}
+
+/// Logs every snapshot at `info` level.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogExporter;
+
+impl MetricsExporter for LogExporter {
+    fn report(&self, snapshot: &MetricsSnapshot) {
+        for (name, edge) in &snapshot.edges {
+            info!(
+                "edge '{name}': {} sent, {} dropped, {} queued (peak {})",
+                edge.sent, edge.dropped, edge.depth, edge.peak_depth
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::PipelineMetrics;
+
+    #[test]
+    fn blocked_send_fails_once_the_consumer_is_dropped() {
+        let metrics = PipelineMetrics::default();
+        let (tx, rx) = metrics.edge::<u32>("frames", 1);
+        tx.send(0).unwrap();
+
+        let producer = thread::spawn(move || tx.send(1));
+        thread::sleep(Duration::from_millis(20));
+        drop(rx);
+
+        assert_eq!(producer.join().unwrap().unwrap_err().into_inner(), 1);
+    }
+
+    #[tokio::test]
+    async fn blocked_async_send_fails_once_the_consumer_is_dropped() {
+        let metrics = PipelineMetrics::default();
+        let (tx, rx) = metrics.edge::<u32>("frames", 1);
+        tx.send_async(0).await.unwrap();
+
+        let producer = tokio::spawn(async move { tx.send_async(1).await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(rx);
+
+        let result = tokio::time::timeout(Duration::from_secs(1), producer).await;
+        assert_eq!(result.unwrap().unwrap().unwrap_err().into_inner(), 1);
+    }
+}
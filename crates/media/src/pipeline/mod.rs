@@ -1,24 +1,107 @@
+use futures::Stream;
 use indexmap::IndexMap;
-use std::thread::JoinHandle;
-use tracing::{info, trace};
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, trace, warn};
 
+pub mod ack;
 pub mod audio_buffer;
+pub mod av_sync;
+pub mod backoff;
 pub mod builder;
 pub mod clock;
+pub mod completion;
 pub mod control;
+pub mod device;
+pub mod diagnostics;
+pub mod drain;
+pub mod health;
+pub mod lazy;
+pub mod lifecycle;
+mod macros;
+pub mod metrics;
+pub mod mux;
+mod pool;
+pub mod replay;
+pub mod stages;
 pub mod task;
+mod task_log;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod watchdog;
 
 use crate::MediaError;
 
 use builder::PipelineBuilder;
 pub use clock::*;
-use control::{Control, ControlBroadcast, PipelineControlSignal};
+use completion::{Completion, CompletionReason, ShutdownHook, ShutdownReport, TaskOutcomes};
+use control::{
+    AckAudience, Control, ControlBroadcast, ControlMessage, PauseMode, PipelineControlSignal,
+    MAX_SOURCE_RATE,
+};
+use device::{DeviceRegistry, HeldDevice};
+use diagnostics::{DiagnosticsSource, PipelineDiagnostics};
+use drain::{DrainProgress, Leftovers};
+use health::{HealthIssue, HealthReport};
+use lifecycle::{LifecycleEvent, LifecycleEvents};
+use metrics::{MetricsExporter, MetricsSnapshot, PipelineMetrics, PipelineSummary};
+use pool::TaskHandle;
+
+const DRAIN_MIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+const DRAIN_MAX_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const STATS_SUBSCRIPTION_CAPACITY: usize = 16;
+const ROTATE_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// The index and name of the candidate that started, for a source spawned with
+/// [`PipelineBuilder::spawn_source_with_fallback`].
+#[derive(Clone, Default)]
+struct ChosenCandidate(Arc<OnceLock<(usize, String)>>);
+
+/// Values attached with [`PipelineBuilder::insert_extension`], keyed by their type.
+#[derive(Default)]
+struct Extensions(HashMap<TypeId, Box<dyn Any + Send + Sync>>);
+
+impl Extensions {
+    fn insert<V: Send + Sync + 'static>(&mut self, value: V) {
+        self.0.insert(TypeId::of::<V>(), Box::new(value));
+    }
+
+    fn get<V: 'static>(&self) -> Option<&V> {
+        self.0.get(&TypeId::of::<V>())?.downcast_ref()
+    }
+}
 
 pub struct Pipeline<T: PipelineClock> {
     clock: T,
+    runtime: tokio::runtime::Handle,
     control: ControlBroadcast,
-    task_handles: IndexMap<String, JoinHandle<()>>,
-    is_shutdown: bool,
+    task_handles: IndexMap<String, TaskHandle>,
+    metrics: PipelineMetrics,
+    devices: DeviceRegistry,
+    completion: Completion,
+    outcomes: TaskOutcomes,
+    settled: tokio::sync::watch::Receiver<bool>,
+    lifecycle: LifecycleEvents,
+    on_shutdown: ShutdownHook,
+    metrics_exporter: Mutex<Option<tokio::task::JoinHandle<()>>>,
+    /// Tasks that run on the runtime alongside the pipeline, such as the stats samplers and
+    /// the diagnostics signal handler, which are aborted once it shuts down.
+    background_tasks: Mutex<Vec<tokio::task::JoinHandle<()>>>,
+    started_at: Instant,
+    finished_at: Option<Instant>,
+    log_summary: bool,
+    paused: Option<PauseMode>,
+    extensions: Extensions,
+    chosen_candidates: IndexMap<String, ChosenCandidate>,
+    /// Shared with the completion monitor, which sets it once every task has stopped.
+    is_shutdown: Arc<AtomicBool>,
 }
 
 impl<T: PipelineClock> Pipeline<T> {
@@ -26,8 +109,287 @@ impl<T: PipelineClock> Pipeline<T> {
         PipelineBuilder::new(clock)
     }
 
+    /// The names of the running tasks, always in the order they were added to the builder.
+    /// Empty once the pipeline has shut down.
+    pub fn task_names(&self) -> impl Iterator<Item = &str> {
+        self.task_handles.keys().map(String::as_str)
+    }
+
+    /// Resolves once the pipeline is fully operational, which is straight away unless it
+    /// was built with [`PipelineBuilder::build_fast`].
+    pub async fn ready(&self) {
+        let mut settled = self.settled.clone();
+        let _ = settled.wait_for(|settled| *settled).await;
+    }
+
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Starts reporting metrics snapshots to `exporter` at its interval, replacing any
+    /// exporter started before. Metrics are collected regardless of whether an exporter
+    /// is attached, so one can be attached at any point while the pipeline runs.
+    pub fn spawn_metrics_exporter(&self, exporter: impl MetricsExporter) {
+        let metrics = self.metrics.clone();
+
+        let handle = self.runtime.spawn(async move {
+            let mut interval = tokio::time::interval(exporter.interval());
+
+            loop {
+                interval.tick().await;
+                exporter.report(&metrics.snapshot());
+            }
+        });
+
+        if let Some(previous) = self.metrics_exporter.lock().unwrap().replace(handle) {
+            previous.abort();
+        }
+    }
+
+    pub fn stop_metrics_exporter(&self) {
+        if let Some(exporter) = self.metrics_exporter.lock().unwrap().take() {
+            exporter.abort();
+        }
+    }
+
+    /// Pushes a metrics snapshot every `interval` to the returned channel, e.g. for a live
+    /// dashboard. Each call is a separate subscription, whose sampler stops once all of its
+    /// receivers are dropped or the pipeline shuts down. A subscriber that stops draining
+    /// misses snapshots rather than holding up the sampler.
+    pub fn subscribe_stats(&self, interval: Duration) -> flume::Receiver<MetricsSnapshot> {
+        let (tx, rx) = flume::bounded(STATS_SUBSCRIPTION_CAPACITY);
+        let metrics = self.metrics.clone();
+
+        let handle = self.runtime.spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+            loop {
+                interval.tick().await;
+
+                if let Err(flume::TrySendError::Disconnected(_)) = tx.try_send(metrics.snapshot()) {
+                    break;
+                }
+            }
+        });
+
+        let mut background_tasks = self.background_tasks.lock().unwrap();
+        background_tasks.retain(|task| !task.is_finished());
+        background_tasks.push(handle);
+
+        rx
+    }
+
+    /// Takes the items still queued on the metered edge called `edge`, e.g. to encode the
+    /// final frames synchronously after stopping a source. The edge's items are of type `O`.
+    ///
+    /// Draining a live edge is disallowed: this fails with [`MediaError::EdgeStillLive`]
+    /// until the task producing into the edge has stopped. The edge's consumer may still be
+    /// taking items too, so drain it once that has stopped as well to get all of them.
+    pub fn drain_edge<O: Send + 'static>(
+        &self,
+        edge: &str,
+    ) -> Result<impl Iterator<Item = O>, MediaError> {
+        self.metrics.drain(edge)
+    }
+
+    /// Why the pipeline stopped, once it has.
+    pub fn completion_reason(&self) -> Option<CompletionReason> {
+        self.completion.reason()
+    }
+
+    /// A stream of the [`LifecycleEvent`]s from now on, e.g. for an async UI to update as
+    /// tasks stop, which ends after [`LifecycleEvent::PipelineShutdown`]. This is the async
+    /// counterpart to
+    /// [`PipelineBuilder::with_lifecycle_observer`](builder::PipelineBuilder::with_lifecycle_observer),
+    /// and there can be any number of them. Events for a stream that falls far behind are
+    /// dropped, with a warning, until it catches up, so it can't stall the pipeline.
+    pub fn events(&self) -> impl Stream<Item = LifecycleEvent> {
+        self.lifecycle.subscribe().into_stream()
+    }
+
+    /// How each task ended, e.g. to show "screen: ok, microphone: failed (device
+    /// disconnected)". Complete once the pipeline has shut down. Tasks that are still
+    /// running or were detached show up as [`completion::TaskOutcome::Running`].
+    pub fn shutdown_report(&self) -> ShutdownReport {
+        self.outcomes.report()
+    }
+
+    /// Whether the pipeline is running with all of its tasks, for a simple status indicator.
+    /// See [`Self::health_report`] for the reasons when it isn't.
+    ///
+    /// This doesn't detect stalls: a pipeline whose tasks are all alive but blocked, e.g.
+    /// on a sink that stopped reading, still counts as healthy. Telling a stall from a
+    /// source that has nothing to send needs a sense of how long is too long, so use
+    /// [`PipelineBuilder::with_idle_timeout`] to stop a pipeline once nothing flows, or
+    /// compare the `sent` counts of [`Self::metrics`] over time to flag stuck edges.
+    pub fn is_healthy(&self) -> bool {
+        self.health_report().is_healthy()
+    }
+
+    /// The reasons the pipeline isn't healthy, which are failed, exited and stopped tasks.
+    /// Stalls aren't among them, see [`Self::is_healthy`].
+    pub fn health_report(&self) -> HealthReport {
+        let mut issues = vec![];
+        let mut failed_task = None;
+
+        match self.completion.reason() {
+            Some(CompletionReason::TaskFinished {
+                task,
+                error: Some(error),
+            }) => {
+                failed_task = Some(task.clone());
+                issues.push(HealthIssue::TaskFailed { task, error });
+            }
+            Some(reason) => issues.push(HealthIssue::Stopped(reason)),
+            None => {}
+        }
+
+        for (name, handle) in &self.task_handles {
+            if handle.is_finished() && failed_task.as_ref() != Some(name) {
+                issues.push(HealthIssue::TaskExited(name.clone()));
+            }
+        }
+
+        HealthReport { issues }
+    }
+
+    /// Whether the pipeline has shut down, or is shutting down: set as soon as a shutdown
+    /// is requested, or once every task has stopped on its own. Can be checked from any
+    /// thread, e.g. by a UI deciding whether to enable its stop button.
+    pub fn is_shutdown(&self) -> bool {
+        self.is_shutdown.load(Ordering::Acquire)
+    }
+
+    /// The index and name of the candidate that started for the task spawned with
+    /// [`PipelineBuilder::spawn_source_with_fallback`], or `None` for other tasks and
+    /// before one has started.
+    pub fn chosen_candidate(&self, task: &str) -> Option<(usize, &str)> {
+        let (index, name) = self.chosen_candidates.get(task)?.0.get()?;
+        Some((*index, name.as_str()))
+    }
+
+    /// The value of type `V` attached with [`PipelineBuilder::insert_extension`], if any.
+    pub fn extension<V: 'static>(&self) -> Option<&V> {
+        self.extensions.get()
+    }
+
+    pub fn clock(&self) -> &T {
+        &self.clock
+    }
+
+    /// Gives `f` mutable access to the clock of the running pipeline, e.g. for tools that
+    /// reconfigure it live. The tasks hold clones of the clock rather than this one, so
+    /// they only observe changes that the clock keeps in state shared between its clones,
+    /// which not every clock does for every setting. Fails once the pipeline has shut down.
+    pub fn with_clock_mut(&mut self, f: impl FnOnce(&mut T)) -> Result<(), MediaError> {
+        if self.is_shutdown() {
+            return Err(MediaError::ShutdownPipeline);
+        };
+
+        f(&mut self.clock);
+
+        Ok(())
+    }
+
+    pub fn clock_skew(&self) -> Option<Duration> {
+        self.clock.wall_clock_skew()
+    }
+
+    /// Totals for the run so far, or for the whole run once the pipeline has shut down.
+    pub fn summary(&self) -> PipelineSummary {
+        let finished_at = self.finished_at.unwrap_or_else(Instant::now);
+        let edges = self.metrics.snapshot().edges;
+
+        let mut tasks = IndexMap::<String, u64>::new();
+        for (name, topology) in self.metrics.topology() {
+            if let (Some(producer), Some(edge)) = (topology.producer, edges.get(&name)) {
+                *tasks.entry(producer).or_default() += edge.sent;
+            }
+        }
+
+        PipelineSummary {
+            duration: finished_at - self.started_at,
+            edges,
+            tasks,
+        }
+    }
+
+    /// A one-shot dump of the pipeline's state, from its tasks and edges down to its latest
+    /// lifecycle events, e.g. for a user to attach to a bug report. See
+    /// [`Self::dump_diagnostics`] to log it instead.
+    pub fn diagnostics(&self) -> PipelineDiagnostics {
+        self.diagnostics_source().capture()
+    }
+
+    /// Logs [`Self::diagnostics`].
+    pub fn dump_diagnostics(&self) {
+        info!("Pipeline diagnostics:\n{}", self.diagnostics());
+    }
+
+    /// Logs [`Self::diagnostics`] every time the process receives `signal`, e.g.
+    /// [`SignalKind::user_defined1`](tokio::signal::unix::SignalKind::user_defined1) for
+    /// `SIGUSR1`, until the pipeline shuts down. Nothing is registered unless this is
+    /// called, and only on Unix; [`Self::dump_diagnostics`] works everywhere.
+    ///
+    /// Receiving the signal replaces its default action, which for `SIGUSR1` is to
+    /// terminate the process, for as long as the process runs, not just the pipeline.
+    #[cfg(unix)]
+    pub fn dump_diagnostics_on_signal(
+        &self,
+        signal: tokio::signal::unix::SignalKind,
+    ) -> Result<(), MediaError> {
+        let source = self.diagnostics_source();
+        let mut signals = {
+            let _runtime = self.runtime.enter();
+            tokio::signal::unix::signal(signal)?
+        };
+
+        let handle = self.runtime.spawn(async move {
+            while signals.recv().await.is_some() {
+                info!("Pipeline diagnostics:\n{}", source.capture());
+            }
+        });
+        self.background_tasks.lock().unwrap().push(handle);
+
+        Ok(())
+    }
+
+    fn diagnostics_source(&self) -> DiagnosticsSource<T> {
+        DiagnosticsSource {
+            clock: self.clock.clone(),
+            metrics: self.metrics.clone(),
+            completion: self.completion.clone(),
+            outcomes: self.outcomes.clone(),
+            lifecycle: self.lifecycle.clone(),
+            started_at: self.started_at,
+            finished_at: self.finished_at,
+        }
+    }
+
+    /// The devices currently held by the pipeline's tasks through [`device::hold_device`].
+    pub fn held_devices(&self) -> Vec<HeldDevice> {
+        self.devices.held_devices()
+    }
+
+    /// Links this pipeline's control plane to `other`'s, so that [`Self::play`], pausing
+    /// with [`PauseMode::Suspend`], [`Self::rotate_segment`] and shutting down this pipeline
+    /// also reach the tasks of `other`, e.g. for separate audio and video pipelines that
+    /// must start and stop together. Only the tasks are affected: `other`'s clock keeps
+    /// running while this one is paused, and it notices it has shut down once its tasks
+    /// have stopped. Messages that wait for acks, as for [`Self::rotate_atomic`], and
+    /// messages to a single task aren't forwarded.
+    ///
+    /// Linking is one way, so link `other` back to have either pipeline control both. That
+    /// can't make them forward each other's controls in a loop, as only the tasks of
+    /// `other` itself are linked, not the pipelines it is linked to in turn. For the same
+    /// reason, linking isn't transitive.
+    pub fn link_control<U: PipelineClock>(&mut self, other: &Pipeline<U>) {
+        self.control.link(&other.control);
+    }
+
     pub async fn play(&mut self) -> Result<(), MediaError> {
-        if self.is_shutdown {
+        if self.is_shutdown() {
             return Err(MediaError::ShutdownPipeline);
         };
 
@@ -37,18 +399,350 @@ impl<T: PipelineClock> Pipeline<T> {
         Ok(())
     }
 
+    /// Stops the clock, and with [`PauseMode::Suspend`] also asks the sources to release
+    /// their devices until [`Self::resume`]. Pausing an already paused pipeline changes
+    /// nothing, so resume it first to switch modes.
+    pub async fn pause(&mut self, mode: PauseMode) -> Result<(), MediaError> {
+        if self.is_shutdown() {
+            return Err(MediaError::ShutdownPipeline);
+        };
+
+        if self.paused.is_some() {
+            return Ok(());
+        }
+
+        self.clock.stop();
+
+        if mode == PauseMode::Suspend {
+            self.control.message(ControlMessage::Suspend);
+        }
+
+        self.paused = Some(mode);
+
+        Ok(())
+    }
+
+    /// Restarts the clock after [`Self::pause`]. Suspended sources are asked to re-acquire
+    /// their devices, which they should do within [`control::MAX_RESUME_LATENCY`].
+    pub async fn resume(&mut self) -> Result<(), MediaError> {
+        if self.is_shutdown() {
+            return Err(MediaError::ShutdownPipeline);
+        };
+
+        match self.paused.take() {
+            Some(PauseMode::Suspend) => {
+                self.control.message(ControlMessage::Resume);
+            }
+            Some(PauseMode::ClockOnly) => {}
+            None => return Ok(()),
+        }
+
+        self.clock.start();
+
+        Ok(())
+    }
+
+    /// Asks sinks that write segmented output to start a new segment.
+    /// Sinks that don't support rotation ignore it.
+    pub fn rotate_segment(&self) -> Result<(), MediaError> {
+        if self.is_shutdown() {
+            return Err(MediaError::ShutdownPipeline);
+        };
+
+        self.control.message(ControlMessage::Rotate);
+
+        Ok(())
+    }
+
+    /// Asks the source named `task` to produce `rate` items per second from now on, see
+    /// [`ControlMessage::SetRate`]. Fails with [`MediaError::InvalidSourceRate`] unless the
+    /// rate is positive and at most [`MAX_SOURCE_RATE`], and with
+    /// [`MediaError::UnknownTask`] if no such task is listening for messages. A task that
+    /// hasn't read its earlier messages yet fails it with [`MediaError::ControlQueueFull`],
+    /// in which case it's worth retrying later.
+    /// Whether the source honours it only shows in its output and logs.
+    pub fn set_source_rate(&self, task: &str, rate: f64) -> Result<(), MediaError> {
+        if self.is_shutdown() {
+            return Err(MediaError::ShutdownPipeline);
+        };
+
+        if !(rate > 0.0 && rate <= MAX_SOURCE_RATE) {
+            return Err(MediaError::InvalidSourceRate(rate));
+        }
+
+        self.control.message_to(task, ControlMessage::SetRate(rate))
+    }
+
+    /// Rotates the segment so that every item captured before the call is written to the
+    /// current segment and every item captured after it to the next. The clock is stopped
+    /// and the sources that ack their messages are sent [`ControlMessage::Suspend`]. Once
+    /// the items queued on the edges downstream of the sources that acknowledged it are
+    /// flushed and nothing more is sent on them, the tasks subscribed with
+    /// [`PipelineBuilder::control_messages`], usually the sinks, are sent
+    /// [`ControlMessage::Rotate`] with [`Self::send_control_ack`] semantics. The sources
+    /// resume once every one of those has acknowledged the rotation, so sinks should ack only
+    /// once the next segment is open.
+    ///
+    /// That makes for a brief gap in the capture: the time to flush the queued items and
+    /// open the next segment, plus up to [`control::MAX_RESUME_LATENCY`] for the sources to
+    /// resume. Sources that can't suspend, which ignore the message or never read it, carry
+    /// on capturing and aren't waited for, so their items around the boundary may land in
+    /// either segment. If the flush and the rotation together take longer than `timeout`,
+    /// this fails with [`MediaError::DrainStalled`] or [`MediaError::ControlAckTimeout`]
+    /// and the sources are resumed regardless. A pipeline paused with [`PauseMode::Suspend`]
+    /// stays suspended.
+    pub async fn rotate_atomic(&mut self, timeout: Duration) -> Result<(), MediaError> {
+        if self.is_shutdown() {
+            return Err(MediaError::ShutdownPipeline);
+        };
+
+        let deadline = Instant::now() + timeout;
+        let paused = self.paused;
+        if paused.is_none() {
+            self.clock.stop();
+        }
+
+        let suspended = self
+            .control
+            .collect_acks(AckAudience::Sources, ControlMessage::Suspend, timeout)
+            .await
+            .handled;
+
+        let rotated = self.rotate_once_flushed(&suspended, deadline).await;
+
+        // Sources that were too slow to ack may still have suspended, so this goes to all
+        // of them; the others ignore it.
+        if paused != Some(PauseMode::Suspend) {
+            self.control.message(ControlMessage::Resume);
+        }
+        if paused.is_none() {
+            self.clock.start();
+        }
+
+        rotated
+    }
+
+    async fn rotate_once_flushed(
+        &self,
+        suspended: &[String],
+        deadline: Instant,
+    ) -> Result<(), MediaError> {
+        let downstream = self.metrics.downstream_of(suspended);
+        let mut last_sent = None;
+
+        loop {
+            let snapshot = self.metrics.snapshot();
+            let edges = snapshot
+                .edges
+                .iter()
+                .filter(|(name, _)| downstream.contains(*name));
+            let sent = edges.clone().map(|(_, edge)| edge.sent).sum::<u64>();
+            let fullest = edges.max_by_key(|(_, edge)| edge.depth);
+
+            // Nothing sent since the last poll, so the suspended sources have stopped too.
+            if fullest.map_or(true, |(_, edge)| edge.depth == 0) && last_sent == Some(sent) {
+                break;
+            }
+
+            if Instant::now() >= deadline {
+                let edge = fullest.map(|(name, _)| name.clone()).unwrap_or_default();
+                return Err(MediaError::DrainStalled(edge));
+            }
+
+            last_sent = Some(sent);
+            tokio::time::sleep(ROTATE_POLL_INTERVAL).await;
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let acks = self
+            .control
+            .collect_acks(AckAudience::Subscribers, ControlMessage::Rotate, remaining)
+            .await;
+
+        if !acks.failed.is_empty() {
+            return Err(MediaError::ControlAckTimeout(acks.failed));
+        }
+
+        Ok(())
+    }
+
+    /// Sends a message to every task that acks its control messages and waits until all of
+    /// them have acknowledged it, for commands that need to have completed, e.g. a flush.
+    /// Those are the tasks subscribed with [`PipelineBuilder::control_messages`] and the
+    /// sources that opt in with [`task::PipelineSourceTask::acks_control_messages`]. They ack
+    /// once they've handled the message, see [`control::ControlMessages::try_recv_acked`], or
+    /// on receipt if they read it with [`control::ControlMessages::try_recv`]. A task the
+    /// message doesn't apply to can answer with [`control::ControlAck::ignore`] instead.
+    /// Tasks that never read their messages aren't sent it, so they don't hold this up.
+    pub async fn send_control_ack(
+        &self,
+        message: ControlMessage,
+        timeout: Duration,
+    ) -> Result<(), MediaError> {
+        if self.is_shutdown() {
+            return Err(MediaError::ShutdownPipeline);
+        };
+
+        self.control.message_acked(message, timeout).await
+    }
+
+    /// Stops waiting for the task named `task`, detaching its thread so a later shutdown
+    /// doesn't block on it, e.g. for a device capture wedged in a native call that never
+    /// returns. The task is still sent the shutdown signal, but nothing waits for it to act on
+    /// it, and it no longer shows in [`Self::task_names`] or [`Self::health_report`].
+    ///
+    /// Rust can't interrupt a thread, so an abandoned task leaks its thread, and whatever it
+    /// holds, until its native call returns, if ever. Fails with
+    /// [`MediaError::UnknownTask`] if there's no such task still being waited for.
+    pub fn abandon_task(&mut self, task: &str) -> Result<(), MediaError> {
+        let Some(handle) = self.task_handles.shift_remove(task) else {
+            return Err(MediaError::UnknownTask(task.to_string()));
+        };
+
+        if handle.is_finished() {
+            debug!("Abandoned task '{task}', which had already finished");
+        } else {
+            warn!("Abandoned task '{task}', which may still be running on a detached thread");
+        }
+
+        Ok(())
+    }
+
     pub async fn shutdown(&mut self) -> Result<(), MediaError> {
-        if self.is_shutdown {
+        // Tasks that all stopped on their own still need joining.
+        if self.finished_at.is_some() {
             return Err(MediaError::ShutdownPipeline);
         };
 
         trace!("Shutting down pipeline");
+        self.is_shutdown.store(true, Ordering::Release);
+        self.completion.record(CompletionReason::Stopped);
         self.control.broadcast(Control::Shutdown).await;
+        self.join_tasks();
+        Ok(())
+    }
+
+    /// Stops the sources, then waits for the items already queued on the pipeline's metered
+    /// edges to be processed before joining the tasks. `on_progress` is called every time
+    /// the queues are sampled, so a UI can show e.g. "flushing 340/512 frames".
+    ///
+    /// Sampling backs off while nothing drains. If the queued items stop decreasing for
+    /// `stall_timeout`, the drain is abandoned, the tasks are detached instead of joined and
+    /// [`MediaError::DrainStalled`] names the edge that failed to drain.
+    pub async fn shutdown_graceful(
+        &mut self,
+        stall_timeout: Duration,
+        mut on_progress: impl FnMut(&DrainProgress),
+    ) -> Result<(), MediaError> {
+        if self.finished_at.is_some() {
+            return Err(MediaError::ShutdownPipeline);
+        };
+
+        trace!("Gracefully shutting down pipeline");
+        self.is_shutdown.store(true, Ordering::Release);
+        self.completion.record(CompletionReason::Stopped);
+        self.control.broadcast(Control::Shutdown).await;
+
+        let initial = self.metrics.snapshot();
+        let mut lowest_remaining = usize::MAX;
+        let mut last_progress = Instant::now();
+        let mut poll_interval = DRAIN_MIN_POLL_INTERVAL;
+
+        loop {
+            let progress = DrainProgress::between(&initial, &self.metrics.snapshot());
+            on_progress(&progress);
+
+            if progress.is_complete() {
+                break;
+            }
+
+            let running = self.outcomes.running();
+            debug!("Waiting for tasks to stop: {}", running.join(", "));
+
+            if progress.remaining() < lowest_remaining {
+                lowest_remaining = progress.remaining();
+                last_progress = Instant::now();
+                poll_interval = DRAIN_MIN_POLL_INTERVAL;
+            } else if last_progress.elapsed() >= stall_timeout {
+                let edge = progress.most_backed_up().unwrap_or_default().to_string();
+                warn!(
+                    "Edge '{edge}' stopped draining, abandoning graceful shutdown with {} still running",
+                    running.join(", ")
+                );
+
+                self.task_handles.clear();
+                self.finish();
+                return Err(MediaError::DrainStalled(edge));
+            } else {
+                poll_interval = (poll_interval * 2).min(DRAIN_MAX_POLL_INTERVAL);
+            }
+
+            tokio::time::sleep(poll_interval).await;
+        }
+
+        self.join_tasks();
+        Ok(())
+    }
+
+    /// Like [`Self::shutdown_graceful`], but if the drain stalls, the items still queued on
+    /// edges marked with
+    /// [`PipelinePathBuilder::recover_leftovers`](builder::PipelinePathBuilder::recover_leftovers)
+    /// are taken off them and handed back, e.g. to be processed or persisted on the calling
+    /// thread. The stalled tasks are detached rather than stopped, so one that gets unstuck
+    /// may still have taken some of the items first, but none are handed out twice. The
+    /// leftovers are empty unless the drain stalled.
+    pub async fn shutdown_graceful_with_leftovers(
+        &mut self,
+        stall_timeout: Duration,
+        on_progress: impl FnMut(&DrainProgress),
+    ) -> (Result<(), MediaError>, Leftovers) {
+        let result = self.shutdown_graceful(stall_timeout, on_progress).await;
+
+        let leftovers = match result {
+            Err(MediaError::DrainStalled(_)) => self.metrics.take_leftovers(),
+            _ => Leftovers::default(),
+        };
+
+        (result, leftovers)
+    }
+
+    fn join_tasks(&mut self) {
         for (_name, task) in self.task_handles.drain(..) {
-            let _ = task.join();
+            task.join();
         }
         info!("Pipeline stopped");
+        if let Some((task, latency)) = self.outcomes.report().slowest_to_stop() {
+            debug!("Task '{task}' was the slowest to stop, taking {latency:?}");
+        }
+        self.finish();
         // TODO: Collect shutdown errors?
-        Ok(())
+    }
+
+    /// Wraps up a shutdown once the tasks have been joined, or detached because they
+    /// stalled, which is when a summary matters most.
+    fn finish(&mut self) {
+        self.stop_background_tasks();
+        self.finished_at = Some(Instant::now());
+        self.lifecycle.emit(LifecycleEvent::PipelineShutdown);
+        self.on_shutdown.run(&self.outcomes);
+
+        if self.log_summary {
+            info!("Pipeline summary: {}", self.summary());
+        }
+    }
+
+    /// Stops the metrics exporter and the stats samplers, which would otherwise keep
+    /// running on the runtime for as long as it does.
+    fn stop_background_tasks(&self) {
+        self.stop_metrics_exporter();
+        for task in self.background_tasks.lock().unwrap().drain(..) {
+            task.abort();
+        }
+    }
+}
+
+impl<T: PipelineClock> Drop for Pipeline<T> {
+    fn drop(&mut self) {
+        self.stop_background_tasks();
     }
 }
@@ -0,0 +1,229 @@
+use std::{collections::VecDeque, time::Duration};
+
+use flume::Receiver;
+use tracing::warn;
+
+use crate::pipeline::{builder::PipelineBuilder, metrics::SingleConsumer, stages::TimestampMut};
+use crate::MediaError;
+
+/// How long a [`MuxSink`] waits by default for its other inputs to catch up.
+pub const DEFAULT_LOOK_AHEAD: Duration = Duration::from_millis(500);
+
+/// What a [`MuxSink`] does with an item that arrives after items with later timestamps
+/// have already been written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LateItems {
+    /// Drop the item, logging how many were dropped once the sink stops.
+    #[default]
+    Drop,
+    /// Fail the sink with [`MediaError::MuxItemTooLate`].
+    Fail,
+}
+
+/// A sink that writes the items of several inputs interleaved in timestamp order, e.g. the
+/// audio and video packets of a muxer. Each input must be in timestamp order itself. Inputs
+/// of different types need to be mapped into a common type first, like an enum of the
+/// streams. Added to a pipeline with [`PipelineBuilder::mux_sink`], and finished once all
+/// of its inputs end.
+///
+/// An item is only written once every input has an item queued, so nothing that comes
+/// before it can still arrive. An input that lags behind by more than the look-ahead
+/// window holds back the others no longer; its items that then arrive too late to be
+/// written in order are handled according to [`LateItems`].
+pub struct MuxSink<I> {
+    inputs: Vec<Receiver<I>>,
+    write: Box<dyn FnMut(usize, I) -> Result<(), MediaError> + Send>,
+    look_ahead: Duration,
+    late: LateItems,
+}
+
+impl<I: TimestampMut + Send + 'static> MuxSink<I> {
+    /// `write` is given the index of the input each item came from along with the item.
+    pub fn new(
+        inputs: impl IntoIterator<Item = impl Into<SingleConsumer<I>>>,
+        write: impl FnMut(usize, I) -> Result<(), MediaError> + Send + 'static,
+    ) -> Self {
+        Self {
+            inputs: inputs
+                .into_iter()
+                .map(|input| input.into().into_inner())
+                .collect(),
+            write: Box::new(write),
+            look_ahead: DEFAULT_LOOK_AHEAD,
+            late: LateItems::default(),
+        }
+    }
+
+    /// How far, in item timestamps, the newest item may be ahead of the oldest queued one
+    /// before the sink stops waiting for the inputs that have nothing queued.
+    pub fn with_look_ahead(mut self, look_ahead: Duration) -> Self {
+        self.look_ahead = look_ahead;
+        self
+    }
+
+    pub fn on_late(mut self, late: LateItems) -> Self {
+        self.late = late;
+        self
+    }
+
+    fn run(mut self) -> Result<(), MediaError> {
+        let mut open = vec![true; self.inputs.len()];
+        let mut queued = self
+            .inputs
+            .iter()
+            .map(|_| VecDeque::new())
+            .collect::<Vec<_>>();
+        let mut newest = Duration::ZERO;
+        let mut last_written = None;
+        let mut dropped = 0usize;
+
+        while open.iter().any(|open| *open) {
+            let (index, received) = {
+                let mut selector = flume::Selector::new();
+                for (index, input) in self.inputs.iter().enumerate() {
+                    if open[index] {
+                        selector = selector.recv(input, move |received| (index, received));
+                    }
+                }
+                selector.wait()
+            };
+
+            match received {
+                Ok(item) => {
+                    let timestamp = item.timestamp();
+
+                    if let Some(late_by) = last_written
+                        .and_then(|written: Duration| written.checked_sub(timestamp))
+                        .filter(|late_by| !late_by.is_zero())
+                    {
+                        match self.late {
+                            LateItems::Drop => {
+                                dropped += 1;
+                                continue;
+                            }
+                            LateItems::Fail => {
+                                return Err(MediaError::MuxItemTooLate {
+                                    input: index,
+                                    late_by,
+                                })
+                            }
+                        }
+                    }
+
+                    newest = newest.max(timestamp);
+                    queued[index].push_back(item);
+                }
+                Err(_) => open[index] = false,
+            }
+
+            while let Some(index) = self.next_to_write(&queued, &open, newest) {
+                let item = queued[index].pop_front().unwrap();
+                last_written = Some(item.timestamp());
+                (self.write)(index, item)?;
+            }
+        }
+
+        if dropped > 0 {
+            warn!("Mux sink dropped {dropped} items that arrived too late to be written in order");
+        }
+
+        Ok(())
+    }
+
+    /// The input whose next item is the oldest queued, if it can be written yet.
+    fn next_to_write(
+        &self,
+        queued: &[VecDeque<I>],
+        open: &[bool],
+        newest: Duration,
+    ) -> Option<usize> {
+        let (index, oldest) = queued
+            .iter()
+            .enumerate()
+            .filter_map(|(index, items)| Some((index, items.front()?.timestamp())))
+            .min_by_key(|(_, timestamp)| *timestamp)?;
+
+        let complete = queued
+            .iter()
+            .zip(open)
+            .all(|(items, open)| !items.is_empty() || !open);
+
+        (complete || newest.saturating_sub(oldest) > self.look_ahead).then_some(index)
+    }
+}
+
+impl<T> PipelineBuilder<T> {
+    /// Adds a [`MuxSink`] task. Its inputs can be obtained from other paths with
+    /// [`PipelinePathBuilder::into_receiver`](super::builder::PipelinePathBuilder::into_receiver).
+    /// An error from its writer stops the sink and is reported as the task's result.
+    pub fn mux_sink<I: TimestampMut + Send + 'static>(
+        mut self,
+        name: impl Into<String>,
+        sink: MuxSink<I>,
+    ) -> Self {
+        let name = name.into();
+        for input in &sink.inputs {
+            self.consumes(&name, input);
+        }
+
+        self.spawn_task(name, move |ready| {
+            let _ = ready.send(Ok(()));
+            sink.run().map_err(|error| error.to_string())
+        });
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::pipeline::{Pipeline, RealTimeClock};
+
+    #[tokio::test]
+    async fn interleaves_inputs_by_timestamp() {
+        let (video_tx, video_rx) = flume::bounded(16);
+        let (audio_tx, audio_rx) = flume::bounded(16);
+        let written = Arc::new(Mutex::new(vec![]));
+
+        let sink = MuxSink::new([video_rx, audio_rx], {
+            let written = written.clone();
+            move |input, (_, timestamp): ((), f64)| {
+                written.lock().unwrap().push((input, timestamp));
+                Ok(())
+            }
+        });
+        let (mut pipeline, _done_rx) = Pipeline::builder(RealTimeClock::<()>::new())
+            .mux_sink("mux", sink)
+            .build()
+            .await
+            .unwrap();
+
+        // Video arrives well ahead of the audio that should precede it.
+        for timestamp in [0.0, 0.04, 0.08, 0.12] {
+            video_tx.send(((), timestamp)).unwrap();
+        }
+        for timestamp in [0.0, 0.02, 0.05, 0.1] {
+            audio_tx.send(((), timestamp)).unwrap();
+        }
+        drop((video_tx, audio_tx));
+
+        pipeline.shutdown().await.unwrap();
+
+        assert_eq!(
+            *written.lock().unwrap(),
+            vec![
+                (0, 0.0),
+                (1, 0.0),
+                (1, 0.02),
+                (0, 0.04),
+                (1, 0.05),
+                (0, 0.08),
+                (1, 0.1),
+                (0, 0.12),
+            ]
+        );
+    }
+}
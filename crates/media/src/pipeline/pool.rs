@@ -0,0 +1,73 @@
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    thread::{self, JoinHandle},
+};
+
+use flume::{Receiver, Sender};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed number of threads that run task launch closures one after the other, see
+/// [`PipelineBuilder::with_max_threads`](super::builder::PipelineBuilder::with_max_threads).
+/// The workers exit once the pool is dropped and the queued jobs are done.
+#[derive(Clone)]
+pub(super) struct ThreadPool {
+    jobs: Sender<Job>,
+}
+
+impl ThreadPool {
+    pub fn new(threads: usize) -> Self {
+        let (jobs, queue) = flume::unbounded::<Job>();
+
+        for _ in 0..threads.max(1) {
+            let queue = queue.clone();
+
+            thread::spawn(move || {
+                for job in queue.iter() {
+                    // Keeps the worker alive for the next job when panics are propagated.
+                    let _ = catch_unwind(AssertUnwindSafe(job));
+                }
+            });
+        }
+
+        Self { jobs }
+    }
+
+    pub fn spawn(&self, job: impl FnOnce() + Send + 'static) -> TaskHandle {
+        let (finished_tx, finished) = flume::bounded::<()>(0);
+
+        let _ = self.jobs.send(Box::new(move || {
+            // Dropped once the job is done, including when it panics.
+            let _finished = finished_tx;
+            job();
+        }));
+
+        TaskHandle::Pooled(finished)
+    }
+}
+
+/// A running task, either on a thread of its own or on a [`ThreadPool`].
+pub(super) enum TaskHandle {
+    Thread(JoinHandle<()>),
+    Pooled(Receiver<()>),
+}
+
+impl TaskHandle {
+    pub fn join(self) {
+        match self {
+            Self::Thread(handle) => {
+                let _ = handle.join();
+            }
+            Self::Pooled(finished) => {
+                let _ = finished.recv();
+            }
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        match self {
+            Self::Thread(handle) => handle.is_finished(),
+            Self::Pooled(finished) => finished.is_disconnected(),
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use flume::{Receiver, Sender, TrySendError};
+
+use crate::pipeline::builder::PipelinePathBuilder;
+
+/// Items that decoding can start from, for [`PipelinePathBuilder::replay_from_keyframe`].
+pub trait Keyframe {
+    fn is_keyframe(&self) -> bool;
+}
+
+/// Attaches consumers to a path mid-stream, e.g. a preview opened while recording. Each
+/// is sent the path's recent items first, so it has something to show straight away.
+/// Returned by [`PipelinePathBuilder::replay_last`] and
+/// [`PipelinePathBuilder::replay_from_keyframe`]; clones attach to the same path.
+pub struct Replay<O>(Arc<Mutex<ReplayState<O>>>);
+
+struct ReplayState<O> {
+    history: VecDeque<O>,
+    max_items: usize,
+    keyframes: Option<fn(&O) -> bool>,
+    /// Whether the history starts at a keyframe, when retaining from keyframes.
+    synced: bool,
+    consumers: Vec<Sender<O>>,
+    ended: bool,
+}
+
+impl<O> Clone for Replay<O> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<O: Clone> Replay<O> {
+    /// Attaches a consumer, which is sent the retained history and then every item the path
+    /// carries from now on. `queue_size` is the room for live items on top of the history:
+    /// a consumer that falls further behind misses items rather than holding up the path.
+    /// Once the path has ended, the receiver yields the history and then ends.
+    pub fn attach(&self, queue_size: usize) -> Receiver<O> {
+        let mut state = self.0.lock().unwrap();
+        let (tx, rx) = flume::bounded(state.history.len() + queue_size.max(1));
+
+        for item in &state.history {
+            let _ = tx.try_send(item.clone());
+        }
+        if !state.ended {
+            state.consumers.push(tx);
+        }
+
+        rx
+    }
+
+    /// How many items a consumer attaching now would be replayed.
+    pub fn history_len(&self) -> usize {
+        self.0.lock().unwrap().history.len()
+    }
+
+    fn record(&self, item: &O) {
+        let mut state = self.0.lock().unwrap();
+
+        state.consumers.retain(|consumer| {
+            !matches!(
+                consumer.try_send(item.clone()),
+                Err(TrySendError::Disconnected(_))
+            )
+        });
+        state.retain(item);
+    }
+
+    fn end(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.ended = true;
+        state.consumers.clear();
+    }
+}
+
+impl<O: Clone> ReplayState<O> {
+    fn retain(&mut self, item: &O) {
+        if self.max_items == 0 {
+            return;
+        }
+
+        match self.keyframes {
+            Some(is_keyframe) if is_keyframe(item) => {
+                self.history.clear();
+                self.synced = true;
+            }
+            Some(_) if !self.synced => return,
+            _ => {}
+        }
+
+        if self.history.len() == self.max_items {
+            if self.keyframes.is_some() {
+                // A history that doesn't start at a keyframe can't be decoded.
+                self.history.clear();
+                self.synced = false;
+                return;
+            }
+            self.history.pop_front();
+        }
+
+        self.history.push_back(item.clone());
+    }
+}
+
+impl<T, PreviousOutput: Clone + Send + 'static> PipelinePathBuilder<T, PreviousOutput> {
+    /// Passes the items through unchanged, keeping the last `history` of them for the
+    /// consumers attached mid-stream through the returned [`Replay`].
+    pub fn replay_last(
+        self,
+        name: impl Into<String>,
+        history: usize,
+    ) -> (Self, Replay<PreviousOutput>) {
+        self.replay(name, history, None)
+    }
+
+    /// Like [`Self::replay_last`], but keeps the items from the latest keyframe on, so a
+    /// late-joining preview can decode straight away instead of waiting for the next
+    /// keyframe. Nothing is kept before the first keyframe. If more than `max_items` arrive
+    /// after a keyframe, the history is discarded until the next one, so attaching then
+    /// replays nothing.
+    pub fn replay_from_keyframe(
+        self,
+        name: impl Into<String>,
+        max_items: usize,
+    ) -> (Self, Replay<PreviousOutput>)
+    where
+        PreviousOutput: Keyframe,
+    {
+        self.replay(name, max_items, Some(PreviousOutput::is_keyframe))
+    }
+
+    fn replay(
+        self,
+        name: impl Into<String>,
+        max_items: usize,
+        keyframes: Option<fn(&PreviousOutput) -> bool>,
+    ) -> (Self, Replay<PreviousOutput>) {
+        let replay = Replay(Arc::new(Mutex::new(ReplayState {
+            history: VecDeque::with_capacity(max_items),
+            max_items,
+            keyframes,
+            synced: false,
+            consumers: vec![],
+            ended: false,
+        })));
+        let mode = self.mode;
+
+        let path = self.pipe(name, {
+            let replay = replay.clone();
+
+            move |input, output, backpressure| {
+                for item in mode.items(&input, &output) {
+                    replay.record(&item);
+
+                    if !backpressure.send(&output, item) {
+                        break;
+                    }
+                }
+
+                replay.end();
+                Ok(())
+            }
+        });
+
+        (path, replay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{Pipeline, RealTimeClock};
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Frame {
+        Key(u32),
+        Delta(u32),
+    }
+
+    impl Keyframe for Frame {
+        fn is_keyframe(&self) -> bool {
+            matches!(self, Frame::Key(_))
+        }
+    }
+
+    #[tokio::test]
+    async fn late_consumer_starts_from_the_latest_keyframe() {
+        use Frame::*;
+
+        let (input_tx, input_rx) = flume::bounded(8);
+
+        let (path, replay) = Pipeline::builder(RealTimeClock::<()>::new())
+            .path(input_rx)
+            .replay_from_keyframe("replay", 8);
+        let (builder, output) = path.into_receiver();
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        for frame in [Key(0), Delta(1), Key(2), Delta(3)] {
+            input_tx.send(frame).unwrap();
+        }
+        for _ in 0..4 {
+            output.recv().unwrap();
+        }
+
+        let preview = replay.attach(4);
+        input_tx.send(Delta(4)).unwrap();
+        drop(input_tx);
+
+        assert_eq!(output.iter().collect::<Vec<_>>(), vec![Delta(4)]);
+        assert_eq!(
+            preview.iter().collect::<Vec<_>>(),
+            vec![Key(2), Delta(3), Delta(4)]
+        );
+
+        pipeline.shutdown().await.unwrap();
+    }
+}
@@ -0,0 +1,1411 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use flume::{Receiver, RecvTimeoutError, Sender, TryRecvError, TrySendError};
+use tracing::warn;
+
+use crate::pipeline::{
+    builder::{LaunchOptions, PipelineBuilder, PipelinePathBuilder},
+    completion::DurationMeasure,
+    metrics::{MeteredSender, SingleConsumer},
+    task::{FnSink, PipelineSinkTask, DEFAULT_QUEUE_SIZE},
+    PipelineClock,
+};
+use crate::MediaError;
+
+/// How a stage with several outputs reacts to one of them being full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backpressure {
+    /// Wait for room on every output, so the slowest consumer paces the input.
+    #[default]
+    Shared,
+    /// Drop (and count) items for an output that is full, so a slow consumer
+    /// doesn't hold up the others.
+    Independent,
+}
+
+impl Backpressure {
+    /// Returns whether the output is still connected.
+    fn send<O>(self, output: &MeteredSender<O>, item: O) -> bool {
+        match self {
+            Self::Shared => output.send(item).is_ok(),
+            Self::Independent => {
+                !matches!(output.try_send(item), Err(TrySendError::Disconnected(_)))
+            }
+        }
+    }
+}
+
+/// Whether a path favours completeness or timeliness, see [`PipelinePathBuilder::real_time`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathMode {
+    /// Record everything: no item is ever dropped, and a slow stage holds up the ones
+    /// before it instead.
+    #[default]
+    Complete,
+    /// Being late is worse than being incomplete, e.g. for live streaming. Items that
+    /// miss their deadline of `target_latency` are skipped, stages skip to the newest
+    /// queued item when they fall behind, and items for a full output are dropped rather
+    /// than waited on. Skipped items are counted in
+    /// [`EdgeSnapshot::skipped_late`](super::metrics::EdgeSnapshot::skipped_late).
+    RealTime { target_latency: Duration },
+}
+
+impl PathMode {
+    /// The input's items, skipping any that are superseded by a newer one already queued
+    /// when items have to be timely.
+    pub(super) fn items<'a, I, O>(
+        self,
+        input: &'a Receiver<I>,
+        output: &'a MeteredSender<O>,
+    ) -> impl Iterator<Item = I> + 'a {
+        std::iter::from_fn(move || {
+            let mut item = input.recv().ok()?;
+
+            if let Self::RealTime { .. } = self {
+                while let Ok(newer) = input.try_recv() {
+                    output.record_skipped_late();
+                    item = newer;
+                }
+            }
+
+            Some(item)
+        })
+    }
+}
+
+/// What a [`PipelinePathBuilder::window`] stage does with a window that received no items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyWindow {
+    /// Emit nothing for the window.
+    #[default]
+    Skip,
+    /// Call the fold with an empty slice, e.g. so a level meter falls back to silence.
+    Emit,
+}
+
+/// Which input a [`PipelineBuilder::merge`] stage takes its next item from when several
+/// have items queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergePolicy {
+    /// Take turns between the inputs.
+    #[default]
+    RoundRobin,
+    /// Take from whichever input has the most items queued, so a bursty input is drained
+    /// before it overflows, at the cost of the others waiting out the burst. Keeps the
+    /// worst-case buffering of any one input down when the inputs' rates vary.
+    FairByOccupancy,
+}
+
+impl MergePolicy {
+    /// The input to take the next item from, or `None` if all of them are empty. `next` is
+    /// the input whose turn it is for [`MergePolicy::RoundRobin`].
+    fn pick<O>(self, inputs: &[Receiver<O>], next: usize) -> Option<usize> {
+        match self {
+            Self::RoundRobin => (0..inputs.len())
+                .map(|offset| (next + offset) % inputs.len())
+                .find(|index| !inputs[*index].is_empty()),
+            Self::FairByOccupancy => inputs
+                .iter()
+                .enumerate()
+                .filter(|(_, input)| !input.is_empty())
+                .max_by_key(|(_, input)| input.len())
+                .map(|(index, _)| index),
+        }
+    }
+}
+
+/// How often a [`PipelinePathBuilder::with_byte_capacity`] stage checks whether its
+/// consumer has made room while the byte budget is used up.
+const BYTE_BUDGET_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The memory an item takes up, for [`PipelinePathBuilder::with_byte_capacity`].
+///
+/// The default is the item's own size, which leaves out anything it owns on the heap.
+/// Types holding buffers, such as frames, should override it with the buffers' size.
+pub trait SizeHint {
+    fn size_hint_bytes(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
+}
+
+impl<F: SizeHint> SizeHint for (F, f64) {
+    fn size_hint_bytes(&self) -> usize {
+        self.0.size_hint_bytes() + std::mem::size_of::<f64>()
+    }
+}
+
+impl<T> SizeHint for Vec<T> {
+    fn size_hint_bytes(&self) -> usize {
+        self.len() * std::mem::size_of::<T>()
+    }
+}
+
+impl SizeHint for ffmpeg::frame::Video {
+    fn size_hint_bytes(&self) -> usize {
+        (0..self.planes()).map(|plane| self.data(plane).len()).sum()
+    }
+}
+
+impl SizeHint for ffmpeg::frame::Audio {
+    fn size_hint_bytes(&self) -> usize {
+        (0..self.planes()).map(|plane| self.data(plane).len()).sum()
+    }
+}
+
+/// The sizes of the items on an edge, oldest first. The consumer reads from a plain
+/// receiver, so items are forgotten once the edge holds fewer than are tracked here.
+struct ByteBudget {
+    capacity: usize,
+    sizes: VecDeque<usize>,
+    queued_bytes: usize,
+}
+
+impl ByteBudget {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            sizes: VecDeque::new(),
+            queued_bytes: 0,
+        }
+    }
+
+    fn settle(&mut self, queued_items: usize) {
+        while self.sizes.len() > queued_items {
+            if let Some(size) = self.sizes.pop_front() {
+                self.queued_bytes -= size;
+            }
+        }
+    }
+
+    /// An item larger than the whole budget still fits into an empty edge, so it can't
+    /// block the edge forever.
+    fn fits(&self, size: usize) -> bool {
+        self.sizes.is_empty() || self.queued_bytes + size <= self.capacity
+    }
+
+    fn push(&mut self, size: usize) {
+        self.sizes.push_back(size);
+        self.queued_bytes += size;
+    }
+}
+
+/// Items with a rewritable timestamp, for [`PipelinePathBuilder::shift_timestamps`].
+pub trait TimestampMut {
+    fn timestamp(&self) -> Duration;
+
+    fn set_timestamp(&mut self, timestamp: Duration);
+}
+
+/// Items of which some should jump the queue, for [`PipelinePathBuilder::prioritize`],
+/// e.g. keyframe requests mixed in with frames.
+pub trait Priority {
+    fn is_high_priority(&self) -> bool;
+}
+
+/// How many high-priority items [`PipelinePathBuilder::prioritize`] lets through in a row
+/// by default while a normal item is waiting.
+pub const DEFAULT_MAX_PRIORITY_RUN: usize = 8;
+
+/// Frames paired with their timestamp in seconds, as produced by the capture sources.
+impl<F> TimestampMut for (F, f64) {
+    fn timestamp(&self) -> Duration {
+        Duration::from_secs_f64(self.1.max(0.0))
+    }
+
+    fn set_timestamp(&mut self, timestamp: Duration) {
+        self.1 = timestamp.as_secs_f64();
+    }
+}
+
+impl<T> PipelineBuilder<T> {
+    /// Spawns a task that writes into a new metered edge, and starts a path from that edge.
+    fn stage<O: Send + 'static>(
+        mut self,
+        name: impl Into<String>,
+        queue_size: usize,
+        run: impl FnOnce(MeteredSender<O>) -> Result<(), String> + Send + 'static,
+    ) -> PipelinePathBuilder<T, O> {
+        let name = name.into();
+        let (output, next_input) = self.task_edge(&name, name.clone(), queue_size);
+
+        self.spawn_task(name, move |ready| {
+            let _ = ready.send(Ok(()));
+            run(output)
+        });
+
+        PipelinePathBuilder::new(self, next_input)
+    }
+
+    /// Interleaves the items of all `inputs` as they arrive, choosing between the inputs
+    /// that have items queued according to `policy`. The path ends once all inputs end.
+    ///
+    /// Inputs from other paths can be obtained with [`PipelinePathBuilder::into_receiver`].
+    pub fn merge<O: Send + 'static>(
+        self,
+        name: impl Into<String>,
+        inputs: impl IntoIterator<Item = impl Into<SingleConsumer<O>>>,
+        policy: MergePolicy,
+    ) -> PipelinePathBuilder<T, O> {
+        let name = name.into();
+        let mut inputs = inputs
+            .into_iter()
+            .map(|input| input.into().into_inner())
+            .collect::<Vec<_>>();
+        for input in &inputs {
+            self.consumes(&name, input);
+        }
+
+        self.stage(name, DEFAULT_QUEUE_SIZE, move |output| {
+            let mut next = 0;
+
+            loop {
+                inputs.retain(|input| !(input.is_disconnected() && input.is_empty()));
+                if inputs.is_empty() {
+                    break;
+                }
+
+                let received = match policy.pick(&inputs, next) {
+                    Some(index) => {
+                        next = index + 1;
+                        inputs[index].try_recv().ok()
+                    }
+                    // Nothing is queued, so wait for whichever input produces first.
+                    None => inputs
+                        .iter()
+                        .fold(flume::Selector::new(), |selector, input| {
+                            selector.recv(input, Result::ok)
+                        })
+                        .wait(),
+                };
+
+                if let Some(item) = received {
+                    if output.send(item).is_err() {
+                        break;
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Pairs items one-for-one from both inputs, waiting until each has an item. Unlike
+    /// interleaving the inputs, this keeps them in lockstep: if one input produces faster,
+    /// it builds up backpressure until the other catches up. The path ends as soon as
+    /// either input ends, dropping any unpaired item.
+    ///
+    /// Inputs from other paths can be obtained with [`PipelinePathBuilder::into_receiver`].
+    pub fn zip<A: Send + 'static, B: Send + 'static>(
+        self,
+        name: impl Into<String>,
+        a: impl Into<SingleConsumer<A>>,
+        b: impl Into<SingleConsumer<B>>,
+    ) -> PipelinePathBuilder<T, (A, B)> {
+        let name = name.into();
+        let (a, b) = (a.into().into_inner(), b.into().into_inner());
+        self.consumes(&name, &a);
+        self.consumes(&name, &b);
+
+        self.stage(name, DEFAULT_QUEUE_SIZE, move |output| {
+            loop {
+                let Ok(a) = a.recv() else { break };
+                let Ok(b) = b.recv() else { break };
+
+                if output.send((a, b)).is_err() {
+                    break;
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+impl<T, PreviousOutput: Send + 'static> PipelinePathBuilder<T, PreviousOutput> {
+    /// Adds the next stage to the path, applying the queue size and backpressure set for it.
+    pub(super) fn pipe<O: Send + 'static>(
+        self,
+        name: impl Into<String>,
+        run: impl FnOnce(Receiver<PreviousOutput>, MeteredSender<O>, Backpressure) -> Result<(), String>
+            + Send
+            + 'static,
+    ) -> PipelinePathBuilder<T, O> {
+        let Self {
+            mut pipeline,
+            next_input,
+            queue_size,
+            backpressure,
+            mode,
+            item_time,
+            latency,
+        } = self;
+
+        let backpressure = match mode {
+            PathMode::Complete => backpressure,
+            PathMode::RealTime { .. } => Backpressure::Independent,
+        };
+        let latency = pipeline.add_path_latency(latency, item_time, queue_size);
+        let name = name.into();
+        pipeline.consumes(&name, &next_input);
+
+        let mut path = pipeline.stage(name, queue_size, move |output| {
+            run(next_input, output, backpressure)
+        });
+        path.mode = mode;
+        path.latency = latency;
+        path
+    }
+
+    pub fn map<O: Send + 'static>(
+        self,
+        name: impl Into<String>,
+        mut f: impl FnMut(PreviousOutput) -> O + Send + 'static,
+    ) -> PipelinePathBuilder<T, O> {
+        let mode = self.mode;
+
+        self.pipe(name, move |input, output, backpressure| {
+            for item in mode.items(&input, &output) {
+                if !backpressure.send(&output, f(item)) {
+                    break;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Like [`Self::map`] for fallible transforms. The first item `f` fails on stops the
+    /// stage with its error, which fails the pipeline. See [`Self::try_map_with_dead_letter`]
+    /// to carry on instead.
+    pub fn try_map<O: Send + 'static>(
+        self,
+        name: impl Into<String>,
+        mut f: impl FnMut(PreviousOutput) -> Result<O, MediaError> + Send + 'static,
+    ) -> PipelinePathBuilder<T, O> {
+        let mode = self.mode;
+
+        self.pipe(name, move |input, output, backpressure| {
+            for item in mode.items(&input, &output) {
+                if !backpressure.send(&output, f(item).map_err(|error| error.to_string())?) {
+                    break;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Like [`Self::try_map`], but items that `f` fails on are sent to `dead_letter` along
+    /// with the error and the stage carries on, so problematic frames can be inspected
+    /// without losing the recording. `f` only borrows the item, so that the failed item
+    /// can be passed on whole.
+    ///
+    /// With [`Backpressure::Shared`] the stage waits for room on a full `dead_letter`
+    /// channel, while with [`Backpressure::Independent`] the failed item is dropped instead
+    /// and the drops are logged once the stage stops. Failed items are dropped as well once
+    /// nothing receives from `dead_letter` anymore.
+    pub fn try_map_with_dead_letter<O: Send + 'static>(
+        self,
+        name: impl Into<String>,
+        mut f: impl FnMut(&PreviousOutput) -> Result<O, MediaError> + Send + 'static,
+        dead_letter: Sender<(PreviousOutput, MediaError)>,
+    ) -> PipelinePathBuilder<T, O> {
+        let mode = self.mode;
+        let name = name.into();
+
+        self.pipe(name.clone(), move |input, output, backpressure| {
+            let mut dropped = 0usize;
+
+            for item in mode.items(&input, &output) {
+                let error = match f(&item) {
+                    Ok(mapped) => {
+                        if !backpressure.send(&output, mapped) {
+                            break;
+                        }
+                        continue;
+                    }
+                    Err(error) => error,
+                };
+
+                let delivered = match backpressure {
+                    Backpressure::Shared => dead_letter.send((item, error)).is_ok(),
+                    Backpressure::Independent => dead_letter.try_send((item, error)).is_ok(),
+                };
+
+                if !delivered {
+                    dropped += 1;
+                }
+            }
+
+            if dropped > 0 {
+                warn!("Stage {name} dropped {dropped} failed items the dead-letter channel couldn't take");
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Passes on only the items for which `f` returns true.
+    pub fn filter(
+        self,
+        name: impl Into<String>,
+        mut f: impl FnMut(&PreviousOutput) -> bool + Send + 'static,
+    ) -> Self {
+        let mode = self.mode;
+
+        self.pipe(name, move |input, output, backpressure| {
+            for item in mode.items(&input, &output).filter(|item| f(item)) {
+                if !backpressure.send(&output, item) {
+                    break;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Inserts a [`Self::map`] stage only when `enabled`, and otherwise passes items
+    /// through untouched. As the output type stays the same either way, this avoids
+    /// branching the builder code on config. Optional stages that change the
+    /// type still need that branching, since the type is part of the path.
+    pub fn maybe_map(
+        self,
+        enabled: bool,
+        name: impl Into<String>,
+        f: impl FnMut(PreviousOutput) -> PreviousOutput + Send + 'static,
+    ) -> Self {
+        if enabled {
+            self.map(name, f)
+        } else {
+            self
+        }
+    }
+
+    /// Splits each item into two components and routes them down separate outputs: the
+    /// first component continues this path, the second goes to the returned receiver
+    /// whenever `f` yields one. Runs until the input ends or both outputs are gone.
+    pub fn split<A: Send + 'static, B: Send + 'static>(
+        self,
+        name: impl Into<String>,
+        backpressure: Backpressure,
+        f: impl Fn(PreviousOutput) -> (A, Option<B>) + Send + 'static,
+    ) -> (PipelinePathBuilder<T, A>, SingleConsumer<B>) {
+        let Self {
+            mut pipeline,
+            next_input,
+            queue_size,
+            mode,
+            item_time,
+            latency,
+            ..
+        } = self;
+        let name = name.into();
+        let latency = pipeline.add_path_latency(latency, item_time, queue_size);
+
+        pipeline.consumes(&name, &next_input);
+        let (a_tx, a_rx) = pipeline.task_edge(&name, format!("{name}.0"), queue_size);
+        let (b_tx, b_rx) = pipeline.task_edge(&name, format!("{name}.1"), queue_size);
+
+        pipeline.spawn_task(name, move |ready| {
+            let _ = ready.send(Ok(()));
+
+            let mut a_open = true;
+            let mut b_open = true;
+
+            for item in mode.items(&next_input, &a_tx) {
+                let (a, b) = f(item);
+
+                if a_open {
+                    a_open = backpressure.send(&a_tx, a);
+                }
+
+                if let Some(b) = b.filter(|_| b_open) {
+                    b_open = backpressure.send(&b_tx, b);
+                }
+
+                if !a_open && !b_open {
+                    break;
+                }
+            }
+
+            Ok(())
+        });
+
+        let mut path = PipelinePathBuilder::new(pipeline, a_rx);
+        path.mode = mode;
+        path.latency = latency;
+
+        (path, SingleConsumer::new(b_rx))
+    }
+
+    /// The path's output type, for diagnostics.
+    pub fn output_type_name(&self) -> &'static str {
+        std::any::type_name::<PreviousOutput>()
+    }
+
+    /// Ends the path by forwarding its output into a channel the caller already owns.
+    /// This is the counterpart to [`PipelinePathBuilder::into_receiver`].
+    pub fn connect_to_receiver(
+        self,
+        name: impl Into<String>,
+        tx: Sender<PreviousOutput>,
+    ) -> PipelineBuilder<T> {
+        let Self {
+            mut pipeline,
+            next_input,
+            ..
+        } = self;
+        let name = name.into();
+        pipeline.consumes(&name, &next_input);
+
+        pipeline.spawn_task(name, move |ready| {
+            let _ = ready.send(Ok(()));
+
+            for item in next_input.iter() {
+                if tx.send(item).is_err() {
+                    break;
+                }
+            }
+
+            Ok(())
+        });
+
+        pipeline
+    }
+
+    /// Ends the path with an [`FnSink`]. An error from its closure stops the sink and is
+    /// reported as the task's result.
+    pub fn fn_sink(
+        self,
+        name: impl Into<String>,
+        mut sink: FnSink<PreviousOutput>,
+    ) -> PipelineBuilder<T> {
+        let Self {
+            mut pipeline,
+            next_input,
+            ..
+        } = self;
+        let name = name.into();
+        pipeline.consumes(&name, &next_input);
+        let control_signal = pipeline.control_signal(name.clone(), sink.acks_control_messages());
+
+        pipeline.spawn_task(name, move |ready| {
+            let _ = ready.send(Ok(()));
+            sink.run(&next_input, control_signal)
+                .map_err(|error| error.to_string())
+        });
+
+        pipeline
+    }
+
+    /// Ends the path with a sink task, which is finished once its input ends.
+    pub fn sink(
+        self,
+        name: impl Into<String>,
+        mut task: impl PipelineSinkTask<PreviousOutput> + 'static,
+    ) -> PipelineBuilder<T> {
+        let Self {
+            mut pipeline,
+            next_input,
+            ..
+        } = self;
+        let name = name.into();
+        pipeline.consumes(&name, &next_input);
+
+        let context = pipeline.task_context();
+        let options = LaunchOptions {
+            capabilities: task.required_capabilities(),
+            ..Default::default()
+        };
+
+        pipeline.launch_task(name, options, move |ready| {
+            task.run_with_context(ready, &next_input, context);
+            task.finish();
+            Ok(())
+        });
+
+        pipeline
+    }
+}
+
+impl<T, PreviousOutput: SizeHint + Send + 'static> PipelinePathBuilder<T, PreviousOutput> {
+    /// Passes items through onto an edge that holds at most `bytes` worth of items, as
+    /// measured by [`SizeHint`], e.g. so a few 4K frames can't take up gigabytes of memory.
+    /// The edge is still bounded by the queue size set for it too, and whichever limit is
+    /// hit first applies. Once the byte budget is used up, the stage waits for room with
+    /// [`Backpressure::Shared`] or drops and counts the item with
+    /// [`Backpressure::Independent`].
+    pub fn with_byte_capacity(self, name: impl Into<String>, bytes: usize) -> Self {
+        self.pipe(name, move |input, output, backpressure| {
+            let mut budget = ByteBudget::new(bytes);
+
+            'items: for item in input.iter() {
+                let size = item.size_hint_bytes();
+
+                loop {
+                    budget.settle(output.len());
+
+                    if budget.fits(size) {
+                        break;
+                    }
+
+                    if output.is_disconnected() {
+                        return Ok(());
+                    }
+
+                    match backpressure {
+                        Backpressure::Shared => thread::sleep(BYTE_BUDGET_POLL_INTERVAL),
+                        Backpressure::Independent => {
+                            output.record_dropped();
+                            continue 'items;
+                        }
+                    }
+                }
+
+                let sent = match backpressure {
+                    Backpressure::Shared => output.send(item).map_err(|_| ()),
+                    Backpressure::Independent => match output.try_send(item) {
+                        Err(TrySendError::Disconnected(_)) => Err(()),
+                        // Already counted as dropped, and not queued.
+                        Err(TrySendError::Full(_)) => continue,
+                        Ok(()) => Ok(()),
+                    },
+                };
+
+                if sent.is_err() {
+                    break;
+                }
+
+                budget.push(size);
+            }
+
+            Ok(())
+        })
+    }
+}
+
+impl<T, PreviousOutput: TimestampMut + Send + 'static> PipelinePathBuilder<T, PreviousOutput> {
+    /// Delays each item's timestamp by `offset`, e.g. to line up a stream that started
+    /// early with the others. Items are rewritten as they pass and are never reordered or
+    /// held back, so this only changes what the timestamps say.
+    pub fn shift_timestamps(self, name: impl Into<String>, offset: Duration) -> Self {
+        self.map(name, move |mut item| {
+            item.set_timestamp(item.timestamp() + offset);
+            item
+        })
+    }
+
+    /// Like [`Self::shift_timestamps`], but moves timestamps earlier. Timestamps that would
+    /// end up before the start of the stream are clamped to zero, so the first items after
+    /// the shift can share a timestamp.
+    pub fn shift_timestamps_earlier(self, name: impl Into<String>, offset: Duration) -> Self {
+        self.map(name, move |mut item| {
+            item.set_timestamp(item.timestamp().saturating_sub(offset));
+            item
+        })
+    }
+}
+
+impl<T: PipelineClock, PreviousOutput: Send + 'static> PipelinePathBuilder<T, PreviousOutput> {
+    /// Collects the items arriving within each `duration` long window and emits the result
+    /// of folding them, e.g. for an average audio level over 100ms. Windows are timed
+    /// against the pipeline clock, so time spent paused doesn't count towards a window.
+    /// A partially filled window is flushed when the input ends.
+    pub fn window<O: Send + 'static>(
+        self,
+        name: impl Into<String>,
+        duration: Duration,
+        empty: EmptyWindow,
+        fold: impl Fn(&[PreviousOutput]) -> O + Send + 'static,
+    ) -> PipelinePathBuilder<T, O> {
+        let clock = self.pipeline.clock().clone();
+
+        self.pipe(name, move |next_input, output, backpressure| {
+            let mut window = vec![];
+            let mut window_start = clock.now();
+
+            loop {
+                // Wake up at the end of the window even if nothing arrives.
+                let timeout = duration
+                    .saturating_sub(clock.elapsed_since(window_start))
+                    .max(Duration::from_millis(1));
+
+                match next_input.recv_timeout(timeout) {
+                    Ok(item) => window.push(item),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+
+                if clock.elapsed_since(window_start) < duration {
+                    continue;
+                }
+                window_start = clock.now();
+
+                if window.is_empty() && empty == EmptyWindow::Skip {
+                    continue;
+                }
+
+                if !backpressure.send(&output, fold(&window)) {
+                    return Ok(());
+                }
+                window.clear();
+            }
+
+            if !window.is_empty() {
+                backpressure.send(&output, fold(&window));
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Forwards an item only once `quiet` has passed without another one arriving, and
+    /// then only the latest, e.g. to react once to a burst of config changes. Unlike a rate
+    /// limit, a steady stream of items holds everything back until it settles. The quiet
+    /// period is timed against the pipeline clock, so time spent paused doesn't count. A
+    /// pending item is flushed when the input ends.
+    pub fn debounce(self, name: impl Into<String>, quiet: Duration) -> Self {
+        let clock = self.pipeline.clock().clone();
+
+        self.pipe(name, move |next_input, output, backpressure| {
+            let mut pending = None;
+            let mut last_arrival = clock.now();
+
+            loop {
+                let received = if pending.is_some() {
+                    let timeout = quiet
+                        .saturating_sub(clock.elapsed_since(last_arrival))
+                        .max(Duration::from_millis(1));
+                    next_input.recv_timeout(timeout)
+                } else {
+                    next_input
+                        .recv()
+                        .map_err(|_| RecvTimeoutError::Disconnected)
+                };
+
+                match received {
+                    Ok(item) => {
+                        pending = Some(item);
+                        last_arrival = clock.now();
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+
+                if clock.elapsed_since(last_arrival) < quiet {
+                    continue;
+                }
+
+                if let Some(item) = pending.take() {
+                    if !backpressure.send(&output, item) {
+                        return Ok(());
+                    }
+                }
+            }
+
+            if let Some(item) = pending {
+                backpressure.send(&output, item);
+            }
+
+            Ok(())
+        })
+    }
+}
+
+/// Counts the samples taken by [`PipelinePathBuilder::sample_every`], to tell whether the
+/// sample receiver keeps up.
+#[derive(Debug, Clone, Default)]
+pub struct SampleStats(Arc<SampleCounts>);
+
+#[derive(Debug, Default)]
+struct SampleCounts {
+    emitted: AtomicU64,
+    expected: AtomicU64,
+}
+
+impl SampleStats {
+    /// The samples handed to the sample receiver so far.
+    pub fn emitted(&self) -> u64 {
+        self.0.emitted.load(Ordering::Relaxed)
+    }
+
+    /// The samples there would have been so far at exactly one per interval, from the first
+    /// item up to the latest one. [`Self::emitted`] falls behind this for intervals in which
+    /// no item arrived, and for samples dropped because the receiver was full.
+    pub fn expected(&self) -> u64 {
+        self.0.expected.load(Ordering::Relaxed)
+    }
+}
+
+impl<T: PipelineClock, PreviousOutput: Clone + Send + 'static>
+    PipelinePathBuilder<T, PreviousOutput>
+{
+    /// Forks off a copy of one item per `interval` to the returned receiver, e.g. a frame
+    /// every few seconds for snapshots, while the path itself carries on with every item.
+    /// The first item is always sampled, and after that the first one to arrive in each
+    /// interval. `measure` picks whether intervals are timed against the pipeline clock,
+    /// so time spent paused doesn't count, or against wall time.
+    ///
+    /// The samples never hold up the path: a sample that the receiver has no room for is
+    /// dropped, and shows up as missing from [`SampleStats::emitted`].
+    pub fn sample_every(
+        self,
+        name: impl Into<String>,
+        interval: Duration,
+        measure: DurationMeasure,
+    ) -> (Self, SingleConsumer<PreviousOutput>, SampleStats) {
+        let Self {
+            mut pipeline,
+            next_input,
+            queue_size,
+            backpressure,
+            mode,
+            item_time,
+            latency,
+        } = self;
+        let name = name.into();
+        let clock = pipeline.clock().clone();
+        let latency = pipeline.add_path_latency(latency, item_time, queue_size);
+
+        let backpressure = match mode {
+            PathMode::Complete => backpressure,
+            PathMode::RealTime { .. } => Backpressure::Independent,
+        };
+        let stats = SampleStats::default();
+
+        pipeline.consumes(&name, &next_input);
+        let (output, output_rx) = pipeline.task_edge(&name, name.clone(), queue_size);
+        let (samples, samples_rx) = pipeline.task_edge(&name, format!("{name}.samples"), 1);
+
+        pipeline.spawn_task(name, {
+            let counts = stats.0.clone();
+
+            move |ready| {
+                let _ = ready.send(Ok(()));
+
+                let mut started = None;
+                // In nanoseconds, as a `Duration` can't be multiplied by more than `u32::MAX`
+                // intervals, which a short interval passes within seconds.
+                let interval = interval.as_nanos().max(1);
+                let mut next_sample = 0;
+                let mut samples_open = true;
+
+                for item in mode.items(&next_input, &output) {
+                    let (clock_start, wall_start) =
+                        *started.get_or_insert_with(|| (clock.now(), Instant::now()));
+                    let elapsed = match measure {
+                        DurationMeasure::Clock => clock.elapsed_since(clock_start),
+                        DurationMeasure::Wall => wall_start.elapsed(),
+                    };
+                    let elapsed = elapsed.as_nanos();
+                    let intervals = elapsed / interval;
+                    counts.expected.store(
+                        u64::try_from(intervals + 1).unwrap_or(u64::MAX),
+                        Ordering::Relaxed,
+                    );
+
+                    if samples_open && elapsed >= next_sample {
+                        // Skip the intervals that passed without an item rather than
+                        // catching up on them with a burst.
+                        next_sample = interval.saturating_mul(intervals + 1);
+
+                        match samples.try_send(samples.clone_item(&item)) {
+                            Ok(()) => {
+                                counts.emitted.fetch_add(1, Ordering::Relaxed);
+                            }
+                            Err(TrySendError::Full(_)) => {}
+                            Err(TrySendError::Disconnected(_)) => samples_open = false,
+                        }
+                    }
+
+                    if !backpressure.send(&output, item) {
+                        break;
+                    }
+                }
+
+                Ok(())
+            }
+        });
+
+        let mut path = PipelinePathBuilder::new(pipeline, output_rx);
+        path.mode = mode;
+        path.latency = latency;
+
+        (path, SingleConsumer::new(samples_rx), stats)
+    }
+
+    /// Ends the path by sending a copy of every item to each of `outputs`, e.g. to a disk
+    /// writer that must get everything and a network uploader that may shed load. Each output
+    /// has its own queue and [`Backpressure`]: a full [`Backpressure::Shared`] output holds up
+    /// the stage, and with it every other output, while a full [`Backpressure::Independent`]
+    /// output has the item dropped and counted as a drop of its own edge, named
+    /// `{name}.{index}`. On a real-time path every output drops. Runs until the input ends or
+    /// every output is gone.
+    pub fn fan_out(
+        self,
+        name: impl Into<String>,
+        outputs: impl IntoIterator<Item = FanOutput>,
+    ) -> (PipelineBuilder<T>, Vec<SingleConsumer<PreviousOutput>>) {
+        let Self {
+            mut pipeline,
+            next_input,
+            mode,
+            ..
+        } = self;
+        let name = name.into();
+        pipeline.consumes(&name, &next_input);
+
+        let mut senders = vec![];
+        let mut receivers = vec![];
+        for (index, output) in outputs.into_iter().enumerate() {
+            let (tx, rx) = pipeline.task_edge(&name, format!("{name}.{index}"), output.queue_size);
+            let backpressure = match mode {
+                PathMode::Complete => output.backpressure,
+                PathMode::RealTime { .. } => Backpressure::Independent,
+            };
+            senders.push((tx, backpressure, true));
+            receivers.push(SingleConsumer::new(rx));
+        }
+
+        pipeline.spawn_task(name, move |ready| {
+            let _ = ready.send(Ok(()));
+
+            for item in next_input.iter() {
+                for (output, backpressure, open) in &mut senders {
+                    if *open {
+                        *open = backpressure.send(output, output.clone_item(&item));
+                    }
+                }
+
+                if !senders.iter().any(|(_, _, open)| *open) {
+                    break;
+                }
+            }
+
+            Ok(())
+        });
+
+        (pipeline, receivers)
+    }
+}
+
+/// One output of [`PipelinePathBuilder::fan_out`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FanOutput {
+    /// How many items the output queues before it counts as full.
+    pub queue_size: usize,
+    pub backpressure: Backpressure,
+}
+
+impl FanOutput {
+    /// An output that waits for room, so its consumer gets every item.
+    pub fn complete(queue_size: usize) -> Self {
+        Self {
+            queue_size,
+            backpressure: Backpressure::Shared,
+        }
+    }
+
+    /// An output that drops items while it's full, so its consumer can't hold up the others.
+    pub fn lossy(queue_size: usize) -> Self {
+        Self {
+            queue_size,
+            backpressure: Backpressure::Independent,
+        }
+    }
+}
+
+/// How long [`PipelinePathBuilder::warmup`] discards items for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Warmup {
+    /// Until the pipeline clock has run for this long since the first item arrived, so
+    /// time spent paused doesn't count.
+    Duration(Duration),
+    /// The first this many items.
+    Items(u64),
+}
+
+impl<T: PipelineClock, PreviousOutput: Send + 'static> PipelinePathBuilder<T, PreviousOutput> {
+    /// Discards the items produced during `warmup`, e.g. the frames of a camera whose auto
+    /// exposure is still settling, so the first item that reaches the sink, and so the
+    /// first frame of the file, is a good one. Discarded items are counted in
+    /// [`EdgeSnapshot::skipped_warmup`](super::metrics::EdgeSnapshot::skipped_warmup) rather
+    /// than as drops.
+    pub fn warmup(self, name: impl Into<String>, warmup: Warmup) -> Self {
+        let clock = self.pipeline.clock().clone();
+        let mode = self.mode;
+
+        self.pipe(name, move |next_input, output, backpressure| {
+            let mut first_arrival = None;
+            let mut seen = 0;
+            let mut warming_up = true;
+
+            for item in mode.items(&next_input, &output) {
+                if warming_up {
+                    let first_arrival = *first_arrival.get_or_insert_with(|| clock.now());
+                    seen += 1;
+                    warming_up = match warmup {
+                        Warmup::Duration(duration) => clock.elapsed_since(first_arrival) < duration,
+                        Warmup::Items(items) => seen <= items,
+                    };
+
+                    if warming_up {
+                        output.record_skipped_warmup();
+                        continue;
+                    }
+                }
+
+                if !backpressure.send(&output, item) {
+                    break;
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+impl<T, PreviousOutput: Priority + Send + 'static> PipelinePathBuilder<T, PreviousOutput> {
+    /// Reorders the path so high-priority items go before the normal items queued ahead of
+    /// them, without a separate edge for them. Items of the same priority keep their order.
+    ///
+    /// The stage buffers up to its input's capacity and reorders what it has buffered, so
+    /// priority only applies up to the stage's output: items already queued there are
+    /// passed on in order. Give the stage a small queue size for high-priority items to
+    /// reach the consumer soonest. To bound starvation, no more than `max_run` high-priority
+    /// items are sent in a row while a normal item is waiting, see
+    /// [`DEFAULT_MAX_PRIORITY_RUN`].
+    pub fn prioritize(self, name: impl Into<String>, max_run: usize) -> Self {
+        self.pipe(name, move |next_input, output, backpressure| {
+            let capacity = next_input.capacity().unwrap_or(DEFAULT_QUEUE_SIZE).max(1);
+            let mut high = VecDeque::new();
+            let mut normal = VecDeque::new();
+            let mut run = 0;
+            let mut open = true;
+
+            loop {
+                while open && high.len() + normal.len() < capacity {
+                    let received = if high.is_empty() && normal.is_empty() {
+                        next_input.recv().map_err(|_| TryRecvError::Disconnected)
+                    } else {
+                        next_input.try_recv()
+                    };
+
+                    match received {
+                        Ok(item) if item.is_high_priority() => high.push_back(item),
+                        Ok(item) => normal.push_back(item),
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => open = false,
+                    }
+                }
+
+                let item = if !high.is_empty() && (normal.is_empty() || run < max_run) {
+                    run += 1;
+                    high.pop_front()
+                } else {
+                    run = 0;
+                    normal.pop_front()
+                };
+
+                let Some(item) = item else { break };
+                if !backpressure.send(&output, item) {
+                    break;
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+impl<T: PipelineClock, PreviousOutput: TimestampMut + Send + 'static>
+    PipelinePathBuilder<T, PreviousOutput>
+{
+    /// Switches the rest of the path to [`PathMode::RealTime`], starting with a stage that
+    /// skips items that are more than `target_latency` late. An item's deadline is its
+    /// timestamp relative to the first item's, plus the target latency, measured against
+    /// the time the pipeline clock has been running since the first item arrived.
+    pub fn real_time(mut self, name: impl Into<String>, target_latency: Duration) -> Self {
+        let clock = self.pipeline.clock().clone();
+        self.mode = PathMode::RealTime { target_latency };
+
+        self.pipe(name, move |next_input, output, backpressure| {
+            let mut first = None;
+
+            for item in next_input.iter() {
+                let (first_timestamp, first_arrival) =
+                    *first.get_or_insert_with(|| (item.timestamp(), clock.now()));
+                let deadline = item.timestamp().saturating_sub(first_timestamp) + target_latency;
+
+                if clock.elapsed_since(first_arrival) > deadline {
+                    output.record_skipped_late();
+                    continue;
+                }
+
+                if !backpressure.send(&output, item) {
+                    break;
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backpressure, FanOutput, MergePolicy, Priority, Warmup};
+    use crate::{
+        pipeline::{Pipeline, RealTimeClock},
+        MediaError,
+    };
+
+    #[tokio::test]
+    async fn zip_pairs_items_in_order() {
+        let (a_tx, a_rx) = flume::bounded(8);
+        let (b_tx, b_rx) = flume::bounded(8);
+
+        let (builder, pairs) = Pipeline::builder(RealTimeClock::<()>::new())
+            .zip("zip", a_rx, b_rx)
+            .into_receiver();
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        for i in 0..4 {
+            a_tx.send(i).unwrap();
+        }
+        for i in 0..3 {
+            b_tx.send(i * 10).unwrap();
+        }
+        drop((a_tx, b_tx));
+
+        let pairs = pairs.iter().collect::<Vec<_>>();
+        assert_eq!(pairs, vec![(0, 0), (1, 10), (2, 20)]);
+
+        pipeline.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn merge_takes_the_outputs_of_other_paths() {
+        let (a_tx, a_rx) = flume::bounded(8);
+        let (b_tx, b_rx) = flume::bounded(8);
+
+        let (builder, a) = Pipeline::builder(RealTimeClock::<()>::new())
+            .path(a_rx)
+            .map("double", |n: u32| n * 2)
+            .into_receiver();
+        let (builder, b) = builder.path(b_rx).into_receiver();
+        let (builder, merged) = builder
+            .merge("merge", [a, b], MergePolicy::RoundRobin)
+            .into_receiver();
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        a_tx.send(1).unwrap();
+        b_tx.send(3).unwrap();
+        drop((a_tx, b_tx));
+
+        let mut merged = merged.iter().collect::<Vec<_>>();
+        merged.sort();
+        assert_eq!(merged, vec![2, 3]);
+
+        pipeline.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn split_routes_only_present_components_to_the_second_output() {
+        let (input_tx, input_rx) = flume::bounded(8);
+
+        let (path, evens) = Pipeline::builder(RealTimeClock::<()>::new())
+            .path(input_rx)
+            .split("split", Backpressure::Shared, |n: u32| {
+                (n, (n % 2 == 0).then_some(n * 10))
+            });
+        let (builder, all) = path.into_receiver();
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        for n in 0..5 {
+            input_tx.send(n).unwrap();
+        }
+        drop(input_tx);
+
+        assert_eq!(all.iter().collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(evens.iter().collect::<Vec<_>>(), vec![0, 20, 40]);
+
+        pipeline.shutdown().await.unwrap();
+    }
+
+    #[test]
+    fn fair_by_occupancy_keeps_a_bursty_input_from_overflowing() {
+        let (bursty_tx, bursty_rx) = flume::bounded(8);
+        let (steady_tx, steady_rx) = flume::bounded(8);
+        let inputs = [bursty_rx, steady_rx];
+        let mut steady_sent_at = std::collections::VecDeque::new();
+        let mut longest_steady_wait = 0;
+
+        // The steady input produces every tick and the bursty one fills its channel every
+        // 8 ticks, while the merge forwards two items a tick.
+        for tick in 0..64 {
+            steady_tx.try_send(()).unwrap();
+            steady_sent_at.push_back(tick);
+            if tick % 8 == 0 {
+                for _ in 0..8 {
+                    bursty_tx.try_send(()).expect("bursty input overflowed");
+                }
+            }
+
+            for _ in 0..2 {
+                let index = MergePolicy::FairByOccupancy.pick(&inputs, 0).unwrap();
+                inputs[index].try_recv().unwrap();
+
+                if index == 1 {
+                    let sent_at = steady_sent_at.pop_front().unwrap();
+                    longest_steady_wait = longest_steady_wait.max(tick - sent_at);
+                }
+            }
+        }
+
+        assert!(
+            longest_steady_wait <= 4,
+            "steady input waited {longest_steady_wait} ticks"
+        );
+    }
+
+    #[tokio::test]
+    async fn try_map_with_dead_letter_routes_failed_items() {
+        let (input_tx, input_rx) = flume::bounded(8);
+        let (dead_letter_tx, dead_letter_rx) = flume::bounded(8);
+
+        let (builder, output) = Pipeline::builder(RealTimeClock::<()>::new())
+            .path(input_rx)
+            .try_map_with_dead_letter(
+                "halve",
+                |n: &u32| {
+                    if n % 2 == 0 {
+                        Ok(n / 2)
+                    } else {
+                        Err(MediaError::Any(format!("{n} is odd").into()))
+                    }
+                },
+                dead_letter_tx,
+            )
+            .into_receiver();
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        for n in 0..5 {
+            input_tx.send(n).unwrap();
+        }
+        drop(input_tx);
+
+        assert_eq!(output.iter().collect::<Vec<_>>(), vec![0, 1, 2]);
+        let failed = dead_letter_rx.iter().map(|(n, _)| n).collect::<Vec<_>>();
+        assert_eq!(failed, vec![1, 3]);
+
+        pipeline.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn warmup_discards_the_first_items_without_counting_them_as_drops() {
+        let (input_tx, input_rx) = flume::bounded(8);
+
+        let (builder, output) = Pipeline::builder(RealTimeClock::<()>::new())
+            .path(input_rx)
+            .warmup("settle", Warmup::Items(3))
+            .into_receiver();
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        for n in 0..6u32 {
+            input_tx.send(n).unwrap();
+        }
+        drop(input_tx);
+
+        assert_eq!(output.iter().collect::<Vec<_>>(), vec![3, 4, 5]);
+        let edge = pipeline.metrics().edges["settle"];
+        assert_eq!((edge.skipped_warmup, edge.dropped), (3, 0));
+
+        pipeline.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn fan_out_lets_a_lossy_output_drop_while_a_complete_one_keeps_everything() {
+        let (input_tx, input_rx) = flume::bounded(8);
+
+        let (builder, outputs) = Pipeline::builder(RealTimeClock::<()>::new())
+            .path(input_rx)
+            .fan_out("tee", [FanOutput::complete(16), FanOutput::lossy(1)]);
+        let mut outputs = outputs.into_iter();
+        let (disk, upload) = (outputs.next().unwrap(), outputs.next().unwrap());
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        // The uploader reads nothing until the end, so only its first item fits.
+        for n in 0..8u32 {
+            input_tx.send(n).unwrap();
+        }
+        drop(input_tx);
+
+        assert_eq!(disk.iter().collect::<Vec<_>>(), (0..8).collect::<Vec<_>>());
+        assert_eq!(upload.iter().collect::<Vec<_>>(), vec![0]);
+        let edges = pipeline.metrics().edges;
+        assert_eq!((edges["tee.0"].dropped, edges["tee.1"].dropped), (0, 7));
+
+        pipeline.shutdown().await.unwrap();
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Item {
+        KeyframeRequest(u32),
+        Frame(u32),
+    }
+
+    impl Priority for Item {
+        fn is_high_priority(&self) -> bool {
+            matches!(self, Item::KeyframeRequest(_))
+        }
+    }
+
+    #[tokio::test]
+    async fn prioritize_lets_a_bounded_run_of_high_priority_items_jump_the_queue() {
+        use Item::*;
+
+        let (input_tx, input_rx) = flume::bounded(8);
+        let items = [
+            Frame(0),
+            Frame(1),
+            KeyframeRequest(2),
+            KeyframeRequest(3),
+            KeyframeRequest(4),
+            Frame(5),
+        ];
+        for item in items {
+            input_tx.send(item).unwrap();
+        }
+        drop(input_tx);
+
+        let (builder, output) = Pipeline::builder(RealTimeClock::<()>::new())
+            .path(input_rx)
+            .prioritize("prioritize", 2)
+            .into_receiver();
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+
+        assert_eq!(
+            output.iter().collect::<Vec<_>>(),
+            vec![
+                KeyframeRequest(2),
+                KeyframeRequest(3),
+                Frame(0),
+                KeyframeRequest(4),
+                Frame(1),
+                Frame(5),
+            ]
+        );
+
+        pipeline.shutdown().await.unwrap();
+    }
+}
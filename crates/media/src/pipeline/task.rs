@@ -1,11 +1,129 @@
-use flume::{Receiver, Sender};
+use std::{
+    any::Any,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
 
-use crate::pipeline::{MediaError, PipelineControlSignal};
+use flume::{Receiver, RecvTimeoutError, Sender};
+use tracing::warn;
 
-const DEFAULT_QUEUE_SIZE: usize = 2048;
+use crate::pipeline::{
+    control::{Control, ControlMessage},
+    MediaError, PipelineControlSignal,
+};
+
+pub(super) const DEFAULT_QUEUE_SIZE: usize = 2048;
+
+/// How often an [`FnSink`] checks for control signals while its input is quiet.
+const CONTROL_POLL_INTERVAL: Duration = Duration::from_millis(10);
 
 pub type PipelineReadySignal = Sender<Result<(), MediaError>>;
 
+/// Shared state set with
+/// [`PipelineBuilder::with_context`](super::builder::PipelineBuilder::with_context), such as
+/// read-only config or a global frame counter, handed to every task so they don't each
+/// need to capture their own clones.
+#[derive(Clone, Default)]
+pub struct TaskContext(Option<Arc<dyn Any + Send + Sync>>);
+
+impl TaskContext {
+    pub(super) fn new<C: Send + Sync + 'static>(context: C) -> Self {
+        Self(Some(Arc::new(context)))
+    }
+
+    /// The context, if one of type `C` was set.
+    pub fn get<C: Send + Sync + 'static>(&self) -> Option<Arc<C>> {
+        self.0.clone()?.downcast().ok()
+    }
+}
+
+/// Locks state shared between tasks, recovering it if a task panicked while holding the
+/// lock. Such a panic poisons the lock, so every later `lock().unwrap()` panics in turn and
+/// one failing task takes down the others with errors that point at the wrong place. The
+/// poison is cleared once recovered, so this only warns once per panic. Only use it for
+/// state that stays consistent when a panic cuts an update short, such as counters or
+/// the latest value of something.
+pub fn recover_poisoned<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        warn!("Recovering shared state from a task that panicked while holding its lock");
+        mutex.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// Tells a sink why its input from a source spawned with
+/// [`PipelineBuilder::spawn_source`](super::builder::PipelineBuilder::spawn_source) ended,
+/// obtained with [`PipelineBuilder::source_end`](super::builder::PipelineBuilder::source_end).
+/// A disconnected input looks the same whether the source finished or crashed, which this
+/// tells apart so the sink can finalize its output only in the former case.
+///
+/// A source counts as finished once its `run` returns. Its ready signal, control signal and
+/// output are dropped after that, while a panic drops them without it returning. The sender
+/// of the input may be dropped before `run` returns, so on disconnect the sink waits for the
+/// source's task to end before deciding.
+#[derive(Clone)]
+pub struct SourceEnd {
+    source: String,
+    finished: Arc<AtomicBool>,
+    /// Never sent on, only disconnected once the source's task has ended either way.
+    ended: Receiver<()>,
+}
+
+impl SourceEnd {
+    pub(super) fn new(source: String) -> (Self, SourceFinished) {
+        let finished = Arc::new(AtomicBool::new(false));
+        let (ended_tx, ended) = flume::bounded(0);
+
+        (
+            Self {
+                source,
+                finished: finished.clone(),
+                ended,
+            },
+            SourceFinished {
+                finished,
+                _ended: ended_tx,
+            },
+        )
+    }
+
+    /// Receives the next item from `input`, or `None` at the end of the stream. If the
+    /// input disconnected because the source crashed, this fails with
+    /// [`MediaError::SourceCrashed`] instead.
+    pub fn recv<I>(&self, input: &Receiver<I>) -> Result<Option<I>, MediaError> {
+        match input.recv() {
+            Ok(item) => Ok(Some(item)),
+            Err(_) => self.wait().map(|()| None),
+        }
+    }
+
+    /// Blocks until the source's task has ended, then reports how it ended.
+    pub fn wait(&self) -> Result<(), MediaError> {
+        let _ = self.ended.recv();
+
+        if self.finished.load(Ordering::Acquire) {
+            Ok(())
+        } else {
+            Err(MediaError::SourceCrashed(self.source.clone()))
+        }
+    }
+}
+
+/// The source's half of a [`SourceEnd`], moved into its task and dropped with it.
+pub(super) struct SourceFinished {
+    finished: Arc<AtomicBool>,
+    _ended: Sender<()>,
+}
+
+impl SourceFinished {
+    pub(super) fn set(&self) {
+        self.finished.store(true, Ordering::Release);
+    }
+}
+
 pub trait PipelineSourceTask: Send {
     type Clock;
 
@@ -16,13 +134,123 @@ pub trait PipelineSourceTask: Send {
         control_signal: PipelineControlSignal,
     );
 
+    /// Like [`Self::run`], with the pipeline's [`TaskContext`] as an extra last argument.
+    /// This is what the pipeline calls, and by default it ignores the context, so only
+    /// tasks that use the context need to implement it. Their `run` is then never called.
+    fn run_with_context(
+        &mut self,
+        clock: Self::Clock,
+        ready_signal: PipelineReadySignal,
+        control_signal: PipelineControlSignal,
+        _context: TaskContext,
+    ) {
+        self.run(clock, ready_signal, control_signal);
+    }
+
     fn queue_size(&self) -> usize {
         DEFAULT_QUEUE_SIZE
     }
+
+    /// Whether the task reads its [`ControlMessage`](super::control::ControlMessage)s and
+    /// acks them, so that
+    /// [`Pipeline::send_control_ack`](super::Pipeline::send_control_ack) waits for it.
+    /// Tasks that never read their messages must leave this off, or every such call would
+    /// time out waiting for them.
+    fn acks_control_messages(&self) -> bool {
+        false
+    }
+
+    /// Capabilities the app has to register with
+    /// [`PipelineBuilder::with_capability`](super::builder::PipelineBuilder::with_capability)
+    /// for this task to run, e.g. `"hardware-encoder"`.
+    fn required_capabilities(&self) -> &'static [&'static str] {
+        &[]
+    }
 }
 
 pub trait PipelineSinkTask<Input>: Send {
     fn run(&mut self, ready_signal: PipelineReadySignal, input: &Receiver<Input>);
 
+    /// See [`PipelineSourceTask::run_with_context`].
+    fn run_with_context(
+        &mut self,
+        ready_signal: PipelineReadySignal,
+        input: &Receiver<Input>,
+        _context: TaskContext,
+    ) {
+        self.run(ready_signal, input);
+    }
+
     fn finish(&mut self);
+
+    /// See [`PipelineSourceTask::required_capabilities`].
+    fn required_capabilities(&self) -> &'static [&'static str] {
+        &[]
+    }
+}
+
+/// A sink that applies a closure to each item, for trivial sinks such as pushing to a `Vec`
+/// or sending over a websocket. Added to a path with
+/// [`PipelinePathBuilder::fn_sink`](super::builder::PipelinePathBuilder::fn_sink).
+pub struct FnSink<O> {
+    f: Box<dyn FnMut(O) -> Result<(), MediaError> + Send>,
+    flush: Option<Box<dyn FnMut() -> Result<(), MediaError> + Send>>,
+}
+
+impl<O> FnSink<O> {
+    pub fn new(f: impl FnMut(O) -> Result<(), MediaError> + Send + 'static) -> Self {
+        Self {
+            f: Box::new(f),
+            flush: None,
+        }
+    }
+
+    /// Like [`Self::new`] for a closure that buffers, such as one writing to a file, which
+    /// `flush` writes out on [`ControlMessage::Flush`]. The sink acks the message once
+    /// `flush` has returned, and a failing `flush` stops it like a failing `f` does.
+    pub fn with_flush(
+        f: impl FnMut(O) -> Result<(), MediaError> + Send + 'static,
+        flush: impl FnMut() -> Result<(), MediaError> + Send + 'static,
+    ) -> Self {
+        Self {
+            f: Box::new(f),
+            flush: Some(Box::new(flush)),
+        }
+    }
+
+    /// Only sinks with a flush closure ack their messages, see
+    /// [`PipelineSourceTask::acks_control_messages`].
+    pub(super) fn acks_control_messages(&self) -> bool {
+        self.flush.is_some()
+    }
+
+    /// Consumes items until the input ends or the closure fails. On shutdown, the items
+    /// already queued are flushed through the closure before stopping.
+    pub(super) fn run(
+        &mut self,
+        input: &Receiver<O>,
+        mut control_signal: PipelineControlSignal,
+    ) -> Result<(), MediaError> {
+        loop {
+            match input.recv_timeout(CONTROL_POLL_INTERVAL) {
+                Ok(item) => (self.f)(item)?,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+
+            while let Some((message, ack)) = control_signal.try_message_acked() {
+                match (message, &mut self.flush) {
+                    (ControlMessage::Flush, Some(flush)) => {
+                        flush()?;
+                        ack.ack();
+                    }
+                    _ => ack.ignore(),
+                }
+            }
+
+            if let Some(Control::Shutdown) = control_signal.peek() {
+                return input.drain().try_for_each(&mut self.f);
+            }
+        }
+    }
 }
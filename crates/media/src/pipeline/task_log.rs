@@ -0,0 +1,115 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use tracing::{Dispatch, Event, Subscriber};
+use tracing_subscriber::{
+    filter::LevelFilter,
+    layer::{Context, SubscriberExt},
+    Layer,
+};
+
+/// The dispatcher for a task's thread when logging to one file per task, see
+/// [`PipelineBuilder::with_per_task_log_dir`](super::builder::PipelineBuilder::with_per_task_log_dir).
+/// Events are written to the task's file and still passed on to `inherited`, so the app's
+/// own log keeps seeing them. Falls back to `inherited` if the file can't be created.
+pub(super) fn task_dispatcher(dir: &Path, task: &str, inherited: Dispatch) -> Dispatch {
+    let path = log_path(dir, task);
+
+    let file = match std::fs::create_dir_all(dir).and_then(|_| File::create(&path)) {
+        Ok(file) => file,
+        Err(error) => {
+            tracing::dispatcher::with_default(&inherited, || {
+                tracing::warn!("Failed to create log file {}: {error}", path.display());
+            });
+            return inherited;
+        }
+    };
+
+    let subscriber = tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .with_filter(LevelFilter::current()),
+        )
+        .with(ForwardEvents(inherited));
+
+    Dispatch::new(subscriber)
+}
+
+/// Task names may contain anything, so only keep characters that are safe in a file name.
+/// Names that had to change get a hash of the original appended, so e.g. `mic/usb` and
+/// `mic:usb` don't end up writing to the same file.
+fn log_path(dir: &Path, task: &str) -> PathBuf {
+    let mut name = task
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect::<String>();
+
+    // Rules out empty names as well as `.` and `..`.
+    if name.chars().all(|c| c == '.') {
+        name.insert(0, '_');
+    }
+
+    if name != task {
+        name = format!("{name}-{:08x}", fnv1a(task));
+    }
+
+    dir.join(format!("{name}.log"))
+}
+
+/// A hash that stays the same across runs and Rust versions, so a task keeps its log file.
+fn fnv1a(value: &str) -> u32 {
+    value.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Hands every event on to another dispatcher. Spans aren't forwarded, as the two
+/// dispatchers would disagree on span ids, but the pipeline's task span is entered on the
+/// inherited dispatcher too, so events still show up under it.
+struct ForwardEvents(Dispatch);
+
+impl<S: Subscriber> Layer<S> for ForwardEvents {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if self.0.enabled(event.metadata()) {
+            self.0.event(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_paths_are_safe_and_distinct() {
+        let dir = Path::new("logs");
+        let file_name = |task| {
+            log_path(dir, task)
+                .strip_prefix(dir)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(file_name("mic-1.usb"), "mic-1.usb.log");
+
+        let sanitized = ["mic/usb", "mic:usb", "mic_usb", "", "..", "../mic"].map(file_name);
+        for (index, name) in sanitized.iter().enumerate() {
+            assert!(!name.contains(['/', ':']), "{name} isn't a safe file name");
+            assert!(
+                !name.starts_with('.'),
+                "{name} is hidden or a parent directory"
+            );
+            assert!(!sanitized[..index].contains(name), "{name} is used twice");
+        }
+    }
+}
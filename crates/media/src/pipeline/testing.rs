@@ -0,0 +1,355 @@
+//! Helpers for testing pipelines, available to this crate's tests and, with the `testing`
+//! feature, to other crates'.
+//!
+//! ```ignore
+//! let (mut pipeline, _done_rx) = builder.build().await?;
+//! pipeline.play().await?;
+//! tokio::time::sleep(Duration::from_millis(100)).await;
+//! pipeline.shutdown().await?;
+//!
+//! // On failure, lists how every task ended rather than just the first error.
+//! assert_pipeline_ok(&pipeline.shutdown_report());
+//! ```
+//!
+//! Pipelines whose sources go by the pipeline clock can be run on a [`SteppedClock`]
+//! instead, and their output collected with [`CollectingSink`]s, so a test takes the same
+//! steps and sees the same items every time:
+//!
+//! ```ignore
+//! let outputs = CollectedOutputs::new();
+//! let mut builder = Pipeline::builder(SteppedClock::new(Duration::from_millis(10)));
+//! builder.spawn_source("ticker", Ticker(ticks_tx));
+//! let builder = builder.path(ticks_rx).sink("ticks", outputs.sink("ticks"));
+//! let (pipeline, _done_rx) = builder.build().await?;
+//!
+//! let outputs = run_stepped(pipeline, 3, &outputs).await;
+//! assert_eq!(outputs["ticks"], [10, 20, 30]);
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use flume::Receiver;
+use indexmap::IndexMap;
+
+use crate::pipeline::{
+    clock::{CloneInto, PipelineClock, RunningTime},
+    completion::{ShutdownReport, TaskOutcome},
+    task::{PipelineReadySignal, PipelineSinkTask},
+    Pipeline,
+};
+
+/// How long nothing may move on the pipeline's edges for [`run_stepped`] to consider a step
+/// done. Sources have to react to a step within this.
+const SETTLE_WINDOW: Duration = Duration::from_millis(20);
+/// How long [`run_stepped`] waits for a step, and for the final shutdown, to settle.
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A clock that only moves when [`Self::step`] is called, by the same amount every time.
+/// Clones share their time, so the test's clone steps the clocks the tasks were given.
+#[derive(Debug, Clone)]
+pub struct SteppedClock {
+    step: Duration,
+    elapsed_nanos: Arc<AtomicU64>,
+    running: Arc<AtomicBool>,
+}
+
+impl SteppedClock {
+    pub fn new(step: Duration) -> Self {
+        Self {
+            step,
+            elapsed_nanos: Default::default(),
+            running: Default::default(),
+        }
+    }
+
+    /// Moves the clock on by one step, unless it's stopped, as a stopped clock stands still.
+    pub fn step(&self) {
+        if self.running() {
+            let step = u64::try_from(self.step.as_nanos()).unwrap_or(u64::MAX);
+            self.elapsed_nanos.fetch_add(step, Ordering::SeqCst);
+        }
+    }
+}
+
+impl PipelineClock for SteppedClock {
+    type Instant = RunningTime;
+    type Duration = Duration;
+
+    fn start(&mut self) {
+        self.running.store(true, Ordering::SeqCst);
+    }
+
+    fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    fn running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    fn now(&self) -> RunningTime {
+        RunningTime(Duration::from_nanos(
+            self.elapsed_nanos.load(Ordering::SeqCst),
+        ))
+    }
+
+    fn elapsed_between(earlier: RunningTime, later: RunningTime) -> Duration {
+        later.saturating_duration_since(earlier)
+    }
+
+    fn settle_delay(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+impl CloneInto<SteppedClock> for SteppedClock {
+    fn clone_into(&self) -> SteppedClock {
+        self.clone()
+    }
+}
+
+/// The items the [`CollectingSink`]s created from it have received, by the name each sink
+/// was created with. All of them take the same type of item `T`, which like any pipeline
+/// item has to be `Send + 'static`; asserting on the items also needs `PartialEq + Debug`.
+pub struct CollectedOutputs<T>(Arc<Mutex<IndexMap<String, Vec<T>>>>);
+
+impl<T> CollectedOutputs<T> {
+    pub fn new() -> Self {
+        Self(Default::default())
+    }
+
+    /// A sink that collects its items under `name`. It shows up in the outputs even if
+    /// it gets none.
+    pub fn sink(&self, name: impl Into<String>) -> CollectingSink<T> {
+        let name = name.into();
+        self.0.lock().unwrap().entry(name.clone()).or_default();
+
+        CollectingSink {
+            name,
+            outputs: self.0.clone(),
+        }
+    }
+
+    /// Takes the items collected so far.
+    pub fn take(&self) -> HashMap<String, Vec<T>> {
+        let mut outputs = self.0.lock().unwrap();
+        outputs
+            .iter_mut()
+            .map(|(name, items)| (name.clone(), std::mem::take(items)))
+            .collect()
+    }
+}
+
+impl<T> Default for CollectedOutputs<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A sink that keeps every item it receives, see [`CollectedOutputs::sink`].
+pub struct CollectingSink<T> {
+    name: String,
+    outputs: Arc<Mutex<IndexMap<String, Vec<T>>>>,
+}
+
+impl<T: Send + 'static> PipelineSinkTask<T> for CollectingSink<T> {
+    fn run(&mut self, ready_signal: PipelineReadySignal, input: &Receiver<T>) {
+        let _ = ready_signal.send(Ok(()));
+
+        for item in input.iter() {
+            let mut outputs = self.outputs.lock().unwrap();
+            outputs.entry(self.name.clone()).or_default().push(item);
+        }
+    }
+
+    fn finish(&mut self) {}
+}
+
+/// Plays `pipeline`, steps its clock `steps` times and shuts it down gracefully, returning
+/// what each sink created from `outputs` received. After playing and after every step this
+/// waits for the pipeline to settle, which is when no edge has items queued and nothing
+/// has been sent for a little while, so every step's items have made it through before the
+/// next step is taken.
+///
+/// Panics if the pipeline doesn't settle within a few seconds, or if any task fails, with
+/// the report of [`assert_pipeline_ok`].
+pub async fn run_stepped<T>(
+    mut pipeline: Pipeline<SteppedClock>,
+    steps: usize,
+    outputs: &CollectedOutputs<T>,
+) -> HashMap<String, Vec<T>> {
+    let clock = pipeline.clock().clone();
+
+    pipeline.play().await.expect("the pipeline failed to play");
+    settle(&pipeline).await;
+
+    for _ in 0..steps {
+        clock.step();
+        settle(&pipeline).await;
+    }
+
+    pipeline
+        .shutdown_graceful(SETTLE_TIMEOUT, |_| {})
+        .await
+        .expect("the pipeline failed to shut down");
+    assert_pipeline_ok(&pipeline.shutdown_report());
+
+    outputs.take()
+}
+
+async fn settle<C: PipelineClock>(pipeline: &Pipeline<C>) {
+    let deadline = Instant::now() + SETTLE_TIMEOUT;
+    let mut last_sent = None;
+    let mut quiet_since = Instant::now();
+
+    loop {
+        let metrics = pipeline.metrics();
+        let sent = metrics.total_sent();
+        let drained = metrics.edges.values().all(|edge| edge.depth == 0);
+
+        if !drained || last_sent != Some(sent) {
+            last_sent = Some(sent);
+            quiet_since = Instant::now();
+        } else if quiet_since.elapsed() >= SETTLE_WINDOW {
+            return;
+        }
+
+        assert!(
+            Instant::now() < deadline,
+            "the pipeline didn't settle within {SETTLE_TIMEOUT:?}"
+        );
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+}
+
+/// Panics unless every task in `report` finished or stopped without an error, listing how
+/// each task ended. Tasks that are still running, e.g. as they were detached when a drain
+/// stalled, count as failures.
+#[track_caller]
+pub fn assert_pipeline_ok(report: &ShutdownReport) {
+    let ok = report
+        .tasks
+        .values()
+        .all(|outcome| matches!(outcome, TaskOutcome::Finished | TaskOutcome::Stopped));
+
+    if !ok {
+        panic!("{}", describe(report));
+    }
+}
+
+fn describe(report: &ShutdownReport) -> String {
+    let mut description = String::from("Pipeline didn't shut down cleanly:");
+
+    for (task, outcome) in &report.tasks {
+        let _ = match outcome {
+            TaskOutcome::Running => write!(description, "\n  {task}: still running"),
+            TaskOutcome::Finished => write!(description, "\n  {task}: finished"),
+            TaskOutcome::Stopped => write!(description, "\n  {task}: stopped"),
+            TaskOutcome::Failed(error) => write!(description, "\n  {task}: FAILED: {error}"),
+        };
+
+        if let Some(latency) = report.stop_latencies.get(task) {
+            let _ = write!(description, " ({latency:?} after stopping)");
+        }
+    }
+
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use flume::Sender;
+
+    use super::*;
+    use crate::pipeline::{
+        control::{Control, PipelineControlSignal},
+        task::PipelineSourceTask,
+    };
+
+    /// Sends the clock's time in milliseconds whenever it has moved on.
+    struct Ticker(Sender<u64>);
+
+    impl PipelineSourceTask for Ticker {
+        type Clock = SteppedClock;
+
+        fn run(
+            &mut self,
+            clock: Self::Clock,
+            ready: PipelineReadySignal,
+            mut control: PipelineControlSignal,
+        ) {
+            let _ = ready.send(Ok(()));
+            let mut last = clock.now();
+
+            while let Some(Control::Play) = control.last() {
+                let now = clock.now();
+                if now != last {
+                    last = now;
+                    if self.0.send(now.0.as_millis() as u64).is_err() {
+                        break;
+                    }
+                }
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn run_stepped_collects_every_step() {
+        let outputs = CollectedOutputs::new();
+        let (ticks_tx, ticks_rx) = flume::bounded(8);
+
+        let mut builder = Pipeline::builder(SteppedClock::new(Duration::from_millis(10)));
+        builder.spawn_source("ticker", Ticker(ticks_tx));
+        let builder = builder
+            .path(ticks_rx)
+            .map("double", |ms| ms * 2)
+            .sink("doubled", outputs.sink("doubled"));
+        let (pipeline, _done_rx) = builder.build().await.unwrap();
+
+        let outputs = run_stepped(pipeline, 3, &outputs).await;
+
+        assert_eq!(outputs["doubled"], vec![20, 40, 60]);
+    }
+
+    #[test]
+    fn stepped_clock_stands_still_while_stopped() {
+        let mut clock = SteppedClock::new(Duration::from_millis(10));
+        let start = clock.now();
+
+        clock.step();
+        assert_eq!(clock.elapsed_since(start), Duration::ZERO);
+
+        clock.start();
+        clock.step();
+        clock.step();
+        assert_eq!(clock.elapsed_since(start), Duration::from_millis(20));
+    }
+
+    #[test]
+    #[should_panic(expected = "microphone: FAILED: device disconnected")]
+    fn names_the_failed_task_and_its_error() {
+        let report = ShutdownReport {
+            tasks: [
+                ("screen".to_string(), TaskOutcome::Stopped),
+                (
+                    "microphone".to_string(),
+                    TaskOutcome::Failed("device disconnected".to_string()),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            stop_latencies: Default::default(),
+        };
+
+        assert_pipeline_ok(&report);
+    }
+}
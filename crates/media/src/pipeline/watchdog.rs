@@ -0,0 +1,160 @@
+use std::time::Duration;
+
+use flume::Sender;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::pipeline::{
+    completion::{Completion, CompletionReason, DurationMeasure},
+    control::{Control, ControlBroadcast},
+    metrics::PipelineMetrics,
+    MediaError, PipelineClock,
+};
+
+const SKEW_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const DURATION_LIMIT_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+const NO_DATA_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+/// Keeps very short idle timeouts from turning the watchdog into a busy loop.
+const MIN_IDLE_CHECK_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Shuts the pipeline down if no items flow through any metered edge for `idle_timeout`.
+///
+/// The idle timer only runs while the clock does, so a paused (or not yet playing)
+/// pipeline is never considered idle, and resuming starts a fresh window.
+pub(super) fn spawn_idle_watchdog<T: PipelineClock>(
+    clock: T,
+    metrics: PipelineMetrics,
+    mut control: ControlBroadcast,
+    idle_timeout: Duration,
+    completion: Completion,
+    stop_requests: Sender<MediaError>,
+) {
+    tokio::spawn(async move {
+        let mut last_sent = metrics.snapshot().total_sent();
+        let mut last_progress = clock.now();
+
+        loop {
+            tokio::time::sleep((idle_timeout / 4).max(MIN_IDLE_CHECK_INTERVAL)).await;
+
+            // The completion monitor is gone, so the pipeline has already finished.
+            if stop_requests.is_disconnected() {
+                break;
+            }
+
+            let sent = metrics.snapshot().total_sent();
+            if !clock.running() || sent != last_sent {
+                last_sent = sent;
+                last_progress = clock.now();
+                continue;
+            }
+
+            if clock.elapsed_since(last_progress) >= idle_timeout {
+                if completion.record(CompletionReason::IdleTimeout(idle_timeout)) {
+                    warn!("No items produced for {idle_timeout:?}, shutting down idle pipeline");
+                    let _ = stop_requests.try_send(MediaError::IdleTimeout(idle_timeout));
+                    control.broadcast(Control::Shutdown).await;
+                }
+                break;
+            }
+        }
+    });
+}
+
+/// Logs a warning if no items have flowed through any metered edge once the clock has run
+/// for `grace`, e.g. because a stage's input never got connected. As with the idle
+/// watchdog, time before playing or spent paused doesn't count.
+pub(super) fn spawn_no_data_check<T: PipelineClock>(
+    clock: T,
+    metrics: PipelineMetrics,
+    grace: Duration,
+    stop_requests: Sender<MediaError>,
+) {
+    tokio::spawn(async move {
+        let started = clock.now();
+
+        loop {
+            tokio::time::sleep(NO_DATA_CHECK_INTERVAL.min(grace)).await;
+
+            if stop_requests.is_disconnected() {
+                break;
+            }
+
+            if clock.elapsed_since(started) < grace {
+                continue;
+            }
+
+            let snapshot = metrics.snapshot();
+            if !snapshot.edges.is_empty() && snapshot.total_sent() == 0 {
+                warn!("Pipeline appears to be producing no data, none of its edges sent anything in {grace:?}");
+            }
+            break;
+        }
+    });
+}
+
+/// Logs a warning whenever the clock's wall clock skew crosses `threshold`.
+pub(super) fn spawn_skew_monitor<T: PipelineClock>(
+    clock: T,
+    threshold: Duration,
+    stop_requests: Sender<MediaError>,
+) {
+    tokio::spawn(async move {
+        let mut exceeded = false;
+
+        loop {
+            tokio::time::sleep(SKEW_CHECK_INTERVAL).await;
+
+            if stop_requests.is_disconnected() {
+                break;
+            }
+
+            let skew = clock.wall_clock_skew().unwrap_or_default();
+            if skew > threshold && !exceeded {
+                warn!("Clock skew of {skew:?} exceeds {threshold:?}, audio and video may drift");
+            }
+            exceeded = skew > threshold;
+        }
+    });
+}
+
+/// Stops the pipeline once it has run for `limit`, unless it was already stopped for
+/// another reason. Unlike the idle watchdog this is a normal completion, so the tasks
+/// are asked to shut down and the pipeline completes once they do.
+pub(super) fn spawn_duration_limit<T: PipelineClock>(
+    clock: T,
+    mut control: ControlBroadcast,
+    limit: Duration,
+    measure: DurationMeasure,
+    completion: Completion,
+    stop_requests: Sender<MediaError>,
+) {
+    tokio::spawn(async move {
+        // The clock stands still while it's stopped, so only its running time counts.
+        let origin = clock.now();
+        let mut first_started = None;
+
+        loop {
+            tokio::time::sleep(DURATION_LIMIT_CHECK_INTERVAL.min(limit)).await;
+
+            if stop_requests.is_disconnected() || completion.reason().is_some() {
+                break;
+            }
+
+            if clock.running() {
+                first_started.get_or_insert_with(Instant::now);
+            }
+            let elapsed = match measure {
+                DurationMeasure::Clock => clock.elapsed_since(origin),
+                DurationMeasure::Wall => first_started.map_or(Duration::ZERO, |at| at.elapsed()),
+            };
+
+            if elapsed >= limit {
+                if completion.record(CompletionReason::DurationLimitReached) {
+                    info!("Pipeline reached its maximum duration of {limit:?}, stopping");
+                    control.broadcast(Control::Shutdown).await;
+                }
+                break;
+            }
+        }
+    });
+}
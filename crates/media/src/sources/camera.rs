@@ -5,7 +5,11 @@ use tracing::{error, info};
 use crate::{
     data::{FFVideo, VideoInfo},
     feeds::{CameraConnection, CameraFeed, RawCameraFrame},
-    pipeline::{clock::RealTimeClock, control::Control, task::PipelineSourceTask},
+    pipeline::{
+        clock::RealTimeClock,
+        control::{Control, ControlMessage},
+        task::PipelineSourceTask,
+    },
     MediaError,
 };
 
@@ -63,6 +67,10 @@ impl CameraSource {
 impl PipelineSourceTask for CameraSource {
     type Clock = RealTimeClock<Instant>;
 
+    fn acks_control_messages(&self) -> bool {
+        true
+    }
+
     // #[tracing::instrument(skip_all)]
     fn run(
         &mut self,
@@ -70,28 +78,52 @@ impl PipelineSourceTask for CameraSource {
         ready_signal: crate::pipeline::task::PipelineReadySignal,
         mut control_signal: crate::pipeline::control::PipelineControlSignal,
     ) {
-        let mut frames_rx: Option<Receiver<RawCameraFrame>> = None;
+        let mut frames_rx: Option<Receiver<RawCameraFrame>> = Some(self.feed_connection.attach());
 
         info!("Camera source ready");
 
-        let frames = frames_rx.get_or_insert_with(|| self.feed_connection.attach());
-
         ready_signal.send(Ok(())).unwrap();
 
         loop {
             match control_signal.last() {
-                Some(Control::Play) => match frames.drain().last().or_else(|| frames.recv().ok()) {
-                    Some(frame) => {
-                        if let Err(error) = self.process_frame(frame) {
-                            eprintln!("{error}");
+                Some(Control::Play) => {
+                    if let Some((message, ack)) = control_signal.try_message_acked() {
+                        if message != ControlMessage::Suspend {
+                            // The camera can't rotate, flush or change its rate.
+                            ack.ignore();
+                            continue;
+                        }
+
+                        // Detaching from the feed stops it from converting and sending frames to us.
+                        if let Some(rx) = frames_rx.take() {
+                            self.pause_and_drain_frames(rx);
+                        }
+                        ack.ack();
+                        info!("Camera source suspended");
+
+                        if !control_signal.wait_for_resume() {
+                            info!("Camera source stopped");
                             break;
                         }
+                        info!("Camera source resumed");
+                        continue;
                     }
-                    None => {
-                        error!("Lost connection with the camera feed");
-                        break;
+
+                    let frames = frames_rx.get_or_insert_with(|| self.feed_connection.attach());
+
+                    match frames.drain().last().or_else(|| frames.recv().ok()) {
+                        Some(frame) => {
+                            if let Err(error) = self.process_frame(frame) {
+                                eprintln!("{error}");
+                                break;
+                            }
+                        }
+                        None => {
+                            error!("Lost connection with the camera feed");
+                            break;
+                        }
                     }
-                },
+                }
                 Some(Control::Shutdown) | None => {
                     if let Some(rx) = frames_rx.take() {
                         self.pause_and_drain_frames(rx);
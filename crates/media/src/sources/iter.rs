@@ -0,0 +1,132 @@
+use flume::Sender;
+use std::{thread, time::Duration};
+use tracing::{info, warn};
+
+use crate::pipeline::{
+    clock::{PipelineClock, RealTimeClock},
+    control::{Control, ControlMessage, PipelineControlSignal},
+    task::{PipelineReadySignal, PipelineSourceTask},
+};
+
+/// How often a paced [`IterSource`] checks for control signals while waiting.
+const CONTROL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How quickly an [`IterSource`] emits its items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IterPacing {
+    #[default]
+    AsFastAsPossible,
+    /// One item per interval of pipeline clock time, so time spent paused doesn't count.
+    /// [`ControlMessage::SetRate`] changes the interval.
+    Interval(Duration),
+}
+
+/// A source that emits the items of an iterator, e.g. for test data or simple generators.
+/// Once the iterator is exhausted, the output is closed and the task finishes.
+pub struct IterSource<I: Iterator> {
+    iter: I,
+    output: Option<Sender<I::Item>>,
+    pacing: IterPacing,
+}
+
+impl<I: Iterator> IterSource<I> {
+    pub fn new(iter: impl IntoIterator<IntoIter = I>, output: Sender<I::Item>) -> Self {
+        Self {
+            iter: iter.into_iter(),
+            output: Some(output),
+            pacing: IterPacing::default(),
+        }
+    }
+
+    pub fn with_pacing(mut self, pacing: IterPacing) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    fn set_rate(&mut self, rate: f64) {
+        if self.pacing == IterPacing::AsFastAsPossible {
+            info!("Ignoring a rate of {rate}/s, as the source isn't paced");
+            return;
+        }
+
+        match Duration::try_from_secs_f64(1.0 / rate) {
+            Ok(interval) => self.pacing = IterPacing::Interval(interval),
+            Err(_) => warn!("Ignoring an invalid rate of {rate}/s"),
+        }
+    }
+
+    /// Waits out one interval of running clock time, if paced, applying any rate change
+    /// that arrives meanwhile. Returns false if the pipeline shut down in the meantime.
+    fn wait_interval(
+        &mut self,
+        clock: &RealTimeClock<()>,
+        control_signal: &mut PipelineControlSignal,
+    ) -> bool {
+        let started = clock.now();
+
+        loop {
+            while let Some((message, ack)) = control_signal.try_message_acked() {
+                if let ControlMessage::SetRate(rate) = message {
+                    self.set_rate(rate);
+                    ack.ack();
+                } else {
+                    ack.ignore();
+                }
+            }
+
+            let IterPacing::Interval(interval) = self.pacing else {
+                return true;
+            };
+
+            let remaining = interval.saturating_sub(clock.elapsed_since(started));
+            if remaining.is_zero() {
+                return true;
+            }
+
+            thread::sleep(remaining.min(CONTROL_POLL_INTERVAL));
+
+            if !matches!(control_signal.last(), Some(Control::Play)) {
+                return false;
+            }
+        }
+    }
+}
+
+impl<I> PipelineSourceTask for IterSource<I>
+where
+    I: Iterator + Send,
+    I::Item: Send,
+{
+    type Clock = RealTimeClock<()>;
+
+    fn acks_control_messages(&self) -> bool {
+        true
+    }
+
+    fn run(
+        &mut self,
+        clock: Self::Clock,
+        ready_signal: PipelineReadySignal,
+        mut control_signal: PipelineControlSignal,
+    ) {
+        let _ = ready_signal.send(Ok(()));
+
+        let Some(output) = self.output.take() else {
+            return;
+        };
+
+        while let Some(Control::Play) = control_signal.last() {
+            if !self.wait_interval(&clock, &mut control_signal) {
+                break;
+            }
+
+            let Some(item) = self.iter.next() else {
+                break;
+            };
+
+            if output.send(item).is_err() {
+                break;
+            }
+        }
+    }
+}
@@ -1,10 +1,14 @@
 mod audio_input;
 mod audio_mixer;
 mod camera;
+mod iter;
 mod screen_capture;
+mod stream;
 // pub mod system_audio;
 
 pub use audio_input::*;
 pub use audio_mixer::*;
 pub use camera::*;
+pub use iter::*;
 pub use screen_capture::*;
+pub use stream::*;
@@ -0,0 +1,179 @@
+use std::fmt::Display;
+
+use flume::Sender;
+use futures::{FutureExt, Stream, StreamExt};
+
+use crate::{
+    pipeline::{
+        control::{Control, PipelineControlSignal},
+        task::PipelineReadySignal,
+    },
+    MediaError,
+};
+
+/// A source that forwards the items of an async [`Stream`], e.g. frames arriving on a
+/// network socket. This is the async counterpart to [`IterSource`](super::IterSource),
+/// added to a pipeline with
+/// [`PipelineBuilder::spawn_stream_source`](crate::pipeline::builder::PipelineBuilder::spawn_stream_source).
+///
+/// The stream is driven on a runtime local to the task's thread. It ends the task when it
+/// ends, when the pipeline shuts down, or with the error of the first item that fails. It's
+/// polled once before the task signals ready, so a stream that fails straight away fails
+/// the build.
+pub struct StreamSource<S, T> {
+    stream: S,
+    output: Sender<T>,
+}
+
+impl<S, T, E> StreamSource<S, T>
+where
+    S: Stream<Item = Result<T, E>> + Send + 'static,
+    T: Send + 'static,
+    E: Display,
+{
+    pub fn new(stream: S, output: Sender<T>) -> Self {
+        Self { stream, output }
+    }
+
+    pub(crate) fn run(
+        self,
+        ready_signal: PipelineReadySignal,
+        mut control_signal: PipelineControlSignal,
+    ) -> Result<(), String> {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(error) => {
+                let _ = ready_signal.send(Err(MediaError::TaskLaunch(error.to_string())));
+                return Err(error.to_string());
+            }
+        };
+
+        let Self { stream, output } = self;
+
+        runtime.block_on(async move {
+            let mut stream = std::pin::pin!(stream.fuse());
+
+            // Polling once before signalling ready makes a stream that fails straight away,
+            // e.g. to connect, fail the build rather than the running pipeline.
+            let mut next = match stream.next().now_or_never() {
+                Some(Some(Err(error))) => {
+                    let _ = ready_signal.send(Err(MediaError::TaskLaunch(error.to_string())));
+                    return Err(error.to_string());
+                }
+                Some(Some(Ok(item))) => Some(item),
+                Some(None) | None => None,
+            };
+            let _ = ready_signal.send(Ok(()));
+
+            let mut control = control_signal.changed().await;
+
+            while let Some(Control::Play) = control {
+                let item = match next.take() {
+                    Some(item) => item,
+                    None => tokio::select! {
+                        changed = control_signal.changed() => {
+                            control = changed;
+                            continue;
+                        }
+                        item = stream.next() => match item {
+                            Some(Ok(item)) => item,
+                            Some(Err(error)) => return Err(error.to_string()),
+                            None => break,
+                        },
+                    },
+                };
+
+                // A full output mustn't keep the task from seeing the pipeline stop.
+                let mut send = std::pin::pin!(output.send_async(item));
+                let sent = loop {
+                    tokio::select! {
+                        sent = &mut send => break sent.is_ok(),
+                        changed = control_signal.changed() => {
+                            control = changed;
+                            if control != Some(Control::Play) {
+                                break false;
+                            }
+                        }
+                    }
+                };
+                if !sent {
+                    break;
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::stream;
+
+    use super::*;
+    use crate::pipeline::{Pipeline, RealTimeClock};
+
+    async fn run_stream(items: Vec<Result<u32, String>>) -> (Result<(), String>, Vec<u32>) {
+        let (output_tx, output_rx) = flume::bounded(8);
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        builder.spawn_stream_source("network", StreamSource::new(stream::iter(items), output_tx));
+        let (mut pipeline, done_rx) = builder.build().await.unwrap();
+        pipeline.play().await.unwrap();
+
+        let result = tokio::time::timeout(Duration::from_secs(5), done_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        let _ = pipeline.shutdown().await;
+
+        (result, output_rx.drain().collect())
+    }
+
+    #[tokio::test]
+    async fn a_stream_that_ends_finishes_the_task() {
+        let (result, items) = run_stream(vec![Ok(1), Ok(2)]).await;
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(items, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn a_stream_error_fails_the_task() {
+        let (result, items) = run_stream(vec![Ok(1), Err("connection lost".into())]).await;
+
+        assert!(result.unwrap_err().contains("connection lost"));
+        assert_eq!(items, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn a_stream_failing_straight_away_fails_the_build() {
+        let (output_tx, _output_rx) = flume::bounded::<u32>(8);
+        let items = stream::iter([Err::<u32, _>("connection refused")]);
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        builder.spawn_stream_source("network", StreamSource::new(items, output_tx));
+
+        let error = builder.build().await.err().unwrap();
+
+        assert!(error.to_string().contains("connection refused"));
+    }
+
+    #[tokio::test]
+    async fn a_full_output_does_not_hold_up_shutdown() {
+        let (output_tx, _output_rx) = flume::bounded(1);
+        let items = stream::iter(0..).map(Ok::<u32, String>);
+        let mut builder = Pipeline::builder(RealTimeClock::<()>::new());
+        builder.spawn_stream_source("network", StreamSource::new(items, output_tx));
+        let (mut pipeline, _done_rx) = builder.build().await.unwrap();
+        pipeline.play().await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), pipeline.shutdown())
+            .await
+            .expect("the source stayed blocked on its full output")
+            .unwrap();
+    }
+}